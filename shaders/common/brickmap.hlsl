@@ -0,0 +1,23 @@
+#pragma once
+
+// must match BRICK_SIZE, EMPTY_BRICK, Brick and BrickmapGpu in src/world/brickmap.rs
+#define BRICK_SIZE 8
+#define EMPTY_BRICK 0xffffffff
+
+struct Brick {
+    uint occupancy[16];
+};
+
+struct BrickmapHeader {
+    uint3 dimensions;
+    uint brick_count;
+};
+
+uint brickmap_top_index(uint3 brick, uint3 dimensions) {
+    return brick.x + brick.y * dimensions.x + brick.z * dimensions.x * dimensions.y;
+}
+
+bool brick_voxel(Brick brick, uint3 local) {
+    uint bit = local.x + local.y * BRICK_SIZE + local.z * BRICK_SIZE * BRICK_SIZE;
+    return (brick.occupancy[bit / 32] >> (bit % 32)) & 1;
+}
@@ -0,0 +1,11 @@
+#pragma once
+
+// voxel hit by a primary ray, reconstructed from the depth and normal trace() wrote to the G-buffer.
+// trace() divides the ray length by the length of the direction, and its normal points along the ray.
+// g_depth has to be 32 bit for this, a 16 bit depth is only accurate to 1/2048 of the distance which moves hits
+// near a voxel edge into the neighbouring voxel a few voxels away already. With 32 bits only hits within float
+// rounding of an edge can still end up in the neighbour
+int3 gbuffer_voxel(float3 origin, float3 direction, float depth, float3 normal) {
+    float3 hit = origin + direction * depth * length(direction);
+    return int3(floor(hit + normal * 0.5));
+}
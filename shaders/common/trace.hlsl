@@ -1,16 +1,16 @@
 #pragma once
 
-float sdSphere(float3 p, float d) { return length(p) - d; } 
+#include "brickmap.hlsl"
 
-float sdBox( float3 p, float3 b ){
-  float3 d = abs(p) - b;
-  return min(max(d.x,max(d.y,d.z)),0.0) + length(max(d,0.0));
-}
-
-bool getVoxel(float3 position) {
-	float3 p = position + float3(0.5, 0.5, 0.5);
-	float d = min(max(-sdSphere(p, 7.5), sdBox(p, float3(6.0, 6.0, 6.0))), -sdSphere(p, 50.0));
-	return d < 0.0;
+// expects brickmap_header, brickmap_top and brick_pool to be declared by the including shader
+bool getVoxel(int3 position) {
+    BrickmapHeader header = brickmap_header[0];
+    if (any(position < 0)) return false;
+    uint3 brick = uint3(position) / BRICK_SIZE;
+    if (any(brick >= header.dimensions)) return false;
+    uint pointer = brickmap_top[brickmap_top_index(brick, header.dimensions)];
+    if (pointer == EMPTY_BRICK) return false;
+    return brick_voxel(brick_pool[pointer], uint3(position) % BRICK_SIZE);
 }
 
 void trace(
@@ -43,4 +43,4 @@ void trace(
     depth = length(float3(mask) * (sideDist - deltaDist)) / length(direction);
     material = 1;
     complexity = i;
-}
\ No newline at end of file
+}
@@ -0,0 +1,50 @@
+#include "common/camera.hlsl"
+#include "common/gbuffer.hlsl"
+
+struct EditorPreview {
+    int3 half_extents;
+    float radius;
+    uint2 cursor;
+    uint shape;
+    uint place;
+    float4 color;
+};
+
+RWTexture2D<unorm float4> primary_ray_direction;
+StructuredBuffer<Camera> camera_data;
+RWTexture2D<snorm float4> g_normal;
+RWTexture2D<float> g_depth;
+StructuredBuffer<EditorPreview> editor_preview;
+RWTexture2D<unorm float4> screen_texture;
+
+// voxel the brush acts on for the ray through `pos`
+int3 brush_voxel(int2 pos, Camera camera, EditorPreview preview) {
+    float3 normal = round(g_normal[pos].xyz);
+    int3 voxel = gbuffer_voxel(camera.position, primary_ray_direction[pos].xyz, g_depth[pos], normal);
+    if (preview.place) voxel -= int3(normal);
+    return voxel;
+}
+
+// same shapes as Brush::positions in src/world/edit.rs
+bool inside_brush(int3 offset, EditorPreview preview) {
+    if (any(abs(offset) > preview.half_extents)) return false;
+    if (preview.shape == 0) return length(float3(offset)) <= preview.radius;
+    if (preview.shape == 2) return length(float2(offset.xz)) <= preview.radius;
+    return true;
+}
+
+[numthreads(32, 32, 1)] void main(uint2 threadId
+                                  : SV_DispatchThreadID)
+{
+    int2 pos = int2(threadId);
+    Camera camera = camera_data[0];
+    EditorPreview preview = editor_preview[0];
+    if (any(threadId >= camera.screen_dimensions)) return;
+
+    int3 center = brush_voxel(int2(preview.cursor), camera, preview);
+    int3 voxel = brush_voxel(pos, camera, preview);
+    if (inside_brush(voxel - center, preview)) {
+        float4 color = screen_texture[pos];
+        screen_texture[pos] = float4(lerp(color.rgb, preview.color.rgb, preview.color.a), color.a);
+    }
+}
@@ -0,0 +1,37 @@
+#include "common/camera.hlsl"
+#include "common/gbuffer.hlsl"
+
+// must match PickGpu in src/compute_passes/picker.rs
+struct Pick {
+    int3 voxel;
+    float depth;
+    float3 normal;
+    uint material;
+    uint2 cursor;
+    uint valid;
+    uint padding;
+};
+
+RWTexture2D<unorm float4> primary_ray_direction;
+StructuredBuffer<Camera> camera_data;
+RWTexture2D<snorm float4> g_normal;
+RWTexture2D<float> g_depth;
+RWTexture2D<uint> g_material;
+RWStructuredBuffer<Pick> pick;
+
+// copies the G-buffer values under the cursor into a buffer small enough to read back every frame
+[numthreads(1, 1, 1)] void main()
+{
+    Pick result = pick[0];
+    Camera camera = camera_data[0];
+    result.valid = all(result.cursor < camera.screen_dimensions);
+    if (result.valid) {
+        int2 pos = int2(result.cursor);
+        float3 normal = round(g_normal[pos].xyz);
+        result.depth = g_depth[pos];
+        result.normal = normal;
+        result.material = g_material[pos];
+        result.voxel = gbuffer_voxel(camera.position, primary_ray_direction[pos].xyz, result.depth, normal);
+    }
+    pick[0] = result;
+}
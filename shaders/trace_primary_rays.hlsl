@@ -1,6 +1,6 @@
 #include "common/camera.hlsl"
 #include "common/random.hlsl"
-#include "common/trace.hlsl"
+#include "common/brickmap.hlsl"
 
 RWTexture2D<unorm float4> primary_ray_direction;
 StructuredBuffer<Camera> camera_data;
@@ -8,6 +8,11 @@ RWTexture2D<unorm float4> g_normal;
 RWTexture2D<float> g_depth;
 RWTexture2D<uint> g_material;
 RWTexture2D<uint> g_complexity;
+StructuredBuffer<BrickmapHeader> brickmap_header;
+StructuredBuffer<uint> brickmap_top;
+StructuredBuffer<Brick> brick_pool;
+
+#include "common/trace.hlsl"
 
 [numthreads(32, 32, 1)] void main(uint2 threadId
                                   : SV_DispatchThreadID)
@@ -39,4 +44,4 @@ RWTexture2D<uint> g_complexity;
     g_depth[pos] = depth;
     g_material[pos] = material;
     g_complexity[pos] = complexity;
-}
\ No newline at end of file
+}
@@ -0,0 +1,27 @@
+#include "common/brickmap.hlsl"
+
+struct UploadInfo {
+    uint brick_count;
+    uint top_count;
+};
+
+StructuredBuffer<UploadInfo> upload_info;
+StructuredBuffer<uint> brick_upload_targets;
+StructuredBuffer<Brick> brick_upload_data;
+StructuredBuffer<uint2> top_upload_data;
+RWStructuredBuffer<uint> brickmap_top;
+RWStructuredBuffer<Brick> brick_pool;
+
+// scatters the staged bricks and top level pointers to their place in the world buffers
+[numthreads(64, 1, 1)] void main(uint thread_id
+                                 : SV_DispatchThreadID)
+{
+    UploadInfo info = upload_info[0];
+    if (thread_id < info.brick_count) {
+        brick_pool[brick_upload_targets[thread_id]] = brick_upload_data[thread_id];
+    }
+    if (thread_id < info.top_count) {
+        uint2 update = top_upload_data[thread_id];
+        brickmap_top[update.x] = update.y;
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use cogrrs::egui::{self, Slider, TextEdit, Ui};
+use glam::{IVec3, UVec3};
+use log::{error, info};
+
+use crate::constants::{WORLD_CENTER, WORLD_SIZE_IN_BRICKS};
+use crate::file_watcher::FileWatcher;
+use crate::jobs::Jobs;
+use crate::world::asset::{clear_in_world, place_in_world, spawn_load_asset};
+use crate::world::brickmap::BRICK_SIZE;
+use crate::world::material::STONE;
+use crate::world::voxelized::MeshGridBitfield;
+use crate::world::VoxelStorage;
+
+// how often the files of loaded assets are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+struct PlacedAsset {
+    size: u32,
+    origin: IVec3,
+    dimensions: UVec3,
+}
+
+// Loads obj and vox files in the background and places them in the middle of the world. The source files of
+// placed assets are watched, when one of them is saved again it is voxelized again and swapped into the world.
+// Only the box of the most recent placement of a file is remembered: reloading clears that box, including
+// whatever other assets or edits overlap it, and voxels of earlier placements of the same file stay behind
+pub struct AssetManager {
+    jobs: Jobs<MeshGridBitfield>,
+    // by path, the size the running job was started with
+    loading: HashMap<String, u32>,
+    placed: HashMap<String, PlacedAsset>,
+    watcher: FileWatcher,
+    pub hot_reload: bool,
+    path: String,
+    size: u32,
+    error: Option<String>,
+}
+
+impl Default for AssetManager {
+    fn default() -> Self {
+        Self {
+            jobs: Jobs::default(),
+            loading: HashMap::new(),
+            placed: HashMap::new(),
+            watcher: FileWatcher::new(WATCH_INTERVAL),
+            hot_reload: true,
+            path: String::new(),
+            size: 64,
+            error: None,
+        }
+    }
+}
+
+impl AssetManager {
+    // a load of the same file that is still running is cancelled, only the latest version gets placed
+    pub fn load(&mut self, path: &str, size: u32) {
+        self.jobs.cancel(path);
+        self.jobs.push(spawn_load_asset(path, size));
+        self.loading.insert(path.to_string(), size);
+    }
+
+    // returns whether an asset was placed in the world
+    pub fn update(&mut self, world: &mut impl VoxelStorage) -> bool {
+        let mut placed = false;
+        if self.hot_reload {
+            for path in self.watcher.changed() {
+                let path = path.to_string_lossy().to_string();
+                if let Some(size) = self.placed.get(&path).map(|asset| asset.size) {
+                    info!("{} changed, reloading", path);
+                    self.load(&path, size);
+                }
+            }
+        }
+
+        for (path, result) in self.jobs.poll() {
+            let size = self.loading.remove(&path).unwrap_or(self.size);
+            match result {
+                Ok(grid) => {
+                    if let Some(old) = self.placed.get(&path) {
+                        clear_in_world(world, old.origin, old.dimensions);
+                    }
+                    let origin = WORLD_CENTER.as_ivec3() - (grid.dimensions() / 2).as_ivec3();
+                    place_in_world(world, &grid, origin, STONE);
+                    self.watcher.watch(&path);
+                    self.placed.insert(
+                        path,
+                        PlacedAsset {
+                            size,
+                            origin,
+                            dimensions: grid.dimensions(),
+                        },
+                    );
+                    self.error = None;
+                    placed = true;
+                }
+                // a broken export keeps the previous version in the world, the next save is picked up again
+                Err(error) => {
+                    error!("could not load {}: {:#}", path, error);
+                    self.error = Some(format!("could not load {}: {:#}", path, error));
+                }
+            }
+        }
+        // jobs that were cancelled from the ui never report back
+        let jobs = &self.jobs;
+        self.loading.retain(|path, _| jobs.is_running(path));
+        placed
+    }
+
+    pub fn draw_ui(&mut self, ui: &mut Ui) {
+        ui.add(TextEdit::singleline(&mut self.path).hint_text("path to an obj or vox file"));
+        ui.add(Slider::new(&mut self.size, 8..=WORLD_SIZE_IN_BRICKS * BRICK_SIZE).text("Voxelization size"));
+        ui.horizontal(|ui| {
+            if ui.button("Load asset").clicked() && !self.path.is_empty() {
+                let path = self.path.clone();
+                self.load(&path, self.size);
+            }
+            ui.checkbox(&mut self.hot_reload, "Reload on change");
+        });
+        self.jobs.draw_ui(ui);
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+}
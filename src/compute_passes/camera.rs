@@ -1,28 +1,31 @@
 use std::mem::size_of;
 
+use anyhow::{Context, Result};
 use bytemuck::{Pod, Zeroable};
 use cogrrs::egui::{Slider, Ui};
 use cogrrs::wgpu::TextureFormat;
-use cogrrs::TextureRes::FullRes;
-use cogrrs::{div_ceil, CoGr, Encoder, Input, Pipeline, ResourceHandle};
+use cogrrs::{div_ceil, CoGr, Encoder, Input, ResourceHandle};
 use dolly::prelude::{Position, Smooth, YawPitch};
 use dolly::rig::CameraRig;
-use glam::{UVec2, Vec3};
+use glam::{UVec2, Vec2, Vec3};
 
 use cogrrs::puffin;
 
+use crate::constants::WORLD_CENTER;
 use crate::helpers::bool_to_f32;
 use crate::key_mapping::{MOVE_BACKWARD, MOVE_DOWN, MOVE_FORWARD, MOVE_LEFT, MOVE_RIGHT, MOVE_UP};
 
-use super::{ComputePass, ResourceHandles};
+use super::{ComputePass, GraphResources, PassNode, ResourceHandles, ShaderChanges, ShaderPipeline, TO_SCREEN};
+
+pub const PRIMARY_RAY_DIRECTION: &str = "primary_ray_direction";
+pub const CAMERA_DATA: &str = "camera_data";
+pub const DEBUG_RAY_DIRECTION: &str = "debug ray direction";
 
 pub struct Camera {
     camera: CameraRig,
     random_seed: u32,
-    primary_ray_data: ResourceHandle,
-    camera_data: ResourceHandle,
-    generate_rays: Pipeline,
-    debug_ray_direction: Pipeline,
+    generate_rays: ShaderPipeline,
+    debug_ray_direction: ShaderPipeline,
 
     pub aperture: f32,
     pub focal_length: f32,
@@ -43,45 +46,56 @@ struct CameraGpu {
     screen_dimensions: UVec2,
 }
 
+#[derive(Clone)]
 pub struct PrimaryRayGenResults {
     pub primary_ray_data: ResourceHandle,
     pub camera_gpu: ResourceHandle,
 }
 
-impl ResourceHandles for PrimaryRayGenResults {}
+impl ResourceHandles for PrimaryRayGenResults {
+    fn names() -> Vec<&'static str> {
+        vec![PRIMARY_RAY_DIRECTION, CAMERA_DATA]
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            primary_ray_data: resources.get(PRIMARY_RAY_DIRECTION)?,
+            camera_gpu: resources.get(CAMERA_DATA)?,
+        })
+    }
+}
 
 impl ComputePass for Camera {
+    const NAME: &'static str = "camera";
     type Inputs = ();
     type Outputs = PrimaryRayGenResults;
 
-    fn new(gpu: &mut CoGr) -> Self {
+    fn new(gpu: &mut CoGr, resources: &mut GraphResources) -> Result<Self> {
         let camera: CameraRig = CameraRig::builder()
             .with(YawPitch::new().yaw_degrees(45.0).pitch_degrees(-30.0))
-            .with(Position::new(Vec3::Y))
+            .with(Position::new(WORLD_CENTER + Vec3::Y))
             .with(Smooth::new_position_rotation(0.5, 0.5))
             .build();
-        let primary_ray_data = gpu.texture("primary_ray_direction", FullRes, TextureFormat::Rgba32Float);
-        let camera_data = gpu.buffer("camera_data", 1, size_of::<CameraGpu>());
-        let generate_rays = gpu.pipeline("shaders/generate_rays.hlsl").unwrap();
-        let debug_ray_direction = gpu.pipeline("shaders/debug_renders/ray_direction.hlsl").unwrap();
-        Self {
+        resources.texture(gpu, PRIMARY_RAY_DIRECTION, TextureFormat::Rgba32Float);
+        resources.buffer(gpu, CAMERA_DATA, 1, size_of::<CameraGpu>());
+        let generate_rays = ShaderPipeline::new(gpu, "shaders/generate_rays.hlsl")?;
+        let debug_ray_direction = ShaderPipeline::new(gpu, "shaders/debug_renders/ray_direction.hlsl")?;
+        Ok(Self {
             camera,
             random_seed: 1,
-            primary_ray_data,
-            camera_data,
             generate_rays,
             debug_ray_direction,
             aperture: 1000f32,
             focal_length: 1.7,
             sensor_height: 1.57f32,
-        }
+        })
     }
 
-    fn rebuild(&mut self, _gpu: &mut CoGr) {
-        todo!()
+    fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges) {
+        self.generate_rays.rebuild(gpu, changes);
+        self.debug_ray_direction.rebuild(gpu, changes);
     }
 
-    fn dispatch(&mut self, encoder: &mut Encoder, _: &Self::Inputs) -> Self::Outputs {
+    fn dispatch(&mut self, encoder: &mut Encoder, _: &Self::Inputs, outputs: &Self::Outputs) -> Result<()> {
         puffin::profile_scope!("Generate rays");
 
         self.random_seed += 1;
@@ -97,27 +111,21 @@ impl ComputePass for Camera {
             screen_dimensions: UVec2::new(encoder.width(), encoder.height()),
         };
         // upload latest camera data to gpu
-        encoder.set_buffer_data(&self.camera_data, [camera_data]).unwrap();
-        // use latest camera data to calculate new rays
         encoder
-            .dispatch_pipeline(
-                &mut self.generate_rays,
-                (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1),
-                &[0; 0],
-                &[&self.primary_ray_data, &self.camera_data],
-            )
-            .unwrap();
-
-        PrimaryRayGenResults {
-            primary_ray_data: self.primary_ray_data.clone(),
-            camera_gpu: self.camera_data.clone(),
-        }
+            .set_buffer_data(&outputs.camera_gpu, [camera_data])
+            .context("could not upload the camera data")?;
+        // use latest camera data to calculate new rays
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.generate_rays
+            .dispatch(encoder, workgroups, &[&outputs.primary_ray_data, &outputs.camera_gpu])
     }
 
     fn draw_ui(&mut self, ui: &mut Ui) {
         ui.add(Slider::new(&mut self.aperture, 2.8..=1000.0).text("Aperture"));
         ui.add(Slider::new(&mut self.focal_length, 1.7..=5.0).text("Focal length"));
         ui.add(Slider::new(&mut self.sensor_height, 0.0..=10.0).text("Sensor height"));
+        self.generate_rays.draw_error(ui);
+        self.debug_ray_direction.draw_error(ui);
     }
 }
 
@@ -135,14 +143,23 @@ impl Camera {
         self.camera.driver_mut::<Position>().translate(move_vec * dt * 10.0);
         self.camera.update(dt);
     }
-    pub fn debug_ray_direction(&mut self, encoder: &mut Encoder, to_screen: &ResourceHandle) {
-        encoder
-            .dispatch_pipeline(
-                &mut self.debug_ray_direction,
-                (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1),
-                &[0; 0],
-                &[&self.primary_ray_data, to_screen],
-            )
-            .unwrap();
+    // origin and direction of the ray generate_rays.hlsl produces for `pixel`, without the depth of field jitter
+    pub fn ray_through_pixel(&self, pixel: Vec2, screen_dimensions: UVec2) -> (Vec3, Vec3) {
+        let transform = &self.camera.final_transform;
+        let sensor_center = transform.position - transform.forward() * self.focal_length;
+        // the shader divides the screen dimensions as integers
+        let aspect_ratio = (screen_dimensions.x / screen_dimensions.y) as f32;
+        let horizontal_shift = (pixel.x / screen_dimensions.x as f32 - 0.5) * self.sensor_height * aspect_ratio;
+        let vertical_shift = (pixel.y / screen_dimensions.y as f32 - 0.5) * self.sensor_height;
+        let position_on_sensor = sensor_center + horizontal_shift * transform.right() + vertical_shift * transform.up();
+        (transform.position, transform.position - position_on_sensor)
+    }
+    pub fn debug_ray_direction_node() -> PassNode {
+        PassNode::new(DEBUG_RAY_DIRECTION).reads([PRIMARY_RAY_DIRECTION]).writes([TO_SCREEN])
+    }
+    pub fn debug_ray_direction(&mut self, encoder: &mut Encoder, resources: &GraphResources) -> Result<()> {
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.debug_ray_direction
+            .dispatch(encoder, workgroups, &[&resources.get(PRIMARY_RAY_DIRECTION)?, &resources.get(TO_SCREEN)?])
     }
 }
@@ -0,0 +1,130 @@
+use std::mem::size_of;
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+use cogrrs::egui::Ui;
+use cogrrs::{div_ceil, puffin, CoGr, Encoder, ResourceHandle};
+use glam::{IVec3, UVec2, Vec4};
+
+use crate::editor::{BrushShape, EditorTool, VoxelEditor};
+
+use super::{
+    ComputePass, GraphResources, PrimaryRayCasterResults, PrimaryRayGenResults, ResourceHandles, ScreenTarget, ShaderChanges, ShaderPipeline,
+};
+
+// tints the voxels the brush would change, the voxel behind every pixel is reconstructed from the G-buffer
+pub struct EditorPreview {
+    preview_data: ResourceHandle,
+    preview: ShaderPipeline,
+    data: EditorPreviewGpu,
+}
+
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable)]
+struct EditorPreviewGpu {
+    half_extents: IVec3,
+    radius: f32,
+    cursor: UVec2,
+    // 0 sphere, 1 box, 2 cylinder
+    shape: u32,
+    // 1 when the brush is centered on the empty voxel in front of the hit face
+    place: u32,
+    color: Vec4,
+}
+
+// the image on screen is read as well, the preview is blended over it
+pub struct EditorPreviewInputs {
+    pub rays: PrimaryRayGenResults,
+    pub gbuffer: PrimaryRayCasterResults,
+    pub screen: ScreenTarget,
+}
+
+impl ResourceHandles for EditorPreviewInputs {
+    fn names() -> Vec<&'static str> {
+        [PrimaryRayGenResults::names(), PrimaryRayCasterResults::names(), ScreenTarget::names()].concat()
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            rays: PrimaryRayGenResults::from_resources(resources)?,
+            gbuffer: PrimaryRayCasterResults::from_resources(resources)?,
+            screen: ScreenTarget::from_resources(resources)?,
+        })
+    }
+}
+
+impl ComputePass for EditorPreview {
+    const NAME: &'static str = "editor preview";
+    type Inputs = EditorPreviewInputs;
+    type Outputs = ScreenTarget;
+
+    fn new(gpu: &mut CoGr, _: &mut GraphResources) -> Result<Self> {
+        let preview_data = gpu.buffer("editor_preview", 1, size_of::<EditorPreviewGpu>());
+        let preview = ShaderPipeline::new(gpu, "shaders/editor_preview.hlsl")?;
+        Ok(Self {
+            preview_data,
+            preview,
+            data: EditorPreviewGpu::zeroed(),
+        })
+    }
+
+    fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges) {
+        self.preview.rebuild(gpu, changes);
+    }
+
+    fn dispatch(&mut self, encoder: &mut Encoder, inputs: &Self::Inputs, outputs: &Self::Outputs) -> Result<()> {
+        puffin::profile_scope!("Editor preview");
+        if self.data.color.w == 0.0 {
+            return Ok(());
+        }
+        encoder
+            .set_buffer_data(&self.preview_data, [self.data])
+            .context("could not upload the brush")?;
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.preview.dispatch(
+            encoder,
+            workgroups,
+            &[
+                &inputs.rays.primary_ray_data,
+                &inputs.rays.camera_gpu,
+                &inputs.gbuffer.normal,
+                &inputs.gbuffer.depth,
+                &self.preview_data,
+                &outputs.to_screen,
+            ],
+        )
+    }
+
+    fn draw_ui(&mut self, ui: &mut Ui) {
+        self.preview.draw_error(ui);
+    }
+}
+
+impl EditorPreview {
+    // nothing is drawn while the cursor is not over the image or the editor is disabled
+    pub fn update(&mut self, editor: &VoxelEditor, cursor: Option<UVec2>) {
+        let cursor = match cursor {
+            Some(cursor) if editor.enabled && editor.hovered().is_some() => cursor,
+            _ => {
+                self.data.color = Vec4::ZERO;
+                return;
+            }
+        };
+        let color = match editor.tool {
+            EditorTool::Place => Vec4::new(0.2, 1.0, 0.2, 0.5),
+            EditorTool::Remove => Vec4::new(1.0, 0.2, 0.2, 0.5),
+            EditorTool::Paint => Vec4::new(0.2, 0.4, 1.0, 0.5),
+        };
+        self.data = EditorPreviewGpu {
+            half_extents: IVec3::splat(editor.size),
+            radius: editor.size as f32,
+            cursor,
+            shape: match editor.shape {
+                BrushShape::Sphere => 0,
+                BrushShape::Box => 1,
+                BrushShape::Cylinder => 2,
+            },
+            place: (editor.tool == EditorTool::Place) as u32,
+            color,
+        };
+    }
+}
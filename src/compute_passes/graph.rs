@@ -0,0 +1,296 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, ensure, Result};
+use cogrrs::egui::{Color32, Ui};
+
+// a pass as the render graph sees it, the names of the resources it reads and writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassNode {
+    pub name: &'static str,
+    pub reads: Vec<&'static str>,
+    pub writes: Vec<&'static str>,
+}
+
+impl PassNode {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+    pub fn reads(mut self, resources: impl IntoIterator<Item = &'static str>) -> Self {
+        self.reads.extend(resources);
+        self
+    }
+    pub fn writes(mut self, resources: impl IntoIterator<Item = &'static str>) -> Self {
+        self.writes.extend(resources);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    // the enabled passes in the order they have to run
+    pub order: Vec<&'static str>,
+    // pass and resource for every write that no enabled pass reads and that is not an output of the graph
+    pub unused_outputs: Vec<(&'static str, &'static str)>,
+}
+
+// Orders passes by the resources they read and write. A pass runs after every other enabled pass that writes
+// a resource it reads, passes that do not depend on each other run in the order they were added. Reading a
+// resource no other enabled pass writes is an error, so is a cycle. A pass that writes a resource without
+// reading it replaces its content, two enabled passes doing that to the same resource would race, which is an
+// error as well. Only names are involved, so graphs can be built and checked without a GPU
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<PassNode>,
+    disabled: HashSet<&'static str>,
+    // groups of passes of which at most one is enabled, enabling one disables the others
+    exclusive: Vec<Vec<&'static str>>,
+    // resources that are used outside of the graph, like the image that is shown on screen
+    outputs: HashSet<&'static str>,
+    schedule: Schedule,
+    error: Option<String>,
+}
+
+impl RenderGraph {
+    pub fn add_pass(&mut self, node: PassNode) -> Result<()> {
+        ensure!(self.pass(node.name).is_none(), "pass {} was added twice", node.name);
+        self.passes.push(node);
+        Ok(())
+    }
+
+    pub fn output(&mut self, resource: &'static str) {
+        self.outputs.insert(resource);
+    }
+
+    // for alternatives like debug renders that all replace the image on screen
+    pub fn exclusive(&mut self, passes: impl IntoIterator<Item = &'static str>) {
+        self.exclusive.push(passes.into_iter().collect());
+    }
+
+    pub fn is_enabled(&self, pass: &str) -> bool {
+        self.pass(pass).is_some() && !self.disabled.contains(pass)
+    }
+
+    // compiles the graph again, a change that would make it invalid is undone and returned as the error
+    pub fn set_enabled(&mut self, pass: &str, enabled: bool) -> Result<()> {
+        let Some(name) = self.pass(pass).map(|node| node.name) else {
+            bail!("there is no pass called {}", pass);
+        };
+        let previously_disabled = self.disabled.clone();
+        if enabled {
+            self.disabled.remove(name);
+            let others: Vec<&'static str> = self
+                .exclusive
+                .iter()
+                .filter(|group| group.contains(&name))
+                .flatten()
+                .copied()
+                .filter(|&other| other != name)
+                .collect();
+            self.disabled.extend(others);
+        } else {
+            self.disabled.insert(name);
+        }
+        let compiled = self.compile();
+        if compiled.is_err() {
+            self.disabled = previously_disabled;
+        }
+        compiled
+    }
+
+    // the schedule is only replaced when the graph is valid
+    pub fn compile(&mut self) -> Result<()> {
+        self.schedule = self.plan()?;
+        Ok(())
+    }
+
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    pub fn plan(&self) -> Result<Schedule> {
+        let enabled: Vec<&PassNode> = self.passes.iter().filter(|node| self.is_enabled(node.name)).collect();
+        for (index, node) in enabled.iter().enumerate() {
+            for &resource in node.writes.iter().filter(|resource| !node.reads.contains(resource)) {
+                let other = enabled[index + 1..]
+                    .iter()
+                    .find(|other| other.writes.contains(&resource) && !other.reads.contains(&resource));
+                if let Some(other) = other {
+                    bail!(
+                        "{} and {} both replace {}, only one of them can be enabled",
+                        node.name,
+                        other.name,
+                        resource
+                    );
+                }
+            }
+        }
+        let mut dependencies = vec![Vec::new(); enabled.len()];
+        for (index, node) in enabled.iter().enumerate() {
+            for &resource in &node.reads {
+                let resource_writers: Vec<usize> = (0..enabled.len())
+                    .filter(|&writer| writer != index && enabled[writer].writes.contains(&resource))
+                    .collect();
+                ensure!(
+                    !resource_writers.is_empty(),
+                    "{} reads {} before any enabled pass writes it",
+                    node.name,
+                    resource
+                );
+                dependencies[index].extend(resource_writers);
+            }
+        }
+
+        let mut done = vec![false; enabled.len()];
+        let mut order = Vec::with_capacity(enabled.len());
+        while order.len() < enabled.len() {
+            let ready = (0..enabled.len()).find(|&index| !done[index] && dependencies[index].iter().all(|&dependency| done[dependency]));
+            let Some(next) = ready else {
+                let waiting: Vec<&str> = (0..enabled.len())
+                    .filter(|&index| !done[index])
+                    .map(|index| enabled[index].name)
+                    .collect();
+                bail!("passes wait for each other in a cycle: {}", waiting.join(", "));
+            };
+            done[next] = true;
+            order.push(enabled[next].name);
+        }
+
+        let read: HashSet<&str> = enabled.iter().flat_map(|node| node.reads.iter().copied()).collect();
+        let unused_outputs = enabled
+            .iter()
+            .flat_map(|node| node.writes.iter().map(move |&resource| (node.name, resource)))
+            .filter(|(_, resource)| !read.contains(resource) && !self.outputs.contains(resource))
+            .collect();
+        Ok(Schedule { order, unused_outputs })
+    }
+
+    // a checkbox for every pass, toggles that would break the graph are refused
+    pub fn draw_ui(&mut self, ui: &mut Ui) {
+        ui.collapsing("render graph", |ui| {
+            for index in 0..self.passes.len() {
+                let name = self.passes[index].name;
+                let mut enabled = self.is_enabled(name);
+                if ui.checkbox(&mut enabled, name).changed() {
+                    self.error = self.set_enabled(name, enabled).err().map(|error| format!("{:#}", error));
+                }
+            }
+            ui.label(format!("order: {}", self.schedule.order.join(" -> ")));
+            for (pass, resource) in &self.schedule.unused_outputs {
+                ui.label(format!("{} writes {} but nothing reads it", pass, resource));
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+    }
+
+    fn pass(&self, name: &str) -> Option<&PassNode> {
+        self.passes.iter().find(|node| node.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(nodes: impl IntoIterator<Item = PassNode>) -> RenderGraph {
+        let mut graph = RenderGraph::default();
+        graph.output("screen");
+        for node in nodes {
+            graph.add_pass(node).unwrap();
+        }
+        graph
+    }
+
+    fn error(result: Result<impl std::fmt::Debug>) -> String {
+        format!("{:#}", result.unwrap_err())
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_they_read_from() {
+        let graph = graph([
+            PassNode::new("shade").reads(["gbuffer", "rays"]).writes(["screen"]),
+            PassNode::new("overlay").reads(["gbuffer", "screen"]).writes(["screen"]),
+            PassNode::new("trace").reads(["rays"]).writes(["gbuffer"]),
+            PassNode::new("camera").writes(["rays"]),
+        ]);
+        let schedule = graph.plan().unwrap();
+        assert_eq!(schedule.order, vec!["camera", "trace", "shade", "overlay"]);
+        assert!(schedule.unused_outputs.is_empty());
+    }
+
+    #[test]
+    fn invalid_graphs_are_errors() {
+        let missing = graph([PassNode::new("trace").reads(["rays"]).writes(["screen"])]);
+        assert_eq!(error(missing.plan()), "trace reads rays before any enabled pass writes it");
+
+        let cycle = graph([
+            PassNode::new("camera").writes(["rays"]),
+            PassNode::new("a").reads(["rays", "b"]).writes(["a"]),
+            PassNode::new("b").reads(["a"]).writes(["b"]),
+        ]);
+        assert_eq!(error(cycle.plan()), "passes wait for each other in a cycle: a, b");
+
+        let racing = graph([PassNode::new("depth").writes(["screen"]), PassNode::new("normals").writes(["screen"])]);
+        assert_eq!(
+            error(racing.plan()),
+            "depth and normals both replace screen, only one of them can be enabled"
+        );
+
+        let mut twice = graph([PassNode::new("camera")]);
+        assert!(twice.add_pass(PassNode::new("camera")).is_err());
+    }
+
+    #[test]
+    fn unused_outputs_are_reported() {
+        let graph = graph([
+            PassNode::new("camera").writes(["rays", "seed"]),
+            PassNode::new("trace").reads(["rays"]).writes(["screen", "complexity"]),
+        ]);
+        assert_eq!(graph.plan().unwrap().unused_outputs, vec![("camera", "seed"), ("trace", "complexity")]);
+    }
+
+    #[test]
+    fn toggles_that_break_the_graph_are_undone() {
+        let mut graph = graph([
+            PassNode::new("camera").writes(["rays"]),
+            PassNode::new("trace").reads(["rays"]).writes(["screen"]),
+        ]);
+        graph.compile().unwrap();
+        assert!(graph.set_enabled("camera", false).is_err());
+        assert!(graph.is_enabled("camera"));
+        assert_eq!(graph.schedule().order, vec!["camera", "trace"]);
+
+        graph.set_enabled("trace", false).unwrap();
+        assert_eq!(graph.schedule().order, vec!["camera"]);
+        graph.set_enabled("camera", false).unwrap();
+        assert!(graph.schedule().order.is_empty());
+        assert!(graph.set_enabled("missing", true).is_err());
+    }
+
+    #[test]
+    fn enabling_an_exclusive_pass_disables_the_others() {
+        let mut graph = graph([
+            PassNode::new("camera").writes(["rays"]),
+            PassNode::new("depth").reads(["rays"]).writes(["screen"]),
+            PassNode::new("normals").reads(["rays"]).writes(["screen"]),
+            PassNode::new("broken").reads(["missing"]).writes(["screen"]),
+            PassNode::new("overlay").reads(["screen"]).writes(["screen"]),
+        ]);
+        graph.exclusive(["depth", "normals", "broken"]);
+        graph.set_enabled("depth", true).unwrap();
+        assert_eq!(graph.schedule().order, vec!["camera", "depth", "overlay"]);
+        graph.set_enabled("normals", true).unwrap();
+        assert_eq!(graph.schedule().order, vec!["camera", "normals", "overlay"]);
+
+        // a failed switch keeps the render that was enabled before
+        assert!(graph.set_enabled("broken", true).is_err());
+        assert!(graph.set_enabled("normals", false).is_err());
+        assert!(graph.is_enabled("normals") && !graph.is_enabled("depth") && !graph.is_enabled("broken"));
+        assert_eq!(graph.schedule().order, vec!["camera", "normals", "overlay"]);
+    }
+}
@@ -1,28 +1,60 @@
 mod camera;
+mod editor_preview;
+mod graph;
+mod picker;
 mod primary_ray_caster;
+mod resources;
+mod shader;
+mod world_upload;
+
+use anyhow::Result;
+use cogrrs::{egui::Ui, CoGr, Encoder};
 
 pub use camera::*;
-use cogrrs::{egui::Ui, CoGr, Encoder, ResourceHandle};
+pub use editor_preview::*;
+pub use graph::*;
+pub use picker::*;
 pub use primary_ray_caster::*;
+pub use resources::*;
+pub use shader::*;
+pub use world_upload::*;
 
-pub trait ComputePass {
+pub trait ComputePass: Sized {
+    // identifies the pass in the render graph and in errors
+    const NAME: &'static str;
     type Inputs: ResourceHandles;
     type Outputs: ResourceHandles;
 
-    fn new(gpu: &mut CoGr) -> Self;
-    fn rebuild(&mut self, gpu: &mut CoGr);
-    fn dispatch(&mut self, encoder: &mut Encoder, inputs: &Self::Inputs) -> Self::Outputs;
+    // the resources a pass shares with other passes are created through `resources`
+    fn new(gpu: &mut CoGr, resources: &mut GraphResources) -> Result<Self>;
+    // shaders that fail to compile keep their previous pipeline and show the error in draw_ui instead
+    fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges);
+    fn dispatch(&mut self, encoder: &mut Encoder, inputs: &Self::Inputs, outputs: &Self::Outputs) -> Result<()>;
     fn draw_ui(&mut self, ui: &mut Ui);
+
+    fn node() -> PassNode {
+        PassNode::new(Self::NAME).reads(Self::Inputs::names()).writes(Self::Outputs::names())
+    }
+
+    // looks up the inputs and outputs by name and dispatches
+    fn run(&mut self, encoder: &mut Encoder, resources: &GraphResources) -> Result<()> {
+        let inputs = Self::Inputs::from_resources(resources)?;
+        let outputs = Self::Outputs::from_resources(resources)?;
+        self.dispatch(encoder, &inputs, &outputs)
+    }
 }
 
-pub trait ResourceHandles {}
-impl ResourceHandles for () {}
-impl ResourceHandles for ResourceHandle {}
-impl ResourceHandles for [&ResourceHandle] {}
-impl<T: ResourceHandles> ResourceHandles for (T, T) {}
-impl<T: ResourceHandles> ResourceHandles for (T, T, T) {}
-impl<T: ResourceHandles> ResourceHandles for (T, T, T, T) {}
-impl<T: ResourceHandles> ResourceHandles for (T, T, T, T, T) {}
-impl<T: ResourceHandles> ResourceHandles for (T, T, T, T, T, T) {}
-impl<T: ResourceHandles> ResourceHandles for (T, T, T, T, T, T, T) {}
-impl<T: ResourceHandles> ResourceHandles for (T, T, T, T, T, T, T, T) {}
+// named resources a pass reads or writes, the names are how the render graph connects the passes
+pub trait ResourceHandles: Sized {
+    fn names() -> Vec<&'static str>;
+    fn from_resources(resources: &GraphResources) -> Result<Self>;
+}
+
+impl ResourceHandles for () {
+    fn names() -> Vec<&'static str> {
+        Vec::new()
+    }
+    fn from_resources(_: &GraphResources) -> Result<Self> {
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use std::mem::size_of;
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+use cogrrs::egui::Ui;
+use cogrrs::{puffin, CoGr, Encoder, ResourceHandle};
+use glam::{IVec3, UVec2, Vec3};
+
+use crate::world::material::{Material, MATERIAL_NAMES};
+use crate::world::picking::PickResult;
+
+use super::{ComputePass, GraphResources, PrimaryRayCasterResults, PrimaryRayGenResults, ResourceHandles, ShaderChanges, ShaderPipeline};
+
+// reads what is under the cursor back from the G-buffer of the primary ray caster. The result arrives
+// after the frame it was requested in has finished, so it lags behind the cursor by a frame
+pub struct Picker {
+    pick: ResourceHandle,
+    pick_pipeline: ShaderPipeline,
+    cursor: Option<UVec2>,
+    requested: bool,
+    last_pick: Option<PickResult>,
+}
+
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable, Debug)]
+struct PickGpu {
+    voxel: IVec3,
+    depth: f32,
+    normal: Vec3,
+    material: u32,
+    cursor: UVec2,
+    valid: u32,
+    padding: u32,
+}
+
+pub struct PickerInputs {
+    pub rays: PrimaryRayGenResults,
+    pub gbuffer: PrimaryRayCasterResults,
+}
+
+impl ResourceHandles for PickerInputs {
+    fn names() -> Vec<&'static str> {
+        [PrimaryRayGenResults::names(), PrimaryRayCasterResults::names()].concat()
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            rays: PrimaryRayGenResults::from_resources(resources)?,
+            gbuffer: PrimaryRayCasterResults::from_resources(resources)?,
+        })
+    }
+}
+
+// the pick buffer is read back by the picker itself, so it is not shared with other passes
+impl ComputePass for Picker {
+    const NAME: &'static str = "picker";
+    type Inputs = PickerInputs;
+    type Outputs = ();
+
+    fn new(gpu: &mut CoGr, _: &mut GraphResources) -> Result<Self> {
+        let pick = gpu.buffer("pick", 1, size_of::<PickGpu>());
+        let pick_pipeline = ShaderPipeline::new(gpu, "shaders/pick.hlsl")?;
+        Ok(Self {
+            pick,
+            pick_pipeline,
+            cursor: None,
+            requested: false,
+            last_pick: None,
+        })
+    }
+
+    fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges) {
+        self.pick_pipeline.rebuild(gpu, changes);
+    }
+
+    fn dispatch(&mut self, encoder: &mut Encoder, inputs: &Self::Inputs, _: &Self::Outputs) -> Result<()> {
+        puffin::profile_scope!("Pick");
+        let Some(cursor) = self.cursor else {
+            return Ok(());
+        };
+        let request = PickGpu { cursor, ..PickGpu::zeroed() };
+        encoder
+            .set_buffer_data(&self.pick, [request])
+            .context("could not upload the pick request")?;
+        self.pick_pipeline.dispatch(
+            encoder,
+            (1, 1, 1),
+            &[
+                &inputs.rays.primary_ray_data,
+                &inputs.rays.camera_gpu,
+                &inputs.gbuffer.normal,
+                &inputs.gbuffer.depth,
+                &inputs.gbuffer.material,
+                &self.pick,
+            ],
+        )?;
+        self.requested = true;
+        Ok(())
+    }
+
+    fn draw_ui(&mut self, ui: &mut Ui) {
+        match &self.last_pick {
+            Some(pick) => {
+                let material = MATERIAL_NAMES.get(pick.material as usize).copied().unwrap_or("unknown");
+                ui.label(format!("cursor voxel: {}", pick.voxel));
+                ui.label(format!("cursor normal: {}", pick.normal));
+                ui.label(format!("cursor depth: {:.2}", pick.depth));
+                ui.label(format!("cursor material: {} ({})", material, pick.material));
+            }
+            None => {
+                ui.label("cursor voxel: none");
+            }
+        }
+        self.pick_pipeline.draw_error(ui);
+    }
+}
+
+impl Picker {
+    // pixel to pick in the next dispatch, None stops picking
+    pub fn request(&mut self, cursor: Option<UVec2>) {
+        self.cursor = cursor;
+        if cursor.is_none() {
+            self.last_pick = None;
+        }
+    }
+
+    // reads back the result of the last dispatch, has to be called once the frame that dispatched it was submitted
+    pub fn read_back(&mut self, gpu: &mut CoGr) -> Result<()> {
+        if !self.requested {
+            return Ok(());
+        }
+        puffin::profile_function!();
+        self.requested = false;
+        let pick: Vec<PickGpu> = gpu.read_buffer(&self.pick).context("could not read back the pick")?;
+        // the cursor may have left the image since the pick was requested
+        self.last_pick = pick
+            .first()
+            .filter(|pick| pick.valid == 1 && self.cursor.is_some())
+            .map(|pick| PickResult {
+                voxel: pick.voxel,
+                normal: pick.normal.round().as_ivec3(),
+                depth: pick.depth,
+                material: pick.material as Material,
+            });
+        Ok(())
+    }
+
+    pub fn last_pick(&self) -> Option<&PickResult> {
+        self.last_pick.as_ref()
+    }
+}
@@ -1,122 +1,151 @@
+use anyhow::Result;
+use cogrrs::egui::Ui;
 use cogrrs::wgpu::TextureFormat;
-use cogrrs::TextureRes::FullRes;
-use cogrrs::{div_ceil, puffin, CoGr, Encoder, Pipeline, ResourceHandle};
+use cogrrs::{div_ceil, puffin, CoGr, Encoder, ResourceHandle};
 
 use crate::compute_passes::camera::PrimaryRayGenResults;
 
-use super::{ComputePass, ResourceHandles};
+use super::{ComputePass, GraphResources, PassNode, ResourceHandles, ShaderChanges, ShaderPipeline, WorldBuffers, TO_SCREEN};
+
+pub const NORMAL: &str = "normal";
+pub const DEPTH: &str = "depth";
+pub const MATERIAL: &str = "material";
+pub const COMPLEXITY: &str = "complexity";
+pub const DEBUG_COMPLEXITY: &str = "debug complexity";
+pub const DEBUG_DEPTH: &str = "debug depth";
+pub const DEBUG_NORMALS: &str = "debug normals";
 
 pub struct PrimaryRayCaster {
-    normal: ResourceHandle,
-    depth: ResourceHandle,
-    material: ResourceHandle,
-    complexity: ResourceHandle,
-    trace_ray: Pipeline,
-    debug_complexity: Pipeline,
-    debug_depth: Pipeline,
-    debug_normals: Pipeline,
+    trace_ray: ShaderPipeline,
+    debug_complexity: ShaderPipeline,
+    debug_depth: ShaderPipeline,
+    debug_normals: ShaderPipeline,
 }
 
+#[derive(Clone)]
 pub struct PrimaryRayCasterResults {
-    normal: ResourceHandle,
-    depth: ResourceHandle,
-    material: ResourceHandle,
-    complexity: ResourceHandle,
+    pub normal: ResourceHandle,
+    pub depth: ResourceHandle,
+    pub material: ResourceHandle,
+    pub complexity: ResourceHandle,
+}
+
+impl ResourceHandles for PrimaryRayCasterResults {
+    fn names() -> Vec<&'static str> {
+        vec![NORMAL, DEPTH, MATERIAL, COMPLEXITY]
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            normal: resources.get(NORMAL)?,
+            depth: resources.get(DEPTH)?,
+            material: resources.get(MATERIAL)?,
+            complexity: resources.get(COMPLEXITY)?,
+        })
+    }
+}
+
+pub struct PrimaryRayCasterInputs {
+    pub rays: PrimaryRayGenResults,
+    pub world: WorldBuffers,
 }
 
-impl ResourceHandles for PrimaryRayCasterResults {}
+impl ResourceHandles for PrimaryRayCasterInputs {
+    fn names() -> Vec<&'static str> {
+        [PrimaryRayGenResults::names(), WorldBuffers::names()].concat()
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            rays: PrimaryRayGenResults::from_resources(resources)?,
+            world: WorldBuffers::from_resources(resources)?,
+        })
+    }
+}
 
 impl ComputePass for PrimaryRayCaster {
-    type Inputs = PrimaryRayGenResults;
+    const NAME: &'static str = "primary ray caster";
+    type Inputs = PrimaryRayCasterInputs;
     type Outputs = PrimaryRayCasterResults;
 
-    fn new(gpu: &mut CoGr) -> Self {
-        let normal = gpu.texture("normal", FullRes, TextureFormat::Rgba8Snorm);
-        let depth = gpu.texture("depth", FullRes, TextureFormat::R16Float);
-        let material = gpu.texture("material", FullRes, TextureFormat::R8Uint);
-        let complexity = gpu.texture("complexity", FullRes, TextureFormat::R16Uint);
+    fn new(gpu: &mut CoGr, resources: &mut GraphResources) -> Result<Self> {
+        resources.texture(gpu, NORMAL, TextureFormat::Rgba8Snorm);
+        // 32 bits because the picker and the editor preview rebuild the hit voxel from the depth
+        resources.texture(gpu, DEPTH, TextureFormat::R32Float);
+        resources.texture(gpu, MATERIAL, TextureFormat::R8Uint);
+        resources.texture(gpu, COMPLEXITY, TextureFormat::R16Uint);
 
-        let trace_ray = gpu.pipeline("shaders/trace_primary_rays.hlsl").unwrap();
-        let debug_complexity = gpu.pipeline("shaders/debug_renders/complexity.hlsl").unwrap();
-        let debug_depth = gpu.pipeline("shaders/debug_renders/depth.hlsl").unwrap();
-        let debug_normals = gpu.pipeline("shaders/debug_renders/normals.hlsl").unwrap();
-        Self {
-            normal,
-            depth,
-            material,
-            complexity,
+        let trace_ray = ShaderPipeline::new(gpu, "shaders/trace_primary_rays.hlsl")?;
+        let debug_complexity = ShaderPipeline::new(gpu, "shaders/debug_renders/complexity.hlsl")?;
+        let debug_depth = ShaderPipeline::new(gpu, "shaders/debug_renders/depth.hlsl")?;
+        let debug_normals = ShaderPipeline::new(gpu, "shaders/debug_renders/normals.hlsl")?;
+        Ok(Self {
             trace_ray,
             debug_complexity,
             debug_depth,
             debug_normals,
-        }
+        })
     }
 
-    fn dispatch(&mut self, encoder: &mut Encoder, inputs: &Self::Inputs) -> PrimaryRayCasterResults {
+    fn dispatch(&mut self, encoder: &mut Encoder, inputs: &Self::Inputs, outputs: &Self::Outputs) -> Result<()> {
         // use latest camera data to calculate new rays
         puffin::profile_function!();
 
-        encoder
-            .dispatch_pipeline(
-                &mut self.trace_ray,
-                (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1),
-                &[0; 0],
-                &[
-                    &inputs.primary_ray_data,
-                    &inputs.camera_gpu,
-                    &self.normal,
-                    &self.depth,
-                    &self.material,
-                    &self.complexity,
-                ],
-            )
-            .unwrap();
-        PrimaryRayCasterResults {
-            normal: self.normal.clone(),
-            depth: self.depth.clone(),
-            material: self.material.clone(),
-            complexity: self.complexity.clone(),
-        }
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.trace_ray.dispatch(
+            encoder,
+            workgroups,
+            &[
+                &inputs.rays.primary_ray_data,
+                &inputs.rays.camera_gpu,
+                &outputs.normal,
+                &outputs.depth,
+                &outputs.material,
+                &outputs.complexity,
+                &inputs.world.header,
+                &inputs.world.top,
+                &inputs.world.pool,
+            ],
+        )
     }
 
-    fn rebuild(&mut self, _gpu: &mut CoGr) {
-        todo!()
+    fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges) {
+        self.trace_ray.rebuild(gpu, changes);
+        self.debug_complexity.rebuild(gpu, changes);
+        self.debug_depth.rebuild(gpu, changes);
+        self.debug_normals.rebuild(gpu, changes);
     }
 
-    fn draw_ui(&mut self, _ui: &mut cogrrs::egui::Ui) {}
+    fn draw_ui(&mut self, ui: &mut Ui) {
+        self.trace_ray.draw_error(ui);
+        self.debug_complexity.draw_error(ui);
+        self.debug_depth.draw_error(ui);
+        self.debug_normals.draw_error(ui);
+    }
 }
 impl PrimaryRayCaster {
-    pub fn debug_complexity(&mut self, encoder: &mut Encoder, to_screen: &ResourceHandle) {
+    // the debug renders show one of the outputs on screen
+    pub fn debug_nodes() -> [PassNode; 3] {
+        [
+            PassNode::new(DEBUG_COMPLEXITY).reads([COMPLEXITY]).writes([TO_SCREEN]),
+            PassNode::new(DEBUG_DEPTH).reads([DEPTH]).writes([TO_SCREEN]),
+            PassNode::new(DEBUG_NORMALS).reads([NORMAL]).writes([TO_SCREEN]),
+        ]
+    }
+    pub fn debug_complexity(&mut self, encoder: &mut Encoder, resources: &GraphResources) -> Result<()> {
         puffin::profile_function!();
-        encoder
-            .dispatch_pipeline(
-                &mut self.debug_complexity,
-                (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1),
-                &[0; 0],
-                &[&self.complexity, to_screen],
-            )
-            .unwrap();
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.debug_complexity
+            .dispatch(encoder, workgroups, &[&resources.get(COMPLEXITY)?, &resources.get(TO_SCREEN)?])
     }
-    pub fn debug_depth(&mut self, encoder: &mut Encoder, to_screen: &ResourceHandle) {
+    pub fn debug_depth(&mut self, encoder: &mut Encoder, resources: &GraphResources) -> Result<()> {
         puffin::profile_function!();
-        encoder
-            .dispatch_pipeline(
-                &mut self.debug_depth,
-                (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1),
-                &[0; 0],
-                &[&self.depth, to_screen],
-            )
-            .unwrap();
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.debug_depth
+            .dispatch(encoder, workgroups, &[&resources.get(DEPTH)?, &resources.get(TO_SCREEN)?])
     }
-    pub fn debug_normals(&mut self, encoder: &mut Encoder, to_screen: &ResourceHandle) {
+    pub fn debug_normals(&mut self, encoder: &mut Encoder, resources: &GraphResources) -> Result<()> {
         puffin::profile_scope!("Debug normals");
-        encoder
-            .dispatch_pipeline(
-                &mut self.debug_normals,
-                (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1),
-                &[0; 0],
-                &[&self.normal, to_screen],
-            )
-            .unwrap();
+        let workgroups = (div_ceil(encoder.width(), 32), div_ceil(encoder.height(), 32), 1);
+        self.debug_normals
+            .dispatch(encoder, workgroups, &[&resources.get(NORMAL)?, &resources.get(TO_SCREEN)?])
     }
 }
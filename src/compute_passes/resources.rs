@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use cogrrs::wgpu::TextureFormat;
+use cogrrs::TextureRes::FullRes;
+use cogrrs::{CoGr, ResourceHandle};
+
+use super::ResourceHandles;
+
+// the image that is shown on screen
+pub const TO_SCREEN: &str = "to_screen";
+
+// for passes that draw on top of the image that is shown on screen
+pub struct ScreenTarget {
+    pub to_screen: ResourceHandle,
+}
+
+impl ResourceHandles for ScreenTarget {
+    fn names() -> Vec<&'static str> {
+        vec![TO_SCREEN]
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            to_screen: resources.get(TO_SCREEN)?,
+        })
+    }
+}
+
+// the resources passes share, by name. Every resource is created by the first pass that asks for it, the
+// passes that ask for it later get the same one
+#[derive(Default)]
+pub struct GraphResources {
+    handles: HashMap<&'static str, ResourceHandle>,
+}
+
+impl GraphResources {
+    pub fn texture(&mut self, gpu: &mut CoGr, name: &'static str, format: TextureFormat) -> ResourceHandle {
+        self.handles.entry(name).or_insert_with(|| gpu.texture(name, FullRes, format)).clone()
+    }
+
+    pub fn buffer(&mut self, gpu: &mut CoGr, name: &'static str, count: usize, size: usize) -> ResourceHandle {
+        self.handles.entry(name).or_insert_with(|| gpu.buffer(name, count as _, size)).clone()
+    }
+
+    pub fn get(&self, name: &str) -> Result<ResourceHandle> {
+        self.handles
+            .get(name)
+            .cloned()
+            .with_context(|| format!("resource {} was never created", name))
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use cogrrs::egui::{Color32, Ui};
+use cogrrs::{CoGr, Encoder, Pipeline, ResourceHandle};
+use log::{error, info, warn};
+
+use crate::file_watcher::FileWatcher;
+
+const SHADER_DIRECTORY: &str = "shaders";
+// how often the shader sources are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+// a pipeline that can be compiled again from its shader. When compiling fails the last working pipeline is
+// kept, the error is shown in the ui until the shader compiles again
+pub struct ShaderPipeline {
+    path: &'static str,
+    pipeline: Pipeline,
+    error: Option<String>,
+}
+
+impl ShaderPipeline {
+    pub fn new(gpu: &mut CoGr, path: &'static str) -> Result<Self> {
+        let pipeline = gpu.pipeline(path).with_context(|| format!("could not compile {}", path))?;
+        Ok(Self { path, pipeline, error: None })
+    }
+
+    // the resources are bound in the order the shader declares them
+    pub fn dispatch(&mut self, encoder: &mut Encoder, workgroups: (u32, u32, u32), resources: &[&ResourceHandle]) -> Result<()> {
+        encoder
+            .dispatch_pipeline(&mut self.pipeline, workgroups, &[0; 0], resources)
+            .with_context(|| format!("could not dispatch {}", self.path))
+    }
+
+    // only compiles again when the shader or a file it includes changed
+    pub fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges) {
+        if !changes.affects(self.path) {
+            return;
+        }
+        match gpu.pipeline(self.path) {
+            Ok(pipeline) => {
+                self.pipeline = pipeline;
+                if self.error.take().is_some() {
+                    info!("{} compiles again", self.path);
+                }
+            }
+            Err(compile_error) => {
+                let compile_error = format!("{:#}", compile_error);
+                error!("could not compile {}, keeping the previous version: {}", self.path, compile_error);
+                self.error = Some(compile_error);
+            }
+        }
+    }
+
+    pub fn draw_error(&self, ui: &mut Ui) {
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, format!("{}: {}", self.path, error));
+        }
+    }
+}
+
+// the files that have to be compiled again, every watched file that changed or includes a changed file
+#[derive(Default)]
+pub struct ShaderChanges {
+    affected: HashSet<PathBuf>,
+}
+
+impl ShaderChanges {
+    pub fn is_empty(&self) -> bool {
+        self.affected.is_empty()
+    }
+    pub fn affects(&self, shader: &str) -> bool {
+        self.affected.contains(Path::new(shader))
+    }
+}
+
+// Watches every shader in the shader directory and every file they include, wherever it is. Includes are
+// read again whenever a file changes, so files that start being included are picked up as well and files
+// that are not included anymore are no longer watched
+pub struct ShaderWatcher {
+    watcher: FileWatcher,
+    // the shaders found in the shader directory
+    shaders: Vec<PathBuf>,
+    // the files every watched file includes directly
+    includes: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        Self::new(Path::new(SHADER_DIRECTORY))
+    }
+}
+
+impl ShaderWatcher {
+    pub fn new(directory: &Path) -> Self {
+        let mut watcher = Self {
+            watcher: FileWatcher::new(WATCH_INTERVAL),
+            shaders: Vec::new(),
+            includes: HashMap::new(),
+        };
+        watcher.watch_directory(directory);
+        watcher
+    }
+
+    // the shaders that changed since the last call, their pipelines have to be rebuilt
+    pub fn changed(&mut self) -> ShaderChanges {
+        let changed = self.watcher.changed();
+        self.changes(&changed)
+    }
+
+    fn changes(&mut self, changed: &[PathBuf]) -> ShaderChanges {
+        if changed.is_empty() {
+            return ShaderChanges::default();
+        }
+        for path in changed {
+            if self.includes.contains_key(path) {
+                info!("{} changed", path.display());
+                self.read_includes(path);
+            }
+        }
+        self.unwatch_unused();
+        let affected: HashSet<PathBuf> = self.includes.keys().filter(|file| self.depends_on_any(file, changed)).cloned().collect();
+        let shaders = self.shaders.iter().filter(|shader| affected.contains(*shader)).count();
+        info!("rebuilding the pipelines of {} shaders", shaders);
+        ShaderChanges { affected }
+    }
+
+    fn watch_directory(&mut self, directory: &Path) {
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(read_error) => {
+                warn!("could not watch shaders in {}: {}", directory.display(), read_error);
+                return;
+            }
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                self.watch_directory(&path);
+            } else if path.extension().is_some_and(|extension| extension == "hlsl") {
+                self.shaders.push(path.clone());
+                self.watch_file(&path);
+            }
+        }
+    }
+
+    fn watch_file(&mut self, path: &Path) {
+        if self.watcher.is_watching(path) {
+            return;
+        }
+        self.watcher.watch(path);
+        self.includes.entry(path.to_path_buf()).or_default();
+        self.read_includes(path);
+    }
+
+    // a file that can not be read, for example because it is being replaced, keeps the includes it had
+    fn read_includes(&mut self, path: &Path) {
+        let Ok(source) = std::fs::read_to_string(path) else {
+            return;
+        };
+        // includes are relative to the file that includes them
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let included: Vec<PathBuf> = includes(&source).map(|include| directory.join(include)).collect();
+        self.includes.insert(path.to_path_buf(), included.clone());
+        for include in included {
+            self.watch_file(&include);
+        }
+    }
+
+    fn unwatch_unused(&mut self) {
+        let mut used = HashSet::new();
+        let mut stack = self.shaders.clone();
+        while let Some(path) = stack.pop() {
+            if let Some(included) = self.includes.get(&path) {
+                stack.extend(included.iter().filter(|include| !used.contains(*include)).cloned());
+            }
+            used.insert(path);
+        }
+        let watcher = &mut self.watcher;
+        self.includes.retain(|path, _| {
+            let keep = used.contains(path);
+            if !keep {
+                watcher.unwatch(path);
+            }
+            keep
+        });
+    }
+
+    // whether `file` is one of `changed` or includes one of them, directly or through other includes
+    fn depends_on_any(&self, file: &Path, changed: &[PathBuf]) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![file.to_path_buf()];
+        while let Some(path) = stack.pop() {
+            if changed.contains(&path) {
+                return true;
+            }
+            if let Some(included) = self.includes.get(&path) {
+                stack.extend(included.iter().filter(|include| !visited.contains(*include)).cloned());
+            }
+            visited.insert(path);
+        }
+        false
+    }
+}
+
+// the paths of the `#include "path"` directives in a shader
+fn includes(source: &str) -> impl Iterator<Item = &str> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("#include"))
+        .filter_map(|rest| rest.trim().strip_prefix('"')?.split('"').next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_shaders_including_a_changed_file_are_rebuilt() {
+        let directory = std::env::temp_dir().join(format!("svw_shaders_{}", std::process::id()));
+        let common = directory.join("common");
+        std::fs::create_dir_all(&common).unwrap();
+        let (a, b, x, y) = (
+            directory.join("a.hlsl"),
+            directory.join("b.hlsl"),
+            common.join("x.hlsli"),
+            common.join("y.hlsli"),
+        );
+        std::fs::write(&a, "#include \"common/x.hlsli\"\nvoid main() {}").unwrap();
+        std::fs::write(&b, "void main() {}").unwrap();
+        std::fs::write(&x, "#include \"y.hlsli\"").unwrap();
+        std::fs::write(&y, "#include \"x.hlsli\"").unwrap();
+        let path = |path: &PathBuf| path.to_str().unwrap().to_string();
+
+        // only .hlsl files are shaders, x and y are only watched as long as they are included
+        let mut watcher = ShaderWatcher::new(&directory);
+        let changes = watcher.changes(std::slice::from_ref(&y));
+        assert!(changes.affects(&path(&a)) && !changes.affects(&path(&b)));
+        assert!(watcher.changes(&[]).is_empty());
+
+        // a stops including x, so x and y are not watched anymore
+        std::fs::write(&a, "void main() {}").unwrap();
+        let changes = watcher.changes(std::slice::from_ref(&a));
+        assert!(changes.affects(&path(&a)) && !changes.affects(&path(&b)));
+        assert!(!watcher.watcher.is_watching(&x) && !watcher.watcher.is_watching(&y));
+        assert!(!watcher.changes(std::slice::from_ref(&y)).affects(&path(&a)));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+use anyhow::{Context, Result};
+use bytemuck::{Pod, Zeroable};
+use cogrrs::egui::Ui;
+use cogrrs::{div_ceil, puffin, CoGr, Encoder, ResourceHandle};
+use glam::{UVec2, UVec3};
+
+use crate::constants::{MAX_BRICK_UPLOADS_PER_FRAME, WORLD_SIZE_IN_BRICKS};
+use crate::world::brickmap::{Brick, Brickmap, BrickmapGpu, EMPTY_BRICK};
+
+use super::{ComputePass, GraphResources, ResourceHandles, ShaderChanges, ShaderPipeline};
+
+pub const BRICKMAP_HEADER: &str = "brickmap_header";
+pub const BRICKMAP_TOP: &str = "brickmap_top";
+pub const BRICK_POOL: &str = "brick_pool";
+
+// keeps the GPU copy of the brickmap up to date by uploading only the bricks and pointers that changed,
+// the changes are staged in small buffers and scattered to their place by a compute shader
+pub struct WorldUploader {
+    upload_info: ResourceHandle,
+    brick_upload_targets: ResourceHandle,
+    brick_upload_data: ResourceHandle,
+    top_upload_data: ResourceHandle,
+    upload_bricks: ShaderPipeline,
+    header_data: BrickmapGpu,
+    pending: PendingUploads,
+    uploaded_once: bool,
+}
+
+// changes that are not on the GPU yet, keyed by the slot so an element that changes again before it was
+// uploaded is only sent once
+#[derive(Default)]
+struct PendingUploads {
+    bricks: BTreeMap<u32, Brick>,
+    // the new pointer of a top level cell and whether the cell was already hidden while its brick is pending
+    top: BTreeMap<u32, (u32, bool)>,
+}
+
+struct UploadBatch {
+    targets: Vec<u32>,
+    bricks: Vec<Brick>,
+    top_updates: Vec<UVec2>,
+}
+
+impl PendingUploads {
+    fn is_empty(&self) -> bool {
+        self.bricks.is_empty() && self.top.is_empty()
+    }
+
+    // A pointer is published in the same batch as the brick it points at. Until then its cell is emptied, the
+    // slot it pointed at before may already hold another brick.
+    fn take_batch(&mut self, max_bricks: usize) -> UploadBatch {
+        let brick_count = self.bricks.len().min(max_bricks);
+        let (targets, bricks): (Vec<u32>, Vec<Brick>) = (0..brick_count).filter_map(|_| self.bricks.pop_first()).unzip();
+        let mut top_updates = Vec::new();
+        self.top.retain(|&index, (pointer, hidden)| {
+            if *pointer == EMPTY_BRICK || !self.bricks.contains_key(pointer) {
+                top_updates.push(UVec2::new(index, *pointer));
+                return false;
+            }
+            if !*hidden {
+                top_updates.push(UVec2::new(index, EMPTY_BRICK));
+                *hidden = true;
+            }
+            true
+        });
+        UploadBatch {
+            targets,
+            bricks,
+            top_updates,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable)]
+struct UploadInfo {
+    brick_count: u32,
+    top_count: u32,
+}
+
+pub struct WorldBuffers {
+    pub header: ResourceHandle,
+    pub top: ResourceHandle,
+    pub pool: ResourceHandle,
+}
+
+impl ResourceHandles for WorldBuffers {
+    fn names() -> Vec<&'static str> {
+        vec![BRICKMAP_HEADER, BRICKMAP_TOP, BRICK_POOL]
+    }
+    fn from_resources(resources: &GraphResources) -> Result<Self> {
+        Ok(Self {
+            header: resources.get(BRICKMAP_HEADER)?,
+            top: resources.get(BRICKMAP_TOP)?,
+            pool: resources.get(BRICK_POOL)?,
+        })
+    }
+}
+
+const TOP_CELLS: u32 = WORLD_SIZE_IN_BRICKS * WORLD_SIZE_IN_BRICKS * WORLD_SIZE_IN_BRICKS;
+
+impl ComputePass for WorldUploader {
+    const NAME: &'static str = "world upload";
+    type Inputs = ();
+    type Outputs = WorldBuffers;
+
+    fn new(gpu: &mut CoGr, resources: &mut GraphResources) -> Result<Self> {
+        resources.buffer(gpu, BRICKMAP_HEADER, 1, size_of::<BrickmapGpu>());
+        resources.buffer(gpu, BRICKMAP_TOP, TOP_CELLS as _, size_of::<u32>());
+        // every brick in the pool is referenced by one top level cell, so the pool never outgrows the top level
+        resources.buffer(gpu, BRICK_POOL, TOP_CELLS as _, size_of::<Brick>());
+        let upload_info = gpu.buffer("upload_info", 1, size_of::<UploadInfo>());
+        let brick_upload_targets = gpu.buffer("brick_upload_targets", MAX_BRICK_UPLOADS_PER_FRAME as _, size_of::<u32>());
+        let brick_upload_data = gpu.buffer("brick_upload_data", MAX_BRICK_UPLOADS_PER_FRAME as _, size_of::<Brick>());
+        let top_upload_data = gpu.buffer("top_upload_data", TOP_CELLS as _, size_of::<UVec2>());
+        let upload_bricks = ShaderPipeline::new(gpu, "shaders/upload_bricks.hlsl")?;
+        Ok(Self {
+            upload_info,
+            brick_upload_targets,
+            brick_upload_data,
+            top_upload_data,
+            upload_bricks,
+            header_data: BrickmapGpu::zeroed(),
+            pending: PendingUploads::default(),
+            uploaded_once: false,
+        })
+    }
+
+    fn rebuild(&mut self, gpu: &mut CoGr, changes: &ShaderChanges) {
+        self.upload_bricks.rebuild(gpu, changes);
+    }
+
+    fn dispatch(&mut self, encoder: &mut Encoder, _: &Self::Inputs, outputs: &Self::Outputs) -> Result<()> {
+        puffin::profile_scope!("Upload world");
+
+        if !self.pending.is_empty() {
+            let UploadBatch {
+                targets,
+                bricks,
+                top_updates,
+            } = self.pending.take_batch(MAX_BRICK_UPLOADS_PER_FRAME);
+            let info = UploadInfo {
+                brick_count: bricks.len() as u32,
+                top_count: top_updates.len() as u32,
+            };
+
+            encoder
+                .set_buffer_data(&outputs.header, [self.header_data])
+                .context("could not upload the brickmap header")?;
+            encoder
+                .set_buffer_data(&self.upload_info, [info])
+                .context("could not upload the upload info")?;
+            if !bricks.is_empty() {
+                encoder
+                    .set_buffer_data(&self.brick_upload_targets, targets)
+                    .context("could not upload the brick targets")?;
+                encoder
+                    .set_buffer_data(&self.brick_upload_data, bricks)
+                    .context("could not upload the bricks")?;
+            }
+            if !top_updates.is_empty() {
+                encoder
+                    .set_buffer_data(&self.top_upload_data, top_updates)
+                    .context("could not upload the top level changes")?;
+            }
+            self.upload_bricks.dispatch(
+                encoder,
+                (div_ceil(info.brick_count.max(info.top_count), 64), 1, 1),
+                &[
+                    &self.upload_info,
+                    &self.brick_upload_targets,
+                    &self.brick_upload_data,
+                    &self.top_upload_data,
+                    &outputs.top,
+                    &outputs.pool,
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    fn draw_ui(&mut self, ui: &mut Ui) {
+        ui.label(format!("pending brick uploads: {}", self.pending.bricks.len()));
+        self.upload_bricks.draw_error(ui);
+    }
+}
+
+impl WorldUploader {
+    // queues the changes of the brickmap since the last call, the first call uploads everything
+    pub fn schedule(&mut self, brickmap: &mut Brickmap) {
+        assert!(
+            brickmap.dimensions().cmple(UVec3::splat(WORLD_SIZE_IN_BRICKS)).all(),
+            "brickmap of {} bricks does not fit in the world buffers",
+            brickmap.dimensions()
+        );
+        if !self.uploaded_once {
+            brickmap.mark_all_dirty();
+            self.uploaded_once = true;
+        }
+        if !brickmap.has_pending_upload() {
+            return;
+        }
+        puffin::profile_function!();
+
+        self.header_data = brickmap.gpu_header();
+        let plan = brickmap.take_upload_plan();
+        for range in plan.pool_ranges {
+            self.pending.bricks.extend(range.map(|pointer| (pointer, *brickmap.pool().get(pointer))));
+        }
+        for range in plan.top_ranges {
+            self.pending
+                .top
+                .extend(range.map(|index| (index, (brickmap.top()[index as usize], false))));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brick(word: u32) -> Brick {
+        let mut brick = Brick::EMPTY;
+        brick.occupancy[0] = word;
+        brick
+    }
+
+    #[test]
+    fn pointers_are_published_with_their_bricks() {
+        let mut pending = PendingUploads::default();
+        pending.bricks.extend([(0, brick(1)), (1, brick(2)), (2, brick(3))]);
+        // cell 7 lost its brick, cells 3 and 4 point at new bricks
+        pending.top.extend([(3, (0, false)), (4, (2, false)), (7, (EMPTY_BRICK, false))]);
+
+        let batch = pending.take_batch(2);
+        assert_eq!(batch.targets, vec![0, 1]);
+        assert_eq!(batch.bricks, vec![brick(1), brick(2)]);
+        // the brick of cell 4 is still pending, so the cell stays empty until it arrives
+        assert_eq!(
+            batch.top_updates,
+            vec![UVec2::new(3, 0), UVec2::new(4, EMPTY_BRICK), UVec2::new(7, EMPTY_BRICK)]
+        );
+
+        let batch = pending.take_batch(2);
+        assert_eq!(batch.targets, vec![2]);
+        assert_eq!(batch.top_updates, vec![UVec2::new(4, 2)]);
+        assert!(pending.is_empty());
+    }
+}
@@ -1 +1,13 @@
+use glam::Vec3;
+
+use crate::world::brickmap::BRICK_SIZE;
+
 pub const TICKS_PER_SECOND: f32 = 10f32;
+
+pub const DEFAULT_SCENE: &str = "scenes/hollow_box.sdf";
+// generated terrain is saved to and loaded from this chunk archive
+pub const TERRAIN_ARCHIVE: &str = "terrain.svwa";
+// the world is a cube of this many bricks along every axis
+pub const WORLD_SIZE_IN_BRICKS: u32 = 16;
+pub const WORLD_CENTER: Vec3 = Vec3::splat((WORLD_SIZE_IN_BRICKS * BRICK_SIZE / 2) as f32);
+pub const MAX_BRICK_UPLOADS_PER_FRAME: usize = 1024;
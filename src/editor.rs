@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use cogrrs::egui::{Button, ComboBox, Slider, Ui};
+use cogrrs::winit::event::VirtualKeyCode;
+use cogrrs::Input;
+use glam::{IVec3, Vec3};
+
+use crate::key_mapping::{BRUSH_GROW, BRUSH_NEXT_SHAPE, BRUSH_SHRINK, EDIT_PAINT, EDIT_PLACE, EDIT_REMOVE, REDO, UNDO};
+use crate::world::brickmap::Brickmap;
+use crate::world::edit::{Brush, DirtyRegion, EditHistory, EditOperation};
+use crate::world::material::{Material, MATERIAL_NAMES, STONE};
+use crate::world::picking::{pick, PickResult};
+use crate::world::VoxelStorage;
+
+pub const MAX_BRUSH_SIZE: i32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    Place,
+    Remove,
+    Paint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushShape {
+    Sphere,
+    Box,
+    Cylinder,
+}
+
+impl BrushShape {
+    fn next(self) -> Self {
+        match self {
+            BrushShape::Sphere => BrushShape::Box,
+            BrushShape::Box => BrushShape::Cylinder,
+            BrushShape::Cylinder => BrushShape::Sphere,
+        }
+    }
+}
+
+// state of the in-viewport editing tools, the voxel under the cursor is read back from the G-buffer of the
+// primary ray caster, the same data the editor preview draws the brush with
+pub struct VoxelEditor {
+    pub enabled: bool,
+    pub tool: EditorTool,
+    pub shape: BrushShape,
+    // radius or half extent in voxels, 0 edits a single voxel
+    pub size: i32,
+    pub material: Material,
+    history: EditHistory,
+    hovered: Option<PickResult>,
+    held_keys: HashSet<VirtualKeyCode>,
+}
+
+impl Default for VoxelEditor {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            tool: EditorTool::Place,
+            shape: BrushShape::Sphere,
+            size: 2,
+            material: STONE,
+            history: EditHistory::default(),
+            hovered: None,
+            held_keys: HashSet::new(),
+        }
+    }
+}
+
+impl VoxelEditor {
+    pub fn brush(&self) -> Brush {
+        match self.shape {
+            BrushShape::Sphere => Brush::Sphere { radius: self.size as f32 },
+            BrushShape::Box => Brush::Box {
+                half_extents: IVec3::splat(self.size),
+            },
+            BrushShape::Cylinder => Brush::Cylinder {
+                radius: self.size as f32,
+                half_height: self.size,
+            },
+        }
+    }
+
+    pub fn operation(&self) -> EditOperation {
+        match self.tool {
+            EditorTool::Place => EditOperation::Place(self.material),
+            EditorTool::Remove => EditOperation::Remove,
+            EditorTool::Paint => EditOperation::Paint(self.material),
+        }
+    }
+
+    // placing builds on the empty voxel in front of the hit face, the other tools change the hit voxel
+    pub fn target(&self, hit: &PickResult) -> IVec3 {
+        match self.tool {
+            EditorTool::Place => hit.adjacent(),
+            EditorTool::Remove | EditorTool::Paint => hit.voxel,
+        }
+    }
+
+    pub fn hovered(&self) -> Option<&PickResult> {
+        self.hovered.as_ref()
+    }
+
+    // `hit` is what the G-buffer showed under the cursor. Rays that hit nothing still end in a voxel far away and
+    // the read back is a frame old, so only voxels that are solid in the world right now are accepted
+    pub fn hover(&mut self, world: &Brickmap, hit: Option<PickResult>) {
+        // a ray starting inside of a solid voxel has no face to build on
+        self.hovered = hit.filter(|hit| hit.normal != IVec3::ZERO && world.is_solid(hit.voxel));
+    }
+
+    // traces the cursor ray on the CPU, for when the G-buffer can not be read back
+    pub fn hover_ray(&mut self, world: &Brickmap, origin: Vec3, direction: Vec3) {
+        let bounds = (IVec3::ZERO, world.voxel_dimensions().as_ivec3());
+        self.hover(world, pick(world, bounds, origin, direction));
+    }
+
+    pub fn clear_hover(&mut self) {
+        self.hovered = None;
+    }
+
+    // edits the world at the hovered voxel
+    pub fn apply(&mut self, world: &mut Brickmap) -> Result<Option<DirtyRegion>> {
+        let Some(hit) = self.hovered.filter(|_| self.enabled) else {
+            return Ok(None);
+        };
+        self.history.brush(world, self.target(&hit), self.brush(), self.operation())
+    }
+
+    pub fn undo(&mut self, world: &mut Brickmap) -> Option<DirtyRegion> {
+        self.history.undo(world)
+    }
+
+    pub fn redo(&mut self, world: &mut Brickmap) -> Option<DirtyRegion> {
+        self.history.redo(world)
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    // Input only reports held keys, so presses are detected by remembering what was held last frame
+    fn just_pressed(&mut self, input: &Input, key: VirtualKeyCode) -> bool {
+        if input.key_pressed(key) {
+            self.held_keys.insert(key)
+        } else {
+            self.held_keys.remove(&key);
+            false
+        }
+    }
+
+    pub fn handle_hotkeys(&mut self, input: &Input, world: &mut Brickmap) {
+        if self.just_pressed(input, EDIT_PLACE) {
+            self.tool = EditorTool::Place;
+        }
+        if self.just_pressed(input, EDIT_REMOVE) {
+            self.tool = EditorTool::Remove;
+        }
+        if self.just_pressed(input, EDIT_PAINT) && world.stores_materials() {
+            self.tool = EditorTool::Paint;
+        }
+        if self.just_pressed(input, BRUSH_NEXT_SHAPE) {
+            self.shape = self.shape.next();
+        }
+        if self.just_pressed(input, BRUSH_GROW) {
+            self.size = (self.size + 1).min(MAX_BRUSH_SIZE);
+        }
+        if self.just_pressed(input, BRUSH_SHRINK) {
+            self.size = (self.size - 1).max(0);
+        }
+        if self.just_pressed(input, UNDO) {
+            self.undo(world);
+        }
+        if self.just_pressed(input, REDO) {
+            self.redo(world);
+        }
+    }
+
+    pub fn draw_ui(&mut self, ui: &mut Ui, world: &mut Brickmap) {
+        // worlds that only store whether a voxel is solid can not be painted and ignore the material
+        let materials = world.stores_materials();
+        if !materials && self.tool == EditorTool::Paint {
+            self.tool = EditorTool::Place;
+        }
+        ui.checkbox(&mut self.enabled, "Edit on click");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tool, EditorTool::Place, "Place");
+            ui.selectable_value(&mut self.tool, EditorTool::Remove, "Remove");
+            if materials {
+                ui.selectable_value(&mut self.tool, EditorTool::Paint, "Paint");
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.shape, BrushShape::Sphere, "Sphere");
+            ui.selectable_value(&mut self.shape, BrushShape::Box, "Box");
+            ui.selectable_value(&mut self.shape, BrushShape::Cylinder, "Cylinder");
+        });
+        ui.add(Slider::new(&mut self.size, 0..=MAX_BRUSH_SIZE).text("Brush size"));
+        if materials {
+            ComboBox::from_label("Material")
+                .selected_text(MATERIAL_NAMES[self.material as usize])
+                .show_ui(ui, |ui| {
+                    for (material, name) in MATERIAL_NAMES.iter().enumerate().skip(1) {
+                        ui.selectable_value(&mut self.material, material as Material, *name);
+                    }
+                });
+        }
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.history.can_undo(), Button::new("Undo")).clicked() {
+                self.undo(world);
+            }
+            if ui.add_enabled(self.history.can_redo(), Button::new("Redo")).clicked() {
+                self.redo(world);
+            }
+        });
+        match &self.hovered {
+            Some(hit) => ui.label(format!("hovered voxel: {}", hit.voxel)),
+            None => ui.label("hovered voxel: none"),
+        };
+    }
+}
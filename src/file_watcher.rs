@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Notices changes to files by polling their modification time, which works the same on every platform and
+// file system. A change is only reported once the file stopped changing for a poll, so a file that is still
+// being written is not picked up halfway. Files that can not be read, for example because they are being
+// replaced, count as missing and are reported again once they are back
+pub struct FileWatcher {
+    files: HashMap<PathBuf, WatchedFile>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct WatchedFile {
+    // modification time the last reported change was based on
+    reported: Option<SystemTime>,
+    // modification time seen in the last poll
+    seen: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl FileWatcher {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            files: HashMap::new(),
+            interval,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let time = modified(path);
+        self.files.entry(path.to_path_buf()).or_insert(WatchedFile { reported: time, seen: time });
+    }
+
+    pub fn unwatch(&mut self, path: impl AsRef<Path>) {
+        self.files.remove(path.as_ref());
+    }
+
+    pub fn is_watching(&self, path: impl AsRef<Path>) -> bool {
+        self.files.contains_key(path.as_ref())
+    }
+
+    // files that changed since they were last reported, polls at most once per interval
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.interval {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+        self.poll()
+    }
+
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, file) in self.files.iter_mut() {
+            let time = modified(path);
+            let settled = time == file.seen;
+            file.seen = time;
+            if settled && time.is_some() && time != file.reported {
+                file.reported = time;
+                changed.push(path.clone());
+            }
+        }
+        changed.sort();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn set_modified(path: &Path, seconds: u64) {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn changes_are_reported_once_they_settled() {
+        let path = std::env::temp_dir().join(format!("svw_file_watcher_{}.txt", std::process::id()));
+        std::fs::write(&path, "first").unwrap();
+        set_modified(&path, 1000);
+        let mut watcher = FileWatcher::new(Duration::ZERO);
+        watcher.watch(&path);
+        assert!(watcher.poll().is_empty());
+
+        // still being written during the first poll that sees it
+        set_modified(&path, 2000);
+        assert!(watcher.poll().is_empty());
+        assert_eq!(watcher.poll(), vec![path.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        // a file that is replaced is reported once it is back and settled
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.poll().is_empty());
+        std::fs::write(&path, "second").unwrap();
+        set_modified(&path, 3000);
+        assert!(watcher.poll().is_empty());
+        assert_eq!(watcher.changed(), vec![path.clone()]);
+
+        watcher.unwatch(&path);
+        assert!(!watcher.is_watching(&path));
+        set_modified(&path, 4000);
+        assert!(watcher.poll().is_empty());
+        assert!(watcher.poll().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use anyhow::{ensure, Context, Result};
+use glam::IVec3;
+use log::error;
+
+use super::{decode_versioned, encode_versioned, Versioned};
+
+// An archive stores one record per chunk position so single chunks can be read and written without touching
+// the rest of the file. Every record is a container written by `encode_versioned`, so it is compressed and
+// checksummed on its own:
+//
+//   header   magic "SVWA", version u16, reserved u16, index offset u64, index entry count u32
+//   records  at arbitrary offsets, a record may be followed by unused space it can grow into
+//   index    entries of x, y, z i32, offset u64, length u32, capacity u32
+//
+// Changes only become part of the archive when `flush` writes a new index. New records and new indices are
+// always appended after everything that is already in the file and the header is updated last, so a write
+// that is interrupted leaves the previous index and the records it points at intact. Only records written
+// since the last flush are overwritten in place. Replaced records and indices leave unused space behind,
+// compacting reclaims it
+const MAGIC: [u8; 4] = *b"SVWA";
+const VERSION: u16 = 1;
+const HEADER_SIZE: u64 = 20;
+const INDEX_ENTRY_SIZE: usize = 28;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    offset: u64,
+    length: u32,
+    capacity: u32,
+}
+
+pub struct ChunkArchive {
+    filename: String,
+    file: File,
+    index: HashMap<IVec3, IndexEntry>,
+    // end of the file, new records and indices are appended here
+    file_end: u64,
+    // end of the index the header points at, everything before it is referenced by the file
+    flushed_end: u64,
+    // size of the index the header points at
+    index_bytes: u64,
+    // the index in memory differs from the one in the file
+    unflushed: bool,
+}
+
+impl ChunkArchive {
+    pub fn create(filename: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(filename)
+            .with_context(|| format!("could not create archive: {}", filename))?;
+        let mut archive = Self {
+            filename: filename.to_string(),
+            file,
+            index: HashMap::new(),
+            file_end: HEADER_SIZE,
+            flushed_end: HEADER_SIZE,
+            index_bytes: 0,
+            unflushed: true,
+        };
+        archive.flush()?;
+        Ok(archive)
+    }
+
+    pub fn open(filename: &str) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(filename)
+            .with_context(|| format!("could not open archive: {}", filename))?;
+        let (index, index_offset) = read_index(&mut file).with_context(|| format!("could not read archive index: {}", filename))?;
+        let index_bytes = (index.len() * INDEX_ENTRY_SIZE) as u64;
+        let flushed_end = index_offset + index_bytes;
+        // anything after the index was written after the last flush and is not referenced
+        let file_end = file.metadata()?.len();
+        Ok(Self {
+            filename: filename.to_string(),
+            file,
+            index,
+            file_end: file_end.max(flushed_end),
+            flushed_end,
+            index_bytes,
+            unflushed: false,
+        })
+    }
+
+    pub fn open_or_create(filename: &str) -> Result<Self> {
+        if std::path::Path::new(filename).exists() {
+            Self::open(filename)
+        } else {
+            Self::create(filename)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+    pub fn contains(&self, position: IVec3) -> bool {
+        self.index.contains_key(&position)
+    }
+    // positions of all stored chunks, sorted z major
+    pub fn positions(&self) -> Vec<IVec3> {
+        let mut positions: Vec<IVec3> = self.index.keys().copied().collect();
+        positions.sort_by_key(|position| (position.z, position.y, position.x));
+        positions
+    }
+    // bytes that are not used by any record or the index and would be reclaimed by compacting
+    pub fn wasted_bytes(&self) -> u64 {
+        let used: u64 = self.index.values().map(|entry| entry.length as u64).sum();
+        self.file_end - HEADER_SIZE - used - self.index_bytes
+    }
+
+    pub fn read<T: Versioned>(&mut self, position: IVec3) -> Result<Option<T>> {
+        let Some(record) = self.read_record(position)? else {
+            return Ok(None);
+        };
+        let data = decode_versioned(&record).with_context(|| format!("could not decode chunk {} in {}", position, self.filename))?;
+        Ok(Some(data))
+    }
+
+    // appends the record, a record that was written since the last flush is overwritten when it still fits.
+    // Reopening the archive only finds the record once it was flushed
+    pub fn write<T: Versioned>(&mut self, position: IVec3, data: &T) -> Result<()> {
+        let record = encode_versioned(data)?;
+        let length = record.len() as u32;
+        let entry = match self.index.get(&position) {
+            Some(entry) if entry.offset >= self.flushed_end && entry.capacity >= length => IndexEntry { length, ..*entry },
+            _ => {
+                let entry = IndexEntry {
+                    offset: self.file_end,
+                    length,
+                    capacity: length,
+                };
+                self.file_end += length as u64;
+                entry
+            }
+        };
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        self.file
+            .write_all(&record)
+            .with_context(|| format!("could not write chunk {} to {}", position, self.filename))?;
+        self.index.insert(position, entry);
+        self.unflushed = true;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, position: IVec3) -> bool {
+        let removed = self.index.remove(&position).is_some();
+        self.unflushed |= removed;
+        removed
+    }
+
+    // rewrites the archive without unused space, returns the number of bytes that were reclaimed
+    pub fn compact(&mut self) -> Result<u64> {
+        // the original stays usable when compacting fails, so it has to be complete on its own
+        self.flush()?;
+        let wasted = self.wasted_bytes();
+        let compacted_filename = format!("{}.compacting", self.filename);
+        let mut compacted = Self::create(&compacted_filename)?;
+        for position in self.positions() {
+            let record = self.read_record(position)?.unwrap();
+            let entry = IndexEntry {
+                offset: compacted.file_end,
+                length: record.len() as u32,
+                capacity: record.len() as u32,
+            };
+            compacted.file.seek(SeekFrom::Start(entry.offset))?;
+            compacted.file.write_all(&record)?;
+            compacted.file_end += record.len() as u64;
+            compacted.index.insert(position, entry);
+            compacted.unflushed = true;
+        }
+        compacted.flush()?;
+        drop(compacted);
+        // Windows can not replace a file that is still open, so the old handle is closed first
+        self.file = File::open(&compacted_filename).with_context(|| format!("could not open {}", compacted_filename))?;
+        let renamed = std::fs::rename(&compacted_filename, &self.filename);
+        *self = Self::open(&self.filename)?;
+        renamed.with_context(|| format!("could not replace {} with its compacted version", self.filename))?;
+        Ok(wasted)
+    }
+
+    fn read_record(&mut self, position: IVec3) -> Result<Option<Vec<u8>>> {
+        let Some(entry) = self.index.get(&position).copied() else {
+            return Ok(None);
+        };
+        let mut record = vec![0; entry.length as usize];
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        self.file
+            .read_exact(&mut record)
+            .with_context(|| format!("could not read chunk {} from {}", position, self.filename))?;
+        Ok(Some(record))
+    }
+
+    // writes the index after everything else in the file and then points the header at it
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.unflushed {
+            return Ok(());
+        }
+        let positions = self.positions();
+        let mut bytes = Vec::with_capacity(positions.len() * INDEX_ENTRY_SIZE);
+        for position in positions {
+            let entry = self.index[&position];
+            for component in position.to_array() {
+                bytes.extend_from_slice(&component.to_le_bytes());
+            }
+            bytes.extend_from_slice(&entry.offset.to_le_bytes());
+            bytes.extend_from_slice(&entry.length.to_le_bytes());
+            bytes.extend_from_slice(&entry.capacity.to_le_bytes());
+        }
+        let index_offset = self.file_end;
+        self.file.seek(SeekFrom::Start(index_offset))?;
+        self.file
+            .write_all(&bytes)
+            .with_context(|| format!("could not write archive index: {}", self.filename))?;
+        // the records and the index have to be on disk before the header points at them
+        self.file.sync_data()?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&index_offset.to_le_bytes());
+        header.extend_from_slice(&(self.index.len() as u32).to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file
+            .write_all(&header)
+            .with_context(|| format!("could not write archive header: {}", self.filename))?;
+        self.file.sync_data()?;
+
+        self.file_end = index_offset + bytes.len() as u64;
+        self.flushed_end = self.file_end;
+        self.index_bytes = bytes.len() as u64;
+        self.unflushed = false;
+        Ok(())
+    }
+}
+
+// like a buffered writer, an archive that is dropped without flushing flushes and can only log errors
+impl Drop for ChunkArchive {
+    fn drop(&mut self) {
+        if let Err(error) = self.flush() {
+            error!("could not flush {}: {:#}", self.filename, error);
+        }
+    }
+}
+
+fn read_index(file: &mut File) -> Result<(HashMap<IVec3, IndexEntry>, u64)> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header).context("header is truncated")?;
+    ensure!(header[0..4] == MAGIC, "not a chunk archive");
+    let version = u16::from_le_bytes(header[4..6].try_into().unwrap());
+    ensure!(
+        version <= VERSION,
+        "archive version {} is newer than the supported version {}",
+        version,
+        VERSION
+    );
+    let index_offset = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let entry_count = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+    // checked before allocating, a damaged header could ask for gigabytes
+    let file_len = file.metadata()?.len();
+    let index_end = (entry_count as u64)
+        .checked_mul(INDEX_ENTRY_SIZE as u64)
+        .and_then(|index_bytes| index_offset.checked_add(index_bytes));
+    ensure!(
+        index_offset >= HEADER_SIZE && index_end.is_some_and(|index_end| index_end <= file_len),
+        "index of {} entries at {} does not fit in the file of {} bytes",
+        entry_count,
+        index_offset,
+        file_len
+    );
+
+    let mut bytes = vec![0u8; entry_count * INDEX_ENTRY_SIZE];
+    file.seek(SeekFrom::Start(index_offset))?;
+    file.read_exact(&mut bytes).context("index is truncated")?;
+    let mut index = HashMap::with_capacity(entry_count);
+    for entry in bytes.chunks_exact(INDEX_ENTRY_SIZE) {
+        let i32_at = |at: usize| i32::from_le_bytes(entry[at..at + 4].try_into().unwrap());
+        let u32_at = |at: usize| u32::from_le_bytes(entry[at..at + 4].try_into().unwrap());
+        let position = IVec3::new(i32_at(0), i32_at(4), i32_at(8));
+        let entry = IndexEntry {
+            offset: u64::from_le_bytes(entry[12..20].try_into().unwrap()),
+            length: u32_at(20),
+            capacity: u32_at(24),
+        };
+        let end = entry.offset.checked_add(entry.capacity as u64);
+        ensure!(
+            entry.length <= entry.capacity && entry.offset >= HEADER_SIZE && end.is_some_and(|end| end <= index_offset),
+            "index entry of chunk {} points outside of the data",
+            position
+        );
+        ensure!(index.insert(position, entry).is_none(), "chunk {} is in the index twice", position);
+    }
+    Ok((index, index_offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::Chunk;
+    use glam::UVec3;
+
+    // an archive file in the temp directory that is removed again when the test is done
+    struct TempArchive(String);
+
+    impl TempArchive {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("svw_archive_{}_{}.svwa", name, std::process::id()));
+            Self(path.to_string_lossy().into_owned())
+        }
+    }
+
+    impl Drop for TempArchive {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn chunk(position: IVec3, solid: u32) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        for i in 0..solid {
+            chunk.set(UVec3::new(i % 32, i / 32 % 32, i / 1024), 1 + (i % 7) as u8);
+        }
+        chunk
+    }
+
+    fn read(archive: &mut ChunkArchive, position: IVec3) -> Chunk {
+        archive.read(position).unwrap().unwrap()
+    }
+
+    #[test]
+    fn flushed_chunks_survive_reopening() {
+        let file = TempArchive::new("reopen");
+        let chunks = [chunk(IVec3::ZERO, 100), chunk(IVec3::new(-1, 2, 3), 5000)];
+        let mut archive = ChunkArchive::create(&file.0).unwrap();
+        for chunk in &chunks {
+            archive.write(chunk.position(), chunk).unwrap();
+        }
+        archive.flush().unwrap();
+        drop(archive);
+
+        let mut archive = ChunkArchive::open(&file.0).unwrap();
+        assert_eq!(archive.positions(), vec![IVec3::ZERO, IVec3::new(-1, 2, 3)]);
+        for chunk in &chunks {
+            assert_eq!(read(&mut archive, chunk.position()).materials(), chunk.materials());
+        }
+        assert!(archive.read::<Chunk>(IVec3::ONE).unwrap().is_none());
+    }
+
+    #[test]
+    fn unflushed_writes_leave_the_old_archive_intact() {
+        let file = TempArchive::new("crash");
+        let old = chunk(IVec3::ZERO, 100);
+        let mut archive = ChunkArchive::create(&file.0).unwrap();
+        archive.write(old.position(), &old).unwrap();
+        archive.flush().unwrap();
+
+        // a smaller record would fit into the old one, it still has to be appended
+        archive.write(IVec3::ZERO, &chunk(IVec3::ZERO, 10)).unwrap();
+        archive.write(IVec3::X, &chunk(IVec3::X, 3000)).unwrap();
+        assert!(archive.remove(IVec3::ZERO));
+        // crashing skips the flush on drop
+        std::mem::forget(archive);
+
+        let mut archive = ChunkArchive::open(&file.0).unwrap();
+        assert_eq!(archive.positions(), vec![IVec3::ZERO]);
+        assert_eq!(read(&mut archive, IVec3::ZERO).materials(), old.materials());
+    }
+
+    #[test]
+    fn dropping_flushes() {
+        let file = TempArchive::new("drop");
+        let mut archive = ChunkArchive::create(&file.0).unwrap();
+        archive.write(IVec3::Y, &chunk(IVec3::Y, 64)).unwrap();
+        drop(archive);
+
+        let archive = ChunkArchive::open(&file.0).unwrap();
+        assert!(archive.contains(IVec3::Y));
+    }
+
+    #[test]
+    fn remove_and_compact() {
+        let file = TempArchive::new("compact");
+        let mut archive = ChunkArchive::open_or_create(&file.0).unwrap();
+        for x in 0..4 {
+            archive.write(IVec3::X * x, &chunk(IVec3::X * x, 200)).unwrap();
+        }
+        archive.flush().unwrap();
+        assert_eq!(archive.wasted_bytes(), 0);
+
+        assert!(archive.remove(IVec3::X));
+        assert!(!archive.remove(IVec3::X));
+        let rewritten = chunk(IVec3::ZERO, 4000);
+        archive.write(IVec3::ZERO, &rewritten).unwrap();
+        archive.flush().unwrap();
+        let wasted = archive.wasted_bytes();
+        assert!(wasted > 0);
+
+        let length = std::fs::metadata(&file.0).unwrap().len();
+        assert_eq!(archive.compact().unwrap(), wasted);
+        assert_eq!(archive.wasted_bytes(), 0);
+        assert_eq!(std::fs::metadata(&file.0).unwrap().len(), length - wasted);
+        drop(archive);
+
+        let mut archive = ChunkArchive::open_or_create(&file.0).unwrap();
+        assert_eq!(archive.positions(), vec![IVec3::ZERO, IVec3::X * 2, IVec3::X * 3]);
+        assert_eq!(read(&mut archive, IVec3::ZERO).materials(), rewritten.materials());
+        assert_eq!(read(&mut archive, IVec3::X * 3).materials(), chunk(IVec3::X * 3, 200).materials());
+    }
+
+    // overwrites bytes of a flushed archive and returns the error opening it gives
+    fn open_damaged(file: &TempArchive, at: u64, bytes: &[u8]) -> String {
+        let mut damaged = OpenOptions::new().write(true).open(&file.0).unwrap();
+        damaged.seek(SeekFrom::Start(at)).unwrap();
+        damaged.write_all(bytes).unwrap();
+        drop(damaged);
+        format!("{:#}", ChunkArchive::open(&file.0).err().unwrap())
+    }
+
+    #[test]
+    fn damaged_indices_are_rejected() {
+        let file = TempArchive::new("damaged");
+        let mut archive = ChunkArchive::create(&file.0).unwrap();
+        archive.write(IVec3::ZERO, &chunk(IVec3::ZERO, 50)).unwrap();
+        archive.write(IVec3::Y, &chunk(IVec3::Y, 50)).unwrap();
+        drop(archive);
+        let original = std::fs::read(&file.0).unwrap();
+        let index_offset = u64::from_le_bytes(original[8..16].try_into().unwrap());
+        let restore = || std::fs::write(&file.0, &original).unwrap();
+
+        // huge entry counts and offsets fail before anything is allocated
+        assert!(open_damaged(&file, 16, &u32::MAX.to_le_bytes()).contains("does not fit in the file"));
+        restore();
+        assert!(open_damaged(&file, 8, &u64::MAX.to_le_bytes()).contains("does not fit in the file"));
+        restore();
+        assert!(open_damaged(&file, 8, &4u64.to_le_bytes()).contains("does not fit in the file"));
+        restore();
+        // the offset of the first entry, overflowing when its capacity is added
+        assert!(open_damaged(&file, index_offset + 12, &(u64::MAX - 4).to_le_bytes()).contains("points outside of the data"));
+        restore();
+        // the second entry gets the position of the first
+        assert!(open_damaged(&file, index_offset + 28 + 4, &0i32.to_le_bytes()).contains("in the index twice"));
+        restore();
+        assert_eq!(ChunkArchive::open(&file.0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn compacting_keeps_unflushed_changes() {
+        let file = TempArchive::new("compact_unflushed");
+        let mut archive = ChunkArchive::create(&file.0).unwrap();
+        archive.write(IVec3::ZERO, &chunk(IVec3::ZERO, 300)).unwrap();
+        archive.flush().unwrap();
+        archive.write(IVec3::Z, &chunk(IVec3::Z, 20)).unwrap();
+        assert!(archive.remove(IVec3::ZERO));
+        archive.compact().unwrap();
+        assert_eq!(archive.wasted_bytes(), 0);
+        std::mem::forget(archive);
+
+        let mut archive = ChunkArchive::open(&file.0).unwrap();
+        assert_eq!(archive.positions(), vec![IVec3::Z]);
+        assert_eq!(read(&mut archive, IVec3::Z).materials(), chunk(IVec3::Z, 20).materials());
+        assert!(!std::path::Path::new(&format!("{}.compacting", file.0)).exists());
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{bail, ensure, Context, Result};
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_cbor::Value;
+
+use super::{read_file, write_to_file};
+
+// Every file starts with a header so it can be identified before its content is decoded:
+//
+//   magic              4 bytes  "SVWF"
+//   container version  u16
+//   type tag length    u8, followed by the tag as utf8
+//   type version       u32
+//   payload length     u64
+//   payload checksum   u32, crc32 of the payload
+//   payload            lz4 compressed cbor, with the decompressed size prepended
+//
+// all integers are little endian
+pub const MAGIC: [u8; 4] = *b"SVWF";
+pub const CONTAINER_VERSION: u16 = 1;
+
+// a type that is stored in a container, the version has to be increased whenever its serialized form changes
+pub trait Versioned: Serialize + DeserializeOwned {
+    const TYPE_TAG: &'static str;
+    const VERSION: u32;
+
+    // converts the decoded content of version `version` into version `version + 1`
+    fn migrate(version: u32, _value: Value) -> Result<Value> {
+        bail!("no migration from version {} of {}", version, Self::TYPE_TAG)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerHeader {
+    pub container_version: u16,
+    pub type_tag: String,
+    pub type_version: u32,
+    pub payload_length: u64,
+    pub checksum: u32,
+}
+
+pub fn encode_versioned<T: Versioned>(data: &T) -> Result<Vec<u8>> {
+    let payload = serde_cbor::to_vec(data).with_context(|| format!("could not encode {} to cbor", T::TYPE_TAG))?;
+    let payload = compress_prepend_size(&payload);
+    let header = ContainerHeader {
+        container_version: CONTAINER_VERSION,
+        type_tag: T::TYPE_TAG.to_string(),
+        type_version: T::VERSION,
+        payload_length: payload.len() as u64,
+        checksum: crc32(&payload),
+    };
+    let mut bytes = header.to_bytes()?;
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+pub fn decode_versioned<T: Versioned>(bytes: &[u8]) -> Result<T> {
+    let (header, payload) = ContainerHeader::parse(bytes)?;
+    ensure!(
+        header.type_tag == T::TYPE_TAG,
+        "contains a {} but a {} was expected",
+        header.type_tag,
+        T::TYPE_TAG
+    );
+    ensure!(
+        header.type_version <= T::VERSION,
+        "{} version {} was written by a newer build, this build supports up to version {}",
+        T::TYPE_TAG,
+        header.type_version,
+        T::VERSION
+    );
+    ensure!(
+        payload.len() as u64 >= header.payload_length,
+        "file is truncated, the payload has {} of {} bytes",
+        payload.len(),
+        header.payload_length
+    );
+    ensure!(
+        payload.len() as u64 == header.payload_length,
+        "file has {} unexpected bytes after the payload",
+        payload.len() as u64 - header.payload_length
+    );
+    ensure!(crc32(payload) == header.checksum, "checksum mismatch, the file is corrupted");
+    let payload = decompress_size_prepended(payload).context("could not decompress payload")?;
+
+    if header.type_version == T::VERSION {
+        return serde_cbor::from_slice(&payload).with_context(|| format!("could not decode {} from cbor", T::TYPE_TAG));
+    }
+    let mut value: Value = serde_cbor::from_slice(&payload).context("could not decode payload from cbor")?;
+    for version in header.type_version..T::VERSION {
+        value = T::migrate(version, value).with_context(|| format!("could not migrate {} from version {}", T::TYPE_TAG, version))?;
+    }
+    serde_cbor::value::from_value(value).with_context(|| format!("could not decode migrated {}", T::TYPE_TAG))
+}
+
+pub fn write_versioned_file<T: Versioned>(data: &T, filename: &str) -> Result<()> {
+    let bytes = encode_versioned(data)?;
+    write_to_file(&bytes, filename)
+}
+
+pub fn read_versioned_file<T: Versioned>(filename: &str) -> Result<T> {
+    let bytes = read_file(filename)?;
+    decode_versioned(&bytes).with_context(|| format!("could not load {}", filename))
+}
+
+// reads only the header, for tools that want to know what a file contains
+pub fn read_container_header(filename: &str) -> Result<ContainerHeader> {
+    let bytes = read_file(filename)?;
+    let (header, _) = ContainerHeader::parse(&bytes).with_context(|| format!("could not load {}", filename))?;
+    Ok(header)
+}
+
+impl ContainerHeader {
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        let tag_length = u8::try_from(self.type_tag.len()).with_context(|| format!("type tag {} is longer than {} bytes", self.type_tag, u8::MAX))?;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&self.container_version.to_le_bytes());
+        bytes.push(tag_length);
+        bytes.extend_from_slice(self.type_tag.as_bytes());
+        bytes.extend_from_slice(&self.type_version.to_le_bytes());
+        bytes.extend_from_slice(&self.payload_length.to_le_bytes());
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        Ok(bytes)
+    }
+
+    // returns the header and everything after it
+    pub fn parse(bytes: &[u8]) -> Result<(Self, &[u8])> {
+        let mut reader = ByteReader { bytes };
+        ensure!(
+            reader.take(4).is_ok_and(|magic| magic == MAGIC),
+            "not a smol voxel world file, it was probably written before files had a header"
+        );
+        let container_version = u16::from_le_bytes(reader.array()?);
+        ensure!(
+            container_version <= CONTAINER_VERSION,
+            "container version {} is newer than the supported version {}",
+            container_version,
+            CONTAINER_VERSION
+        );
+        let tag_length = reader.take(1)?[0] as usize;
+        let type_tag = String::from_utf8(reader.take(tag_length)?.to_vec()).context("type tag is not valid utf8")?;
+        let header = Self {
+            container_version,
+            type_tag,
+            type_version: u32::from_le_bytes(reader.array()?),
+            payload_length: u64::from_le_bytes(reader.array()?),
+            checksum: u32::from_le_bytes(reader.array()?),
+        };
+        Ok((header, reader.bytes))
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        ensure!(self.bytes.len() >= count, "header is truncated");
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+// crc32 with the IEEE polynomial, the same checksum zip and png use
+pub fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !bytes
+        .iter()
+        .fold(!0u32, |crc, &byte| (crc >> 8) ^ TABLE[((crc ^ byte as u32) & 0xff) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Example {
+        values: Vec<u32>,
+    }
+
+    impl Versioned for Example {
+        const TYPE_TAG: &'static str = "example";
+        const VERSION: u32 = 1;
+    }
+
+    // `Example` after a count was added to it
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct ExampleWithCount {
+        values: Vec<u32>,
+        count: u32,
+    }
+
+    impl Versioned for ExampleWithCount {
+        const TYPE_TAG: &'static str = "example";
+        const VERSION: u32 = 2;
+
+        fn migrate(version: u32, value: Value) -> Result<Value> {
+            ensure!(version == 1, "unknown version {}", version);
+            let Value::Map(mut fields) = value else {
+                bail!("expected a map");
+            };
+            let count = match fields.get(&Value::Text("values".to_string())) {
+                Some(Value::Array(values)) => values.len(),
+                _ => bail!("values are missing"),
+            };
+            fields.insert(Value::Text("count".to_string()), Value::Integer(count as i128));
+            Ok(Value::Map(fields))
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Other {
+        values: Vec<u32>,
+    }
+
+    impl Versioned for Other {
+        const TYPE_TAG: &'static str = "other";
+        const VERSION: u32 = 1;
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        format!("{:#}", decode_versioned::<Example>(bytes).unwrap_err())
+    }
+
+    #[test]
+    fn containers_round_trip() {
+        let example = Example { values: vec![1, 2, 3] };
+        let bytes = encode_versioned(&example).unwrap();
+        assert_eq!(decode_versioned::<Example>(&bytes).unwrap(), example);
+        let (header, _) = ContainerHeader::parse(&bytes).unwrap();
+        assert_eq!((header.type_tag.as_str(), header.type_version), ("example", 1));
+    }
+
+    #[test]
+    fn truncated_and_trailing_bytes_are_reported() {
+        let mut bytes = encode_versioned(&Example { values: vec![7; 100] }).unwrap();
+        assert!(error(&bytes[..bytes.len() - 1]).contains("truncated"));
+        bytes.extend_from_slice(&[0, 0]);
+        assert!(error(&bytes).contains("2 unexpected bytes after the payload"));
+        assert!(error(&bytes[..10]).contains("header is truncated"));
+    }
+
+    #[test]
+    fn older_versions_are_migrated() {
+        let bytes = encode_versioned(&Example { values: vec![4, 5, 6] }).unwrap();
+        let migrated = decode_versioned::<ExampleWithCount>(&bytes).unwrap();
+        assert_eq!(
+            migrated,
+            ExampleWithCount {
+                values: vec![4, 5, 6],
+                count: 3
+            }
+        );
+        // the current version is decoded directly
+        let bytes = encode_versioned(&migrated).unwrap();
+        assert_eq!(decode_versioned::<ExampleWithCount>(&bytes).unwrap(), migrated);
+        assert!(error(&bytes).contains("example version 2 was written by a newer build"));
+    }
+
+    #[test]
+    fn wrong_types_and_corruption_are_reported() {
+        let bytes = encode_versioned(&Other { values: vec![1] }).unwrap();
+        assert!(error(&bytes).contains("contains a other but a example was expected"));
+
+        let mut bytes = encode_versioned(&Example { values: vec![9; 50] }).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x10;
+        assert!(error(&bytes).contains("checksum mismatch"));
+        assert!(error(b"PK\x03\x04 a zip file").contains("not a smol voxel world file"));
+    }
+
+    #[test]
+    fn type_tags_must_fit_their_length() {
+        let header = ContainerHeader {
+            container_version: CONTAINER_VERSION,
+            type_tag: "x".repeat(256),
+            type_version: 1,
+            payload_length: 0,
+            checksum: 0,
+        };
+        assert!(header.to_bytes().is_err());
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
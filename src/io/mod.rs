@@ -7,6 +7,13 @@ use std::{
 
 use lz4_flex::{compress_prepend_size, decompress_size_prepended};
 
+mod archive;
+mod container;
+mod pod;
+pub use archive::*;
+pub use container::*;
+pub use pod::*;
+
 pub fn write_and_compress_to_file<T: Serialize>(data: &T, filename: &str) -> Result<()> {
     let data = serde_cbor::to_vec(data).with_context(|| format!("could not encode to cbor"))?;
     let data = compress_prepend_size(&data);
@@ -33,10 +40,3 @@ pub fn read_file(filename: &str) -> Result<Vec<u8>> {
     file.read_to_end(&mut buf).with_context(|| format!("could not read file: {}", filename))?;
     Ok(buf)
 }
-
-pub fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    unsafe { std::slice::from_raw_parts((p as *const T) as *const u8, ::std::mem::size_of::<T>()) }
-}
-pub fn u8_as_obj<T: Sized>(bytes: &[u8]) -> &T {
-    unsafe { &*(bytes.as_ptr() as *const T) }
-}
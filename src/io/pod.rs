@@ -0,0 +1,36 @@
+use std::any::type_name;
+
+use anyhow::{anyhow, Result};
+use bytemuck::Pod;
+
+// Checked conversions between plain old data and bytes. Views borrow the bytes and fail when their length or
+// alignment does not fit the type, reads copy and only check the length
+
+pub fn as_bytes<T: Pod>(value: &T) -> &[u8] {
+    bytemuck::bytes_of(value)
+}
+
+pub fn slice_as_bytes<T: Pod>(values: &[T]) -> &[u8] {
+    bytemuck::cast_slice(values)
+}
+
+pub fn view<T: Pod>(bytes: &[u8]) -> Result<&T> {
+    bytemuck::try_from_bytes(bytes).map_err(|error| anyhow!("could not view {} bytes as {}: {:?}", bytes.len(), type_name::<T>(), error))
+}
+
+pub fn view_slice<T: Pod>(bytes: &[u8]) -> Result<&[T]> {
+    bytemuck::try_cast_slice(bytes).map_err(|error| anyhow!("could not view {} bytes as [{}]: {:?}", bytes.len(), type_name::<T>(), error))
+}
+
+pub fn read_pod<T: Pod>(bytes: &[u8]) -> Result<T> {
+    bytemuck::try_pod_read_unaligned(bytes).map_err(|error| anyhow!("could not read {} from {} bytes: {:?}", type_name::<T>(), bytes.len(), error))
+}
+
+// copies the values out of the bytes, for data that is not aligned, like a slice of a file that was read into memory
+pub fn read_pod_slice<T: Pod>(bytes: &[u8]) -> Result<Vec<T>> {
+    let size = std::mem::size_of::<T>();
+    if size == 0 || !bytes.len().is_multiple_of(size) {
+        return Err(anyhow!("{} bytes is not a whole number of {}", bytes.len(), type_name::<T>()));
+    }
+    bytes.chunks_exact(size).map(read_pod).collect()
+}
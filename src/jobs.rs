@@ -0,0 +1,254 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use anyhow::{anyhow, bail, Result};
+use cogrrs::egui::{ProgressBar, Ui};
+
+#[derive(Default)]
+struct JobState {
+    // f32 bits, atomics do not come in floats
+    fraction: AtomicU32,
+    cancelled: AtomicBool,
+    status: Mutex<String>,
+}
+
+// handed to the work of a job so it can report how far it is and notice when it was cancelled
+#[derive(Clone)]
+pub struct Progress {
+    state: Arc<JobState>,
+}
+
+impl Progress {
+    pub fn set(&self, fraction: f32) {
+        self.state.fraction.store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+    pub fn set_status(&self, status: &str) {
+        *self.state.status.lock().unwrap() = status.to_string();
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+    // for use with `?` at points where the work can stop
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("cancelled");
+        }
+        Ok(())
+    }
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+// Jobs share a fixed number of worker threads, one core is left to the render loop. Tasks that are spawned
+// while every worker is busy wait in the queue
+fn worker_queue() -> &'static Sender<Task> {
+    static QUEUE: OnceLock<Sender<Task>> = OnceLock::new();
+    QUEUE.get_or_init(|| {
+        let (sender, receiver) = channel::<Task>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(1).max(1));
+        for index in 0..workers {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("job worker {}", index))
+                .spawn(move || loop {
+                    let task = receiver.lock().unwrap().recv();
+                    match task {
+                        // a panicking job must not take its worker with it, the job notices through its channel
+                        Ok(task) => drop(panic::catch_unwind(AssertUnwindSafe(task))),
+                        Err(_) => break,
+                    }
+                })
+                .expect("could not spawn job worker thread");
+        }
+        sender
+    })
+}
+
+// a piece of work running on the worker threads, the result is picked up by polling
+pub struct Job<T> {
+    name: String,
+    state: Arc<JobState>,
+    result: Receiver<Result<T>>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    pub fn spawn(name: &str, work: impl FnOnce(&Progress) -> Result<T> + Send + 'static) -> Self {
+        let state = Arc::new(JobState::default());
+        let progress = Progress { state: state.clone() };
+        let (sender, result) = channel();
+        progress.set_status("queued");
+        let task: Task = Box::new(move || {
+            // a job that was cancelled while it was queued does not start at all
+            let result = progress.check_cancelled().and_then(|_| {
+                progress.set_status("");
+                work(&progress)
+            });
+            // the receiver is gone when the job was dropped, nobody is interested in the result anymore
+            let _ = sender.send(result);
+        });
+        worker_queue().send(task).expect("job workers are never stopped");
+        Self {
+            name: name.to_string(),
+            state,
+            result,
+        }
+    }
+}
+
+impl<T> Job<T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn progress(&self) -> f32 {
+        f32::from_bits(self.state.fraction.load(Ordering::Relaxed))
+    }
+    pub fn status(&self) -> String {
+        self.state.status.lock().unwrap().clone()
+    }
+    // the work stops at the next point where it checks for cancellation
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+    // the result once the job is done, a job that panicked finishes with an error
+    pub fn poll(&self) -> Option<Result<T>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!("job {} panicked", self.name))),
+        }
+    }
+}
+
+// the running jobs of one kind, finished jobs are handed out by `poll` and forgotten
+pub struct Jobs<T> {
+    jobs: Vec<Job<T>>,
+}
+
+impl<T> Default for Jobs<T> {
+    fn default() -> Self {
+        Self { jobs: Vec::new() }
+    }
+}
+
+impl<T: Send + 'static> Jobs<T> {
+    pub fn spawn(&mut self, name: &str, work: impl FnOnce(&Progress) -> Result<T> + Send + 'static) {
+        self.jobs.push(Job::spawn(name, work));
+    }
+}
+
+impl<T> Jobs<T> {
+    pub fn push(&mut self, job: Job<T>) {
+        self.jobs.push(job);
+    }
+    pub fn len(&self) -> usize {
+        self.jobs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+    pub fn is_running(&self, name: &str) -> bool {
+        self.jobs.iter().any(|job| job.name() == name)
+    }
+    pub fn cancel(&self, name: &str) {
+        self.jobs.iter().filter(|job| job.name() == name).for_each(Job::cancel);
+    }
+
+    // results of the jobs that finished since the last call, cancelled jobs are dropped without a result
+    pub fn poll(&mut self) -> Vec<(String, Result<T>)> {
+        let mut finished = Vec::new();
+        self.jobs.retain(|job| match job.poll() {
+            Some(result) => {
+                if !job.is_cancelled() {
+                    finished.push((job.name().to_string(), result));
+                }
+                false
+            }
+            None => true,
+        });
+        finished
+    }
+
+    // a progress bar with a cancel button for every job
+    pub fn draw_ui(&mut self, ui: &mut Ui) {
+        for job in &self.jobs {
+            ui.horizontal(|ui| {
+                let text = match job.status() {
+                    status if status.is_empty() => job.name().to_string(),
+                    status => format!("{}: {}", job.name(), status),
+                };
+                ui.add(ProgressBar::new(job.progress()).text(text));
+                if job.is_cancelled() {
+                    ui.label("cancelling");
+                } else if ui.button("Cancel").clicked() {
+                    job.cancel();
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn wait_for<T>(job: &Job<T>) -> Result<T> {
+        let start = Instant::now();
+        loop {
+            if let Some(result) = job.poll() {
+                return result;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "job {} did not finish", job.name());
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn jobs_share_a_bounded_number_of_threads() {
+        let running = Arc::new(AtomicU32::new(0));
+        let most_running = Arc::new(AtomicU32::new(0));
+        let jobs: Vec<Job<u32>> = (0..64)
+            .map(|index| {
+                let (running, most_running) = (running.clone(), most_running.clone());
+                Job::spawn("count", move |_| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(2));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(index)
+                })
+            })
+            .collect();
+        for (index, job) in jobs.iter().enumerate() {
+            assert_eq!(wait_for(job).unwrap(), index as u32);
+        }
+        let workers = thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(1).max(1));
+        assert!(most_running.load(Ordering::SeqCst) as usize <= workers);
+    }
+
+    #[test]
+    fn panicking_jobs_fail_without_stopping_the_workers() {
+        let panicking: Job<()> = Job::spawn("panic", |_| panic!("job panic"));
+        assert!(wait_for(&panicking).is_err());
+        let after = Job::spawn("after", |_| Ok(1));
+        assert_eq!(wait_for(&after).unwrap(), 1);
+    }
+
+    #[test]
+    fn cancelled_jobs_stop_at_their_next_check() {
+        let job = Job::spawn("cancel", |progress| loop {
+            progress.check_cancelled()?;
+            thread::sleep(Duration::from_millis(1));
+        });
+        job.cancel();
+        let result: Result<()> = wait_for(&job);
+        assert!(result.is_err());
+    }
+}
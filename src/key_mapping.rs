@@ -6,3 +6,12 @@ pub const MOVE_FORWARD: VirtualKeyCode = VirtualKeyCode::W;
 pub const MOVE_BACKWARD: VirtualKeyCode = VirtualKeyCode::S;
 pub const MOVE_UP: VirtualKeyCode = VirtualKeyCode::E;
 pub const MOVE_DOWN: VirtualKeyCode = VirtualKeyCode::Q;
+
+pub const EDIT_PLACE: VirtualKeyCode = VirtualKeyCode::Key1;
+pub const EDIT_REMOVE: VirtualKeyCode = VirtualKeyCode::Key2;
+pub const EDIT_PAINT: VirtualKeyCode = VirtualKeyCode::Key3;
+pub const BRUSH_NEXT_SHAPE: VirtualKeyCode = VirtualKeyCode::Tab;
+pub const BRUSH_GROW: VirtualKeyCode = VirtualKeyCode::Equals;
+pub const BRUSH_SHRINK: VirtualKeyCode = VirtualKeyCode::Minus;
+pub const UNDO: VirtualKeyCode = VirtualKeyCode::Z;
+pub const REDO: VirtualKeyCode = VirtualKeyCode::Y;
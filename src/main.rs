@@ -1,13 +1,18 @@
 use cogrrs::main_loop_run;
 use smol_voxel_world::SmolVoxelWorld;
 
+mod assets;
 mod compute_passes;
 mod constants;
+mod editor;
+mod file_watcher;
 mod helpers;
 mod io;
+mod jobs;
 mod key_mapping;
 mod smol_voxel_world;
 mod world;
+mod world_tools;
 
 pub fn setup_logger() -> Result<(), fern::InitError> {
     fern::Dispatch::new()
@@ -1,57 +1,163 @@
-use crate::compute_passes::ComputePass;
+use crate::assets::AssetManager;
+use crate::compute_passes::{
+    ComputePass, EditorPreview, GraphResources, Picker, RenderGraph, ShaderWatcher, WorldUploader, DEBUG_COMPLEXITY, DEBUG_DEPTH, DEBUG_NORMALS,
+    DEBUG_RAY_DIRECTION, TO_SCREEN,
+};
+use crate::constants::{DEFAULT_SCENE, WORLD_CENTER, WORLD_SIZE_IN_BRICKS};
+use crate::editor::VoxelEditor;
 use crate::smol_voxel_world::TextureFormat::Rgba32Float;
+use crate::world::brickmap::Brickmap;
+use crate::world::sdf::{load_sdf_scene, voxelize_into_world, SdfNode};
+use crate::world_tools::WorldTools;
 use crate::{compute_passes::Camera, compute_passes::PrimaryRayCaster};
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use cogrrs::wgpu::TextureFormat;
 use cogrrs::winit::event::VirtualKeyCode;
 use cogrrs::{egui, puffin};
-use cogrrs::{CoGr, Game, Input, ResourceHandle, TextureRes::FullRes};
-
-#[derive(Debug, PartialEq)]
-enum RenderMode {
-    Complexity,
-    Depth,
-    Normals,
-    RayDirection,
-}
+use cogrrs::{CoGr, Encoder, Game, Input, ResourceHandle};
+use glam::{IVec3, UVec2, UVec3};
+use log::error;
 
 pub struct SmolVoxelWorld {
     to_screen: ResourceHandle,
+    resources: GraphResources,
+    graph: RenderGraph,
     camera: Camera,
     primary_ray_caster: PrimaryRayCaster,
-    render_mode: RenderMode,
+    world_uploader: WorldUploader,
+    // optional passes are disabled when they fail, the reasons are shown in the ui
+    editor_preview: Option<EditorPreview>,
+    picker: Option<Picker>,
+    disabled_passes: Vec<String>,
+    shader_watcher: ShaderWatcher,
+    world: Brickmap,
+    editor: VoxelEditor,
+    // cursor position in pixels and whether it was clicked on the image, as seen by the ui last frame
+    cursor: Option<UVec2>,
+    clicked: bool,
+    assets: AssetManager,
+    world_tools: WorldTools,
 }
 
 impl Game for SmolVoxelWorld {
     fn on_init(gpu: &mut CoGr) -> Result<Self> {
-        let to_screen = gpu.texture("to_screen", FullRes, Rgba32Float);
-        let camera = Camera::new(gpu);
-        let primary_ray_caster = PrimaryRayCaster::new(gpu);
+        let mut resources = GraphResources::default();
+        let to_screen = resources.texture(gpu, TO_SCREEN, Rgba32Float);
+        let camera: Camera = create_pass(gpu, &mut resources)?;
+        let primary_ray_caster: PrimaryRayCaster = create_pass(gpu, &mut resources)?;
+        let world_uploader: WorldUploader = create_pass(gpu, &mut resources)?;
+        let mut disabled_passes = Vec::new();
+        let editor_preview: Option<EditorPreview> = create_optional_pass(gpu, &mut resources, &mut disabled_passes);
+        let picker: Option<Picker> = create_optional_pass(gpu, &mut resources, &mut disabled_passes);
+
+        let mut graph = RenderGraph::default();
+        graph.output(TO_SCREEN);
+        graph.add_pass(WorldUploader::node())?;
+        graph.add_pass(Camera::node())?;
+        graph.add_pass(PrimaryRayCaster::node())?;
+        for node in PrimaryRayCaster::debug_nodes() {
+            graph.add_pass(node)?;
+        }
+        graph.add_pass(Camera::debug_ray_direction_node())?;
+        if editor_preview.is_some() {
+            graph.add_pass(EditorPreview::node())?;
+        }
+        if picker.is_some() {
+            graph.add_pass(Picker::node())?;
+        }
+        // every debug render replaces the image on screen, the normals are shown at first
+        graph.exclusive([DEBUG_COMPLEXITY, DEBUG_DEPTH, DEBUG_NORMALS, DEBUG_RAY_DIRECTION]);
+        graph.set_enabled(DEBUG_NORMALS, true)?;
+
+        let scene = SdfNode::Translate {
+            offset: WORLD_CENTER,
+            child: Box::new(load_sdf_scene(DEFAULT_SCENE)?),
+        };
+        let mut world = Brickmap::new(UVec3::splat(WORLD_SIZE_IN_BRICKS));
+        let world_max = world.voxel_dimensions().as_ivec3();
+        voxelize_into_world(&scene, &mut world, IVec3::ZERO, world_max);
+
         Ok(Self {
             to_screen,
+            resources,
+            graph,
             camera,
             primary_ray_caster,
-            render_mode: RenderMode::Normals,
+            world_uploader,
+            editor_preview,
+            picker,
+            disabled_passes,
+            shader_watcher: ShaderWatcher::default(),
+            world,
+            editor: VoxelEditor::default(),
+            cursor: None,
+            clicked: false,
+            assets: AssetManager::default(),
+            world_tools: WorldTools::default(),
         })
     }
 
     fn on_tick(&mut self, _gpu: &mut CoGr, _dt: f32) -> Result<()> {
+        let placed = self.assets.update(&mut self.world);
+        let replaced = self.world_tools.update(&mut self.world);
+        // the undo history only knows about edits, undoing across these would bring back old voxels
+        if placed || replaced {
+            self.editor.clear_history();
+        }
         Ok(())
     }
 
     fn on_render(&mut self, gpu: &mut CoGr, input: &Input, dt: f32) -> Result<()> {
+        let read_back = self.picker.as_mut().map_or(Ok(()), |picker| picker.read_back(gpu));
+        self.disable_on_error(Picker::NAME, read_back)?;
+        let changes = self.shader_watcher.changed();
+        if !changes.is_empty() {
+            self.camera.rebuild(gpu, &changes);
+            self.primary_ray_caster.rebuild(gpu, &changes);
+            self.world_uploader.rebuild(gpu, &changes);
+            if let Some(preview) = &mut self.editor_preview {
+                preview.rebuild(gpu, &changes);
+            }
+            if let Some(picker) = &mut self.picker {
+                picker.rebuild(gpu, &changes);
+            }
+        }
         let mut encoder = gpu.get_encoder_for_draw()?;
-        if input.key_pressed(VirtualKeyCode::X) {
+        let looking_around = input.key_pressed(VirtualKeyCode::X);
+        if looking_around {
             self.camera.update(input, dt);
         }
-        let primary_ray_gen_results = self.camera.dispatch(&mut encoder, &());
-        let _primary_ray_cast_results = self.primary_ray_caster.dispatch(&mut encoder, &primary_ray_gen_results);
 
-        match self.render_mode {
-            RenderMode::Complexity => self.primary_ray_caster.debug_complexity(&mut encoder, &self.to_screen),
-            RenderMode::Depth => self.primary_ray_caster.debug_depth(&mut encoder, &self.to_screen),
-            RenderMode::Normals => self.primary_ray_caster.debug_normals(&mut encoder, &self.to_screen),
-            RenderMode::RayDirection => self.camera.debug_ray_direction(&mut encoder, &self.to_screen),
+        self.editor.handle_hotkeys(input, &mut self.world);
+        let screen_dimensions = UVec2::new(encoder.width(), encoder.height());
+        match self.cursor.filter(|cursor| !looking_around && cursor.cmplt(screen_dimensions).all()) {
+            Some(cursor) => {
+                match &self.picker {
+                    Some(picker) => self.editor.hover(&self.world, picker.last_pick().copied()),
+                    None => {
+                        let (origin, direction) = self.camera.ray_through_pixel(cursor.as_vec2(), screen_dimensions);
+                        self.editor.hover_ray(&self.world, origin, direction);
+                    }
+                }
+                if self.clicked {
+                    if let Err(error) = self.editor.apply(&mut self.world) {
+                        error!("could not edit the world: {:#}", error);
+                    }
+                }
+            }
+            None => self.editor.clear_hover(),
+        }
+        if let Some(editor_preview) = &mut self.editor_preview {
+            editor_preview.update(&self.editor, self.cursor);
+        }
+        if let Some(picker) = &mut self.picker {
+            picker.request(self.cursor);
+        }
+
+        self.world_uploader.schedule(&mut self.world);
+        for pass in self.graph.schedule().order.clone() {
+            let result = self.run_pass(pass, &mut encoder).with_context(|| format!("{} pass failed", pass));
+            self.disable_on_error(pass, result)?;
         }
 
         encoder.to_screen(&self.to_screen)?;
@@ -61,8 +167,85 @@ impl Game for SmolVoxelWorld {
             egui::Window::new("debug").show(ctx, |ui| {
                 ui.label(format!("fps: {}", 1f32 / dt));
                 self.camera.draw_ui(ui);
+                self.primary_ray_caster.draw_ui(ui);
+                self.world_uploader.draw_ui(ui);
+                if let Some(picker) = &mut self.picker {
+                    picker.draw_ui(ui);
+                }
+                self.graph.draw_ui(ui);
+                for disabled in &self.disabled_passes {
+                    ui.colored_label(egui::Color32::RED, disabled);
+                }
+                ui.separator();
+                self.editor.draw_ui(ui, &mut self.world);
+                if let Some(editor_preview) = &mut self.editor_preview {
+                    editor_preview.draw_ui(ui);
+                }
+                ui.separator();
+                self.assets.draw_ui(ui);
+                ui.separator();
+                self.world_tools.draw_ui(ui, &mut self.world);
             });
+            // the pointer only edits the world while it is not over one of the windows
+            let over_ui = ctx.is_pointer_over_area();
+            let pixels_per_point = ctx.pixels_per_point();
+            let (hover_position, clicked) = ctx.input(|input| (input.pointer.hover_pos(), input.pointer.primary_clicked()));
+            self.cursor = hover_position
+                .filter(|_| !over_ui)
+                .map(|position| UVec2::new((position.x * pixels_per_point) as u32, (position.y * pixels_per_point) as u32));
+            self.clicked = clicked && !over_ui;
         })?;
         Ok(())
     }
 }
+
+impl SmolVoxelWorld {
+    fn run_pass(&mut self, pass: &str, encoder: &mut Encoder) -> Result<()> {
+        let resources = &self.resources;
+        match pass {
+            WorldUploader::NAME => self.world_uploader.run(encoder, resources),
+            Camera::NAME => self.camera.run(encoder, resources),
+            PrimaryRayCaster::NAME => self.primary_ray_caster.run(encoder, resources),
+            DEBUG_COMPLEXITY => self.primary_ray_caster.debug_complexity(encoder, resources),
+            DEBUG_DEPTH => self.primary_ray_caster.debug_depth(encoder, resources),
+            DEBUG_NORMALS => self.primary_ray_caster.debug_normals(encoder, resources),
+            DEBUG_RAY_DIRECTION => self.camera.debug_ray_direction(encoder, resources),
+            EditorPreview::NAME => self.editor_preview.as_mut().map_or(Ok(()), |preview| preview.run(encoder, resources)),
+            Picker::NAME => self.picker.as_mut().map_or(Ok(()), |picker| picker.run(encoder, resources)),
+            _ => bail!("there is no pass called {}", pass),
+        }
+    }
+
+    // optional passes that fail are disabled instead of stopping the app, errors of other passes are returned
+    fn disable_on_error(&mut self, pass: &str, result: Result<()>) -> Result<()> {
+        let Err(pass_error) = result else {
+            return Ok(());
+        };
+        match pass {
+            EditorPreview::NAME => self.editor_preview = None,
+            Picker::NAME => self.picker = None,
+            _ => return Err(pass_error),
+        }
+        let message = format!("{} disabled: {:#}", pass, pass_error);
+        error!("{}", message);
+        self.disabled_passes.push(message);
+        self.graph.set_enabled(pass, false)
+    }
+}
+
+fn create_pass<P: ComputePass>(gpu: &mut CoGr, resources: &mut GraphResources) -> Result<P> {
+    P::new(gpu, resources).with_context(|| format!("could not create the {} pass", P::NAME))
+}
+
+// optional passes that can not be created are left out of the graph instead of stopping the app
+fn create_optional_pass<P: ComputePass>(gpu: &mut CoGr, resources: &mut GraphResources, disabled_passes: &mut Vec<String>) -> Option<P> {
+    match create_pass(gpu, resources) {
+        Ok(pass) => Some(pass),
+        Err(pass_error) => {
+            let message = format!("{} disabled: {:#}", P::NAME, pass_error);
+            error!("{}", message);
+            disabled_passes.push(message);
+            None
+        }
+    }
+}
@@ -1,34 +1,63 @@
-use crate::io::{read_and_decompress_file, read_file, write_and_compress_to_file};
+use crate::io::{read_file, read_pod, read_versioned_file, write_versioned_file, Versioned};
+use crate::jobs::{Job, Progress};
 
+use super::material::{Material, AIR};
 use super::voxelized::MeshGridBitfield;
-use anyhow::{bail, Context, Result};
+use super::VoxelStorage;
+use anyhow::{bail, ensure, Context, Result};
 use bvh::{
     aabb::Bounded,
     bounding_hierarchy::BHShape,
     ray::{Intersection, Ray},
     Point3, Vector3,
 };
-use glam::{uvec3, vec3, UVec3, Vec3};
-use log::debug;
+use glam::{uvec3, vec3, IVec3, UVec3, Vec3};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use std::{
     f32::{EPSILON, INFINITY},
     io::BufReader,
     path::Path,
-    time::Instant,
+    time::SystemTime,
 };
-use tobj::{load_obj, load_obj_buf, Model};
+use tobj::{load_obj_buf, Model};
+
+// the content of a source file, stored next to it compressed
+#[derive(Serialize, Deserialize)]
+struct FileCache(Vec<u8>);
+
+impl Versioned for FileCache {
+    const TYPE_TAG: &'static str = "file_cache";
+    const VERSION: u32 = 1;
+}
+
+fn modified(file: &str) -> Option<SystemTime> {
+    std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+}
+
+// caches are rebuilt when their source was modified after they were written, or when they fail to load,
+// for example because they were written by an older build
+fn read_cache<T: Versioned>(source_file: &str, compressed_file: &str) -> Option<T> {
+    match (modified(source_file), modified(compressed_file)) {
+        (Some(source), Some(cache)) if cache >= source => {}
+        _ => return None,
+    }
+    read_versioned_file(compressed_file)
+        .map_err(|error| warn!("rebuilding cache: {:#}", error))
+        .ok()
+}
 
 pub fn cache_file(file: &str) -> Result<Vec<u8>> {
     if !Path::new(&file).exists() {
         bail!("file {file} does not exist");
     }
     let compressed_file = file.to_string() + ".compressed";
-    if Path::new(&compressed_file).exists() {
-        read_and_decompress_file(&compressed_file)
-    } else {
-        let data = read_file(file)?;
-        write_and_compress_to_file(&data, &compressed_file)?;
+    if let Some(FileCache(data)) = read_cache(file, &compressed_file) {
         Ok(data)
+    } else {
+        let cache = FileCache(read_file(file)?);
+        write_versioned_file(&cache, &compressed_file)?;
+        Ok(cache.0)
     }
 }
 
@@ -109,7 +138,7 @@ pub fn intersects_triangle(ray: &Ray, a: &Point3, b: &Point3, c: &Point3) -> Int
     }
 }
 
-pub fn place_in_bitfield(grid: &mut MeshGridBitfield, size: u32, models: Vec<Model>) {
+pub fn place_in_bitfield(grid: &mut MeshGridBitfield, size: u32, models: Vec<Model>, progress: &Progress) -> Result<()> {
     let mut max_dim = Vec3::splat(f32::MIN);
     let mut min_dim = Vec3::splat(f32::MAX);
 
@@ -124,7 +153,7 @@ pub fn place_in_bitfield(grid: &mut MeshGridBitfield, size: u32, models: Vec<Mod
 
     let scale_factor = (size as f32 - 3f32) / f32::abs(max_dim.max_element() - min_dim.min_element()) / 4f32;
     let offset = ((size as f32 - 1f32) / 2f32) - (max_dim + min_dim) * scale_factor / 2f32;
-    println!(
+    debug!(
         "{} {} {} {} {} {}",
         min_dim,
         max_dim,
@@ -133,7 +162,8 @@ pub fn place_in_bitfield(grid: &mut MeshGridBitfield, size: u32, models: Vec<Mod
         min_dim * scale_factor + offset,
         max_dim * scale_factor + offset
     );
-    for model in models {
+    let model_count = models.len();
+    for (model_index, model) in models.into_iter().enumerate() {
         let mut primitives = Vec::with_capacity(model.mesh.indices.len() / 9);
         model.mesh.indices.chunks(3).for_each(|index| {
             let vertex0 = vec3(
@@ -163,11 +193,15 @@ pub fn place_in_bitfield(grid: &mut MeshGridBitfield, size: u32, models: Vec<Mod
             };
             primitives.push(triangle);
         });
+        progress.set_status(&format!("building bvh of model {}/{}", model_index + 1, model_count));
         let bvh = bvh::bvh::BVH::build(&mut primitives);
         //bvh.pretty_print();
-        println!("built bvh");
+        debug!("built bvh");
 
+        progress.set_status(&format!("voxelizing model {}/{}", model_index + 1, model_count));
         for z in 0..size {
+            progress.check_cancelled()?;
+            progress.set((model_index as f32 + z as f32 / size as f32) / model_count as f32);
             for y in 0..size {
                 for x in 0..size {
                     let origin = Point3::new(x as f32, y as f32, z as f32) + 0.5;
@@ -185,23 +219,159 @@ pub fn place_in_bitfield(grid: &mut MeshGridBitfield, size: u32, models: Vec<Mod
             }
         }
     }
+    Ok(())
 }
 
-pub fn load_obj_to_bitfield<T, B>(mesh_file: &str, size: u32) -> Result<MeshGridBitfield> {
+pub fn load_obj_to_bitfield(mesh_file: &str, size: u32, progress: &Progress) -> Result<MeshGridBitfield> {
     let data = cache_file(mesh_file)?;
-    let compressed_file = mesh_file.to_string() + ".bitcompressed";
-    if Path::new(&compressed_file).exists() {
-        read_and_decompress_file(&compressed_file)
+    // every size is cached on its own
+    let compressed_file = format!("{mesh_file}.{size}.bitcompressed");
+    if let Some(bitfield) = read_cache(mesh_file, &compressed_file) {
+        Ok(bitfield)
     } else {
-        let mut bitfield = MeshGridBitfield::new(mesh_file, uvec3(size, size, size));
+        let mut bitfield = MeshGridBitfield::new(mesh_file, uvec3(size, size, size))?;
 
-        let (models, _) = load_obj_buf(&mut BufReader::new(data.as_slice()), &tobj::GPU_LOAD_OPTIONS, |_| {
-            panic!("remove materials from {mesh_file}")
+        // the bitfield has no materials, material libraries are not loaded and are reported instead
+        let (models, materials) = load_obj_buf(&mut BufReader::new(data.as_slice()), &tobj::GPU_LOAD_OPTIONS, |_| {
+            Err(tobj::LoadError::GenericFailure)
         })
-        .expect("Could not parse obj");
+        .with_context(|| format!("could not parse obj: {mesh_file}"))?;
+        ensure!(
+            materials.is_ok(),
+            "{mesh_file} references a material library, remove the materials from it"
+        );
 
-        place_in_bitfield(&mut bitfield, size, models);
-        write_and_compress_to_file(&bitfield, &compressed_file)?;
+        place_in_bitfield(&mut bitfield, size, models, progress)?;
+        write_versioned_file(&bitfield, &compressed_file)?;
         Ok(bitfield)
     }
 }
+
+// Reads the first model of a MagicaVoxel file. The file is a list of chunks, each with a 4 byte id, the size
+// of its content and the size of its children, the models are stored in SIZE and XYZI chunks inside MAIN.
+// MagicaVoxel uses z as up, so y and z are swapped to match the world
+pub fn load_vox_to_bitfield(vox_file: &str) -> Result<MeshGridBitfield> {
+    let data = read_file(vox_file)?;
+    parse_vox(vox_file, &data).with_context(|| format!("could not parse vox file: {vox_file}"))
+}
+
+fn parse_vox(name: &str, data: &[u8]) -> Result<MeshGridBitfield> {
+    ensure!(data.len() >= 8 && &data[0..4] == b"VOX ", "missing VOX header");
+    let read_u32 = |at: usize| -> Result<u32> {
+        ensure!(at + 4 <= data.len(), "file is truncated");
+        read_pod(&data[at..at + 4])
+    };
+    // skip the header and the MAIN chunk header, its children follow directly
+    let mut at = 8 + 12;
+    let mut dimensions = None;
+    while at + 12 <= data.len() {
+        let id = &data[at..at + 4];
+        let content_size = read_u32(at + 4)? as usize;
+        let content = at + 12;
+        match id {
+            b"SIZE" => {
+                let (x, y, z) = (read_u32(content)?, read_u32(content + 4)?, read_u32(content + 8)?);
+                dimensions = Some(uvec3(x, z, y));
+            }
+            b"XYZI" => {
+                let dimensions = dimensions.context("XYZI chunk before SIZE chunk")?;
+                let mut grid = MeshGridBitfield::new(name, dimensions)?;
+                let count = read_u32(content)? as usize;
+                ensure!(content + 4 + count * 4 <= data.len(), "file is truncated");
+                for voxel in data[content + 4..content + 4 + count * 4].chunks_exact(4) {
+                    let position = uvec3(voxel[0] as u32, voxel[2] as u32, voxel[1] as u32);
+                    ensure!(position.cmplt(dimensions).all(), "voxel {} is outside of the model", position);
+                    grid.set_bit(position, true);
+                }
+                return Ok(grid);
+            }
+            _ => {}
+        }
+        at = content + content_size;
+    }
+    bail!("no model found")
+}
+
+// loads obj and vox files on a worker thread, the result is picked up by polling the job.
+// Obj files are voxelized to a cube of `size` voxels, vox files keep their own size
+pub fn spawn_load_asset(file: &str, size: u32) -> Job<MeshGridBitfield> {
+    let path = file.to_string();
+    Job::spawn(file, move |progress| {
+        let extension = Path::new(&path).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        match extension.to_ascii_lowercase().as_str() {
+            "obj" => load_obj_to_bitfield(&path, size, progress),
+            "vox" => load_vox_to_bitfield(&path),
+            _ => bail!("unsupported asset type: {path}"),
+        }
+    })
+}
+
+// replaces the box of the world covered by the grid with the content of the grid
+pub fn place_in_world(world: &mut impl VoxelStorage, grid: &MeshGridBitfield, origin: IVec3, material: Material) {
+    let d = grid.dimensions();
+    for z in 0..d.z {
+        for y in 0..d.y {
+            for x in 0..d.x {
+                let position = uvec3(x, y, z);
+                let voxel = if grid.get_bit(position) { material } else { AIR };
+                world.set_material(origin + position.as_ivec3(), voxel);
+            }
+        }
+    }
+}
+
+// clears the box a grid was placed in, so a reloaded asset does not leave parts of its old version behind
+pub fn clear_in_world(world: &mut impl VoxelStorage, origin: IVec3, dimensions: UVec3) {
+    for z in 0..dimensions.z as i32 {
+        for y in 0..dimensions.y as i32 {
+            for x in 0..dimensions.x as i32 {
+                world.set_material(origin + IVec3::new(x, y, z), AIR);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u32], children: u32) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32 * 4).to_le_bytes());
+        bytes.extend_from_slice(&children.to_le_bytes());
+        content.iter().for_each(|word| bytes.extend_from_slice(&word.to_le_bytes()));
+        bytes
+    }
+
+    // a vox file with one model, voxels are given as x, y, z in MagicaVoxel coordinates
+    fn vox_file(size: [u32; 3], voxels: &[[u8; 3]]) -> Vec<u8> {
+        let size = chunk(b"SIZE", &size, 0);
+        let mut xyzi = vec![voxels.len() as u32];
+        xyzi.extend(voxels.iter().map(|&[x, y, z]| u32::from_le_bytes([x, y, z, 1])));
+        let xyzi = chunk(b"XYZI", &xyzi, 0);
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[], (size.len() + xyzi.len()) as u32));
+        bytes.extend(size);
+        bytes.extend(xyzi);
+        bytes
+    }
+
+    #[test]
+    fn vox_models_are_read_with_y_up() {
+        let grid = parse_vox("model", &vox_file([2, 3, 4], &[[0, 0, 0], [1, 2, 3], [1, 0, 2]])).unwrap();
+        assert_eq!(grid.dimensions(), uvec3(2, 4, 3));
+        let mut solid: Vec<UVec3> = grid.solid_positions().collect();
+        solid.sort_by_key(|position| position.to_array());
+        assert_eq!(solid, vec![uvec3(0, 0, 0), uvec3(1, 2, 0), uvec3(1, 3, 2)]);
+    }
+
+    #[test]
+    fn broken_vox_files_are_rejected() {
+        let file = vox_file([2, 2, 2], &[[1, 1, 1]]);
+        assert!(parse_vox("truncated", &file[..file.len() - 2]).is_err());
+        assert!(parse_vox("outside", &vox_file([2, 2, 2], &[[2, 0, 0]])).is_err());
+        assert!(parse_vox("header", &file[4..]).is_err());
+        assert!(parse_vox("empty", &file[..20]).is_err());
+    }
+}
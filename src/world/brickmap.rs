@@ -0,0 +1,512 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use anyhow::{bail, Context, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, UVec3};
+use serde::{Deserialize, Serialize};
+
+use crate::io::{read_versioned_file, write_versioned_file, Versioned};
+
+use super::dirty::index_ranges;
+use super::material::{is_solid, Material, AIR, STONE};
+use super::voxelized::MeshGridBitfield;
+use super::VoxelStorage;
+
+pub const BRICK_SIZE: u32 = 8;
+const BRICK_WORDS: usize = (BRICK_SIZE * BRICK_SIZE * BRICK_SIZE / 32) as usize;
+// top level value of a brick without any solid voxels
+pub const EMPTY_BRICK: u32 = u32::MAX;
+
+// 8^3 occupancy bits, bit x + y * 8 + z * 64 of the brick
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Brick {
+    pub occupancy: [u32; BRICK_WORDS],
+}
+
+impl Brick {
+    pub const EMPTY: Brick = Brick { occupancy: [0; BRICK_WORDS] };
+
+    fn bit(position: UVec3) -> (usize, u32) {
+        debug_assert!(position.cmplt(UVec3::splat(BRICK_SIZE)).all());
+        let index = position.x + position.y * BRICK_SIZE + position.z * BRICK_SIZE * BRICK_SIZE;
+        ((index / 32) as usize, 1 << (index % 32))
+    }
+    pub fn get(&self, position: UVec3) -> bool {
+        let (word, bit) = Self::bit(position);
+        self.occupancy[word] & bit != 0
+    }
+    pub fn set(&mut self, position: UVec3, value: bool) {
+        let (word, bit) = Self::bit(position);
+        match value {
+            true => self.occupancy[word] |= bit,
+            false => self.occupancy[word] &= !bit,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.occupancy.iter().all(|&word| word == 0)
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct BrickPool {
+    bricks: Vec<Brick>,
+    // slots of freed bricks, reused before the pool grows
+    free_list: Vec<u32>,
+}
+
+impl BrickPool {
+    pub fn allocate(&mut self, brick: Brick) -> u32 {
+        match self.free_list.pop() {
+            Some(index) => {
+                self.bricks[index as usize] = brick;
+                index
+            }
+            None => {
+                self.bricks.push(brick);
+                self.bricks.len() as u32 - 1
+            }
+        }
+    }
+    pub fn free(&mut self, index: u32) {
+        debug_assert!(!self.free_list.contains(&index));
+        self.bricks[index as usize] = Brick::EMPTY;
+        self.free_list.push(index);
+    }
+    pub fn get(&self, index: u32) -> &Brick {
+        &self.bricks[index as usize]
+    }
+    pub fn get_mut(&mut self, index: u32) -> &mut Brick {
+        &mut self.bricks[index as usize]
+    }
+    // every slot including freed ones, this is what gets uploaded to the GPU
+    pub fn bricks(&self) -> &[Brick] {
+        &self.bricks
+    }
+    pub fn capacity(&self) -> usize {
+        self.bricks.len()
+    }
+    pub fn allocated(&self) -> usize {
+        self.bricks.len() - self.free_list.len()
+    }
+}
+
+// GPU layout: a header, the top level grid as one u32 per brick (x major, EMPTY_BRICK or an index into the pool)
+// and the pool as an array of `Brick`
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable, Debug, PartialEq)]
+pub struct BrickmapGpu {
+    pub dimensions: UVec3,
+    pub brick_count: u32,
+}
+
+// parts of the GPU copy that are out of date, as ranges of elements of the top level and pool buffers
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BrickmapUploadPlan {
+    pub top_ranges: Vec<Range<u32>>,
+    pub pool_ranges: Vec<Range<u32>>,
+}
+
+impl BrickmapUploadPlan {
+    pub fn is_empty(&self) -> bool {
+        self.top_ranges.is_empty() && self.pool_ranges.is_empty()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "BrickmapData")]
+pub struct Brickmap {
+    // size of the top level grid in bricks
+    dimensions: UVec3,
+    top: Vec<u32>,
+    pool: BrickPool,
+    #[serde(skip)]
+    dirty_top: BTreeSet<u32>,
+    #[serde(skip)]
+    dirty_pool: BTreeSet<u32>,
+}
+
+// what a saved brickmap contains, checked before it becomes a `Brickmap` so a damaged file can not point outside of the pool
+#[derive(Deserialize)]
+struct BrickmapData {
+    dimensions: UVec3,
+    top: Vec<u32>,
+    pool: BrickPool,
+}
+
+impl TryFrom<BrickmapData> for Brickmap {
+    type Error = anyhow::Error;
+
+    fn try_from(data: BrickmapData) -> Result<Self> {
+        let BrickmapData { dimensions, top, pool } = data;
+        let brick_count = dimensions
+            .x
+            .checked_mul(dimensions.y)
+            .and_then(|count| count.checked_mul(dimensions.z))
+            .with_context(|| format!("brickmap of {} bricks is too large", dimensions))?;
+        if top.len() != brick_count as usize {
+            bail!("brickmap of {} bricks has {} top level entries", dimensions, top.len());
+        }
+        let capacity = pool.bricks.len();
+        let mut used = vec![false; capacity];
+        for &index in &pool.free_list {
+            match used.get_mut(index as usize) {
+                Some(slot) if !*slot => *slot = true,
+                Some(_) => bail!("brick {} is freed twice", index),
+                None => bail!("freed brick {} is outside of the pool of {} bricks", index, capacity),
+            }
+        }
+        for (top_index, &pointer) in top.iter().enumerate() {
+            if pointer == EMPTY_BRICK {
+                continue;
+            }
+            match used.get_mut(pointer as usize) {
+                Some(slot) if !*slot => *slot = true,
+                Some(_) => bail!("top level entry {} points at brick {} which is free or already used", top_index, pointer),
+                None => bail!(
+                    "top level entry {} points at brick {} outside of the pool of {} bricks",
+                    top_index,
+                    pointer,
+                    capacity
+                ),
+            }
+        }
+        Ok(Self {
+            dimensions,
+            top,
+            pool,
+            dirty_top: BTreeSet::new(),
+            dirty_pool: BTreeSet::new(),
+        })
+    }
+}
+
+impl Brickmap {
+    pub fn new(dimensions: UVec3) -> Self {
+        Self {
+            dimensions,
+            top: vec![EMPTY_BRICK; (dimensions.x * dimensions.y * dimensions.z) as usize],
+            pool: BrickPool::default(),
+            dirty_top: BTreeSet::new(),
+            dirty_pool: BTreeSet::new(),
+        }
+    }
+
+    pub fn from_bitfield(grid: &MeshGridBitfield) -> Self {
+        let dimensions = (grid.dimensions() + BRICK_SIZE - 1) / BRICK_SIZE;
+        let mut brickmap = Self::new(dimensions);
+        for position in grid.solid_positions() {
+            brickmap.set_voxel(position.as_ivec3(), true);
+        }
+        brickmap
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        read_versioned_file(filename)
+    }
+    pub fn save(&self, filename: &str) -> Result<()> {
+        write_versioned_file(self, filename)
+    }
+
+    pub fn dimensions(&self) -> UVec3 {
+        self.dimensions
+    }
+    pub fn voxel_dimensions(&self) -> UVec3 {
+        self.dimensions * BRICK_SIZE
+    }
+    pub fn pool(&self) -> &BrickPool {
+        &self.pool
+    }
+    pub fn top(&self) -> &[u32] {
+        &self.top
+    }
+    pub fn gpu_header(&self) -> BrickmapGpu {
+        BrickmapGpu {
+            dimensions: self.dimensions,
+            brick_count: self.pool.capacity() as u32,
+        }
+    }
+
+    pub fn top_index(&self, brick: UVec3) -> usize {
+        assert!(brick.cmplt(self.dimensions).all(), "brick {} is outside of the brickmap", brick);
+        (brick.x + brick.y * self.dimensions.x + brick.z * self.dimensions.x * self.dimensions.y) as usize
+    }
+
+    fn contains_brick(&self, brick: IVec3) -> bool {
+        brick.cmpge(IVec3::ZERO).all() && brick.cmplt(self.dimensions.as_ivec3()).all()
+    }
+
+    pub fn brick_pointer(&self, brick: UVec3) -> Option<u32> {
+        match self.top[self.top_index(brick)] {
+            EMPTY_BRICK => None,
+            pointer => Some(pointer),
+        }
+    }
+
+    pub fn brick(&self, brick: UVec3) -> Option<&Brick> {
+        self.brick_pointer(brick).map(|pointer| self.pool.get(pointer))
+    }
+
+    // positions outside of the map are empty
+    pub fn get_voxel(&self, position: IVec3) -> bool {
+        let brick = position.div_euclid(IVec3::splat(BRICK_SIZE as i32));
+        if !self.contains_brick(brick) {
+            return false;
+        }
+        let local = (position - brick * BRICK_SIZE as i32).as_uvec3();
+        self.brick(brick.as_uvec3()).is_some_and(|brick| brick.get(local))
+    }
+
+    // allocates a brick for the first solid voxel in it and frees it again when its last voxel is cleared,
+    // returns the brick that changed
+    pub fn set_voxel(&mut self, position: IVec3, value: bool) -> Option<UVec3> {
+        let brick = position.div_euclid(IVec3::splat(BRICK_SIZE as i32));
+        assert!(self.contains_brick(brick), "voxel {} is outside of the brickmap", position);
+        let local = (position - brick * BRICK_SIZE as i32).as_uvec3();
+        let brick = brick.as_uvec3();
+        let top_index = self.top_index(brick);
+        match (self.top[top_index], value) {
+            (EMPTY_BRICK, false) => None,
+            (EMPTY_BRICK, true) => {
+                let mut new_brick = Brick::EMPTY;
+                new_brick.set(local, true);
+                self.allocate(top_index, new_brick);
+                Some(brick)
+            }
+            (pointer, value) => {
+                let existing = self.pool.get_mut(pointer);
+                if existing.get(local) == value {
+                    return None;
+                }
+                existing.set(local, value);
+                if existing.is_empty() {
+                    self.free(top_index);
+                } else {
+                    self.dirty_pool.insert(pointer);
+                }
+                Some(brick)
+            }
+        }
+    }
+
+    // replaces a whole brick, for example one that was streamed in from disk or the network
+    pub fn stream_in(&mut self, brick: UVec3, data: Brick) {
+        self.stream_out(brick);
+        if !data.is_empty() {
+            let top_index = self.top_index(brick);
+            self.allocate(top_index, data);
+        }
+    }
+
+    // removes a brick from the map and returns its content so it can be stored elsewhere
+    pub fn stream_out(&mut self, brick: UVec3) -> Option<Brick> {
+        let top_index = self.top_index(brick);
+        let pointer = self.brick_pointer(brick)?;
+        let data = *self.pool.get(pointer);
+        self.free(top_index);
+        Some(data)
+    }
+
+    fn allocate(&mut self, top_index: usize, brick: Brick) {
+        let pointer = self.pool.allocate(brick);
+        self.top[top_index] = pointer;
+        self.dirty_top.insert(top_index as u32);
+        self.dirty_pool.insert(pointer);
+    }
+
+    fn free(&mut self, top_index: usize) {
+        let pointer = self.top[top_index];
+        self.pool.free(pointer);
+        self.top[top_index] = EMPTY_BRICK;
+        self.dirty_top.insert(top_index as u32);
+        // nothing points at a freed slot anymore so its content on the GPU does not matter
+        self.dirty_pool.remove(&pointer);
+    }
+
+    // everything has to be uploaded, for example after the GPU buffers were recreated
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_top = (0..self.top.len() as u32).collect();
+        self.dirty_pool = self.top.iter().copied().filter(|&pointer| pointer != EMPTY_BRICK).collect();
+    }
+
+    pub fn has_pending_upload(&self) -> bool {
+        !self.dirty_top.is_empty() || !self.dirty_pool.is_empty()
+    }
+
+    // the changes since the last call, adjacent elements are merged into a single range
+    pub fn take_upload_plan(&mut self) -> BrickmapUploadPlan {
+        BrickmapUploadPlan {
+            top_ranges: index_ranges(std::mem::take(&mut self.dirty_top)),
+            pool_ranges: index_ranges(std::mem::take(&mut self.dirty_pool)),
+        }
+    }
+}
+
+impl Versioned for Brickmap {
+    const TYPE_TAG: &'static str = "brickmap";
+    const VERSION: u32 = 1;
+}
+
+impl VoxelStorage for Brickmap {
+    fn get_material(&self, position: IVec3) -> Material {
+        if self.get_voxel(position) {
+            STONE
+        } else {
+            AIR
+        }
+    }
+    fn set_material(&mut self, position: IVec3, material: Material) {
+        let brick = position.div_euclid(IVec3::splat(BRICK_SIZE as i32));
+        if self.contains_brick(brick) {
+            self.set_voxel(position, is_solid(material));
+        }
+    }
+    fn stores_materials(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (start, end) pairs
+    type Ranges = Vec<(u32, u32)>;
+
+    // (top ranges, pool ranges)
+    fn take_plan(brickmap: &mut Brickmap) -> (Ranges, Ranges) {
+        let plan = brickmap.take_upload_plan();
+        let pairs = |ranges: Vec<Range<u32>>| ranges.into_iter().map(|range| (range.start, range.end)).collect();
+        (pairs(plan.top_ranges), pairs(plan.pool_ranges))
+    }
+
+    #[test]
+    fn upload_plans_contain_only_changes() {
+        let mut brickmap = Brickmap::new(UVec3::splat(4));
+        assert!(brickmap.take_upload_plan().is_empty());
+
+        // two voxels in neighbouring bricks allocate two pool slots
+        brickmap.set_voxel(IVec3::new(0, 0, 0), true);
+        brickmap.set_voxel(IVec3::new(8, 0, 0), true);
+        assert_eq!(take_plan(&mut brickmap), (vec![(0, 2)], vec![(0, 2)]));
+        assert!(!brickmap.has_pending_upload());
+
+        // changing a voxel of an existing brick only uploads that brick
+        brickmap.set_voxel(IVec3::new(9, 1, 0), true);
+        assert_eq!(take_plan(&mut brickmap), (vec![], vec![(1, 2)]));
+
+        // freeing a brick only changes its pointer, the freed slot is reused by the next brick
+        brickmap.set_voxel(IVec3::new(0, 0, 0), false);
+        brickmap.set_voxel(IVec3::new(0, 0, 31), true);
+        assert_eq!(take_plan(&mut brickmap), (vec![(0, 1), (48, 49)], vec![(0, 1)]));
+        assert_eq!(brickmap.top()[48], 0);
+        assert_eq!(brickmap.pool().allocated(), 2);
+
+        brickmap.mark_all_dirty();
+        assert_eq!(take_plan(&mut brickmap), (vec![(0, 64)], vec![(0, 2)]));
+    }
+
+    fn brick_with(positions: &[UVec3]) -> Brick {
+        let mut brick = Brick::EMPTY;
+        for &position in positions {
+            brick.set(position, true);
+        }
+        brick
+    }
+
+    #[test]
+    fn freed_slots_are_reused_before_the_pool_grows() {
+        let mut pool = BrickPool::default();
+        let bricks: Vec<u32> = (0..3).map(|index| pool.allocate(brick_with(&[UVec3::new(index, 0, 0)]))).collect();
+        assert_eq!(bricks, vec![0, 1, 2]);
+        pool.free(1);
+        pool.free(0);
+        assert_eq!((pool.capacity(), pool.allocated()), (3, 1));
+        assert_eq!(*pool.get(1), Brick::EMPTY);
+
+        // the most recently freed slot comes back first
+        assert_eq!(pool.allocate(brick_with(&[UVec3::ONE])), 0);
+        assert_eq!(pool.allocate(brick_with(&[UVec3::ONE])), 1);
+        assert_eq!(pool.allocate(brick_with(&[UVec3::ONE])), 3);
+        assert_eq!((pool.capacity(), pool.allocated()), (4, 4));
+    }
+
+    #[test]
+    fn streaming_replaces_whole_bricks() {
+        let mut brickmap = Brickmap::new(UVec3::splat(2));
+        let data = brick_with(&[UVec3::new(1, 2, 3), UVec3::new(7, 7, 7)]);
+        brickmap.stream_in(UVec3::new(1, 0, 1), data);
+        assert!(brickmap.get_voxel(IVec3::new(9, 2, 11)));
+        assert!(brickmap.get_voxel(IVec3::new(15, 7, 15)));
+        assert!(!brickmap.get_voxel(IVec3::new(8, 0, 8)));
+        assert_eq!(take_plan(&mut brickmap), (vec![(5, 6)], vec![(0, 1)]));
+
+        // streaming over an existing brick reuses its slot, streaming in an empty brick frees it
+        let replacement = brick_with(&[UVec3::ZERO]);
+        brickmap.stream_in(UVec3::new(1, 0, 1), replacement);
+        assert!(brickmap.get_voxel(IVec3::new(8, 0, 8)));
+        assert!(!brickmap.get_voxel(IVec3::new(9, 2, 11)));
+        assert_eq!(brickmap.pool().capacity(), 1);
+        assert_eq!(brickmap.stream_out(UVec3::new(1, 0, 1)), Some(replacement));
+        assert_eq!(brickmap.stream_out(UVec3::new(1, 0, 1)), None);
+        brickmap.stream_in(UVec3::new(0, 1, 0), Brick::EMPTY);
+        assert_eq!(brickmap.pool().allocated(), 0);
+        assert_eq!(brickmap.top(), &[EMPTY_BRICK; 8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the brickmap")]
+    fn streaming_outside_of_the_map_panics() {
+        Brickmap::new(UVec3::splat(2)).stream_in(UVec3::new(0, 2, 0), brick_with(&[UVec3::ZERO]));
+    }
+
+    #[test]
+    fn saved_brickmaps_load_back() {
+        let mut brickmap = Brickmap::new(UVec3::new(3, 2, 2));
+        for position in [IVec3::new(0, 0, 0), IVec3::new(23, 15, 15), IVec3::new(10, 3, 9), IVec3::new(1, 0, 0)] {
+            brickmap.set_voxel(position, true);
+        }
+        brickmap.set_voxel(IVec3::new(10, 3, 9), false);
+        let filename = std::env::temp_dir().join(format!("svw_brickmap_{}", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        brickmap.save(filename).unwrap();
+        let mut loaded = Brickmap::load(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+
+        assert_eq!(loaded.dimensions(), brickmap.dimensions());
+        assert_eq!(loaded.top(), brickmap.top());
+        assert_eq!(loaded.pool().bricks(), brickmap.pool().bricks());
+        assert_eq!(loaded.pool().allocated(), 2);
+        // the freed slot is still reused after loading
+        loaded.set_voxel(IVec3::new(10, 3, 9), true);
+        assert_eq!(loaded.pool().capacity(), 3);
+    }
+
+    #[test]
+    fn pointers_outside_of_the_pool_are_rejected() {
+        let mut brickmap = Brickmap::new(UVec3::splat(2));
+        brickmap.set_voxel(IVec3::ZERO, true);
+        brickmap.set_voxel(IVec3::splat(8), true);
+        brickmap.set_voxel(IVec3::splat(8), false);
+        let load = |brickmap: &Brickmap| serde_cbor::from_slice::<Brickmap>(&serde_cbor::to_vec(brickmap).unwrap());
+        assert!(load(&brickmap).is_ok());
+
+        let mut broken = brickmap.clone();
+        broken.top[3] = 7;
+        assert!(load(&broken).err().unwrap().to_string().contains("outside of the pool"));
+        // slot 1 is on the free list
+        let mut broken = brickmap.clone();
+        broken.top[3] = 1;
+        assert!(load(&broken).is_err());
+        let mut broken = brickmap.clone();
+        broken.top[3] = 0;
+        assert!(load(&broken).is_err());
+        let mut broken = brickmap.clone();
+        broken.top.pop();
+        assert!(load(&broken).is_err());
+        let mut broken = brickmap;
+        broken.pool.free_list.push(4);
+        assert!(load(&broken).is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use glam::{IVec3, UVec3};
+use serde::{Deserialize, Serialize};
+
+use crate::io::Versioned;
+
+use super::material::{is_solid, Material, AIR};
+
+pub const CHUNK_SIZE: u32 = 32;
+const _: () = assert!(CHUNK_SIZE == u32::BITS);
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    position: IVec3,
+    materials: Vec<Material>,
+}
+
+impl Chunk {
+    pub fn new(position: IVec3) -> Self {
+        Self {
+            position,
+            materials: vec![AIR; CHUNK_VOLUME],
+        }
+    }
+    pub fn position(&self) -> IVec3 {
+        self.position
+    }
+    // world space position of voxel (0, 0, 0) in this chunk
+    pub fn origin(&self) -> IVec3 {
+        self.position * CHUNK_SIZE as i32
+    }
+    pub fn get(&self, position: UVec3) -> Material {
+        self.materials[Self::index(position)]
+    }
+    pub fn set(&mut self, position: UVec3, material: Material) {
+        let index = Self::index(position);
+        self.materials[index] = material;
+    }
+    pub fn is_solid(&self, position: UVec3) -> bool {
+        is_solid(self.get(position))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.materials.iter().all(|&material| material == AIR)
+    }
+    // bit x is set when voxel (x, y, z) is solid, a row of a chunk is exactly one word
+    pub fn solid_row(&self, y: u32, z: u32) -> u32 {
+        let start = Self::index(UVec3::new(0, y, z));
+        let row = &self.materials[start..start + CHUNK_SIZE as usize];
+        row.iter()
+            .enumerate()
+            .fold(0, |bits, (x, &material)| bits | ((is_solid(material) as u32) << x))
+    }
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+    fn index(position: UVec3) -> usize {
+        assert!(position.x < CHUNK_SIZE);
+        assert!(position.y < CHUNK_SIZE);
+        assert!(position.z < CHUNK_SIZE);
+        (position.x + position.y * CHUNK_SIZE + position.z * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+}
+
+impl Versioned for Chunk {
+    const TYPE_TAG: &'static str = "chunk";
+    const VERSION: u32 = 1;
+}
+
+// splits a world space voxel position into the chunk containing it and the position inside that chunk
+pub fn split_world_position(position: IVec3) -> (IVec3, UVec3) {
+    let size = CHUNK_SIZE as i32;
+    let chunk = IVec3::new(position.x.div_euclid(size), position.y.div_euclid(size), position.z.div_euclid(size));
+    let local = position - chunk * size;
+    (chunk, local.as_uvec3())
+}
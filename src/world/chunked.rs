@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use glam::{IVec3, UVec3};
+use serde::{Deserialize, Serialize};
+
+use crate::io::ChunkArchive;
+
+use super::chunk::{split_world_position, Chunk, CHUNK_SIZE};
+use super::material::{is_solid, Material, AIR};
+use super::VoxelStorage;
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ChunkedWorld {
+    chunks: HashMap<IVec3, Chunk>,
+}
+
+impl ChunkedWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        self.chunks.insert(chunk.position(), chunk)
+    }
+    pub fn remove_chunk(&mut self, position: IVec3) -> Option<Chunk> {
+        self.chunks.remove(&position)
+    }
+    pub fn chunk(&self, position: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&position)
+    }
+    pub fn chunk_mut(&mut self, position: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&position)
+    }
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+    pub fn get(&self, position: IVec3) -> Material {
+        let (chunk, local) = split_world_position(position);
+        self.chunks.get(&chunk).map_or(AIR, |chunk| chunk.get(local))
+    }
+    pub fn is_solid(&self, position: IVec3) -> bool {
+        is_solid(self.get(position))
+    }
+    // allocates the containing chunk when anything but air is written to a chunk that does not exist yet
+    pub fn set(&mut self, position: IVec3, material: Material) {
+        let (chunk, local) = split_world_position(position);
+        if let Some(existing) = self.chunks.get_mut(&chunk) {
+            existing.set(local, material);
+        } else if material != AIR {
+            self.chunks.entry(chunk).or_insert_with(|| Chunk::new(chunk)).set(local, material);
+        }
+    }
+
+    // writes the solid voxels of every chunk to another storage, shifted by `offset`
+    pub fn copy_into(&self, world: &mut impl VoxelStorage, offset: IVec3) {
+        for chunk in self.chunks.values() {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for x in 0..CHUNK_SIZE {
+                        let local = UVec3::new(x, y, z);
+                        if chunk.is_solid(local) {
+                            world.set_material(chunk.origin() + local.as_ivec3() + offset, chunk.get(local));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // writes every chunk, chunks that are in the archive but not in the world are left untouched
+    pub fn save_to_archive(&self, archive: &mut ChunkArchive) -> Result<()> {
+        for chunk in self.chunks.values() {
+            archive.write(chunk.position(), chunk)?;
+        }
+        archive.flush()
+    }
+
+    // loads the chunks in [min, max) that exist in the archive, returns how many were loaded
+    pub fn load_region(&mut self, archive: &mut ChunkArchive, min: IVec3, max: IVec3) -> Result<usize> {
+        let positions: Vec<IVec3> = archive
+            .positions()
+            .into_iter()
+            .filter(|position| position.cmpge(min).all() && position.cmplt(max).all())
+            .collect();
+        for &position in &positions {
+            if let Some(chunk) = archive.read::<Chunk>(position)? {
+                self.insert_chunk(chunk);
+            }
+        }
+        Ok(positions.len())
+    }
+}
+
+impl VoxelStorage for ChunkedWorld {
+    fn get_material(&self, position: IVec3) -> Material {
+        self.get(position)
+    }
+    fn set_material(&mut self, position: IVec3, material: Material) {
+        self.set(position, material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::brickmap::Brickmap;
+    use crate::world::material::{STONE, WATER};
+
+    #[test]
+    fn water_is_stored_but_not_solid() {
+        let mut world = ChunkedWorld::new();
+        world.set(IVec3::new(1, 2, 3), WATER);
+        world.set(IVec3::new(40, 2, 3), STONE);
+        assert_eq!(world.chunk_count(), 2);
+        assert_eq!(world.get(IVec3::new(1, 2, 3)), WATER);
+        assert!(!world.is_solid(IVec3::new(1, 2, 3)));
+
+        let mut brickmap = Brickmap::new(UVec3::splat(8));
+        world.copy_into(&mut brickmap, IVec3::ZERO);
+        assert!(!brickmap.get_voxel(IVec3::new(1, 2, 3)));
+        assert!(brickmap.get_voxel(IVec3::new(40, 2, 3)));
+    }
+}
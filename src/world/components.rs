@@ -0,0 +1,180 @@
+use glam::{IVec3, UVec3};
+
+use super::morphology::Connectivity;
+use super::voxelized::MeshGridBitfield;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Component {
+    pub label: u32,
+    pub voxel_count: u32,
+    pub min: UVec3,
+    // exclusive
+    pub max: UVec3,
+    // the component touches the bottom layer of the grid, everything else is floating
+    pub grounded: bool,
+}
+
+pub struct ComponentLabels {
+    dimensions: UVec3,
+    // 0 for empty voxels, otherwise the index of the component + 1
+    labels: Vec<u32>,
+    components: Vec<Component>,
+}
+
+impl ComponentLabels {
+    pub fn components(&self) -> &[Component] {
+        &self.components
+    }
+    pub fn label(&self, position: UVec3) -> Option<u32> {
+        let index = position.x + position.y * self.dimensions.x + position.z * self.dimensions.x * self.dimensions.y;
+        match self.labels[index as usize] {
+            0 => None,
+            label => Some(label - 1),
+        }
+    }
+    pub fn floating(&self) -> impl Iterator<Item = &Component> {
+        self.components.iter().filter(|component| !component.grounded)
+    }
+    pub fn largest(&self) -> Option<&Component> {
+        self.components.iter().max_by_key(|component| component.voxel_count)
+    }
+    // grid containing only the voxels of one component
+    pub fn extract(&self, label: u32) -> MeshGridBitfield {
+        // the labels were created from a grid of the same size
+        let mut grid = MeshGridBitfield::new(&format!("component {}", label), self.dimensions).unwrap();
+        let component = &self.components[label as usize];
+        for z in component.min.z..component.max.z {
+            for y in component.min.y..component.max.y {
+                for x in component.min.x..component.max.x {
+                    let position = UVec3::new(x, y, z);
+                    if self.label(position) == Some(label) {
+                        grid.set_bit(position, true);
+                    }
+                }
+            }
+        }
+        grid
+    }
+}
+
+impl MeshGridBitfield {
+    pub fn label_components(&self, connectivity: Connectivity) -> ComponentLabels {
+        let d = self.dimensions();
+        let offsets = connectivity.offsets();
+        let mut labels = vec![0u32; (d.x * d.y * d.z) as usize];
+        let mut components = Vec::new();
+        let mut stack = Vec::new();
+        for start in self.solid_positions() {
+            if labels[self.linear_index(start) as usize] != 0 {
+                continue;
+            }
+            let label = components.len() as u32;
+            let mut component = Component {
+                label,
+                voxel_count: 0,
+                min: start,
+                max: start + 1,
+                grounded: false,
+            };
+            labels[self.linear_index(start) as usize] = label + 1;
+            stack.push(start);
+            while let Some(position) = stack.pop() {
+                component.voxel_count += 1;
+                component.min = component.min.min(position);
+                component.max = component.max.max(position + 1);
+                component.grounded |= position.y == 0;
+                for offset in &offsets {
+                    let neighbour = position.as_ivec3() + *offset;
+                    if neighbour.cmplt(IVec3::ZERO).any() || neighbour.cmpge(d.as_ivec3()).any() {
+                        continue;
+                    }
+                    let neighbour = neighbour.as_uvec3();
+                    let index = self.linear_index(neighbour) as usize;
+                    if labels[index] == 0 && self.get_bit(neighbour) {
+                        labels[index] = label + 1;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        ComponentLabels {
+            dimensions: d,
+            labels,
+            components,
+        }
+    }
+
+    // removes stray voxels and debris, returns how many components were removed
+    pub fn remove_small_components(&mut self, min_voxel_count: u32, connectivity: Connectivity) -> usize {
+        let labels = self.label_components(connectivity);
+        let mut removed = 0;
+        for component in labels.components().iter().filter(|component| component.voxel_count < min_voxel_count) {
+            for z in component.min.z..component.max.z {
+                for y in component.min.y..component.max.y {
+                    for x in component.min.x..component.max.x {
+                        let position = UVec3::new(x, y, z);
+                        if labels.label(position) == Some(component.label) {
+                            self.set_bit(position, false);
+                        }
+                    }
+                }
+            }
+            removed += 1;
+        }
+        removed
+    }
+
+    // every component cropped to its bounding box, together with the position of that box in this grid
+    pub fn split_components(&self, connectivity: Connectivity) -> Vec<(MeshGridBitfield, UVec3)> {
+        let labels = self.label_components(connectivity);
+        labels
+            .components()
+            .iter()
+            .map(|component| (labels.extract(component.label).crop(component.min, component.max), component.min))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_with(dimensions: UVec3, positions: &[(u32, u32, u32)]) -> MeshGridBitfield {
+        let mut grid = MeshGridBitfield::new("test", dimensions).unwrap();
+        for &(x, y, z) in positions {
+            grid.set_bit(UVec3::new(x, y, z), true);
+        }
+        grid
+    }
+
+    #[test]
+    fn components_are_split_and_classified() {
+        // a grounded pillar, a floating pair and a voxel only connected to the pair by a corner
+        let grid = grid_with(UVec3::new(8, 8, 8), &[(0, 0, 0), (0, 1, 0), (0, 2, 0), (4, 4, 4), (5, 4, 4), (6, 5, 5)]);
+
+        let labels = grid.label_components(Connectivity::Six);
+        assert_eq!(labels.components().len(), 3);
+        assert_eq!(labels.floating().count(), 2);
+        assert_eq!(labels.largest().unwrap().voxel_count, 3);
+        assert_eq!(labels.label(UVec3::new(4, 4, 4)), labels.label(UVec3::new(5, 4, 4)));
+        assert_eq!(labels.label(UVec3::new(1, 1, 1)), None);
+        assert_eq!(grid.label_components(Connectivity::TwentySix).components().len(), 2);
+
+        let parts = grid.split_components(Connectivity::Six);
+        let (pair, position) = parts.iter().find(|(_, position)| *position == UVec3::new(4, 4, 4)).unwrap();
+        assert_eq!(*position, UVec3::new(4, 4, 4));
+        assert_eq!(pair.dimensions(), UVec3::new(2, 1, 1));
+        assert_eq!(pair.solid_positions().count(), 2);
+        let total: usize = parts.iter().map(|(part, _)| part.solid_positions().count()).sum();
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn small_components_are_removed() {
+        let mut grid = grid_with(UVec3::new(8, 8, 8), &[(0, 0, 0), (0, 1, 0), (0, 2, 0), (4, 4, 4), (6, 6, 6)]);
+        assert_eq!(grid.remove_small_components(2, Connectivity::Six), 2);
+        assert_eq!(grid.solid_positions().count(), 3);
+        assert!(grid.get_bit(UVec3::new(0, 2, 0)));
+    }
+}
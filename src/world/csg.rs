@@ -0,0 +1,226 @@
+use glam::{ivec3, IVec3};
+
+use super::chunk::{split_world_position, CHUNK_SIZE};
+use super::chunked::ChunkedWorld;
+use super::material::AIR;
+use super::voxelized::MeshGridBitfield;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BooleanOp {
+    Union,
+    Intersection,
+    Difference,
+    Xor,
+}
+
+impl BooleanOp {
+    pub fn apply(&self, a: u32, b: u32) -> u32 {
+        match self {
+            BooleanOp::Union => a | b,
+            BooleanOp::Intersection => a & b,
+            BooleanOp::Difference => a & !b,
+            BooleanOp::Xor => a ^ b,
+        }
+    }
+}
+
+impl MeshGridBitfield {
+    // the result keeps the dimensions of `self`, voxel p of `other` lands on voxel p + offset of `self`
+    // and everything of `other` outside of `self` is clipped away
+    pub fn boolean(&self, other: &MeshGridBitfield, offset: IVec3, op: BooleanOp) -> MeshGridBitfield {
+        let mut result = self.clone();
+        result.boolean_in_place(other, offset, op);
+        result
+    }
+
+    pub fn boolean_in_place(&mut self, other: &MeshGridBitfield, offset: IVec3, op: BooleanOp) {
+        let dimensions = self.dimensions().as_ivec3();
+        let other_dimensions = other.dimensions().as_ivec3();
+        for z in 0..dimensions.z {
+            for y in 0..dimensions.y {
+                let other_row = ivec3(0, y, z) - offset;
+                let row_valid = other_row.y >= 0 && other_row.y < other_dimensions.y && other_row.z >= 0 && other_row.z < other_dimensions.z;
+                let row_start = self.linear_index(ivec3(0, y, z).as_uvec3());
+                let other_row_start = if row_valid {
+                    other.linear_index(ivec3(0, other_row.y, other_row.z).as_uvec3())
+                } else {
+                    0
+                };
+
+                // walk the row in segments that never cross a word boundary of `self`
+                let mut x = 0;
+                while x < dimensions.x {
+                    let bit_index = row_start + x as u32;
+                    let count = (32 - bit_index % 32).min((dimensions.x - x) as u32);
+                    let other_bits = if row_valid {
+                        read_row_segment(other, other_row_start, x - offset.x, count, other_dimensions.x)
+                    } else {
+                        0
+                    };
+                    let bits = self.read_bits(bit_index, count);
+                    self.write_bits(bit_index, count, op.apply(bits, other_bits));
+                    x += count as i32;
+                }
+            }
+        }
+    }
+}
+
+// reads `count` bits of a row starting at `x`, bits outside of [0, width) read as empty
+fn read_row_segment(grid: &MeshGridBitfield, row_start: u32, x: i32, count: u32, width: i32) -> u32 {
+    let start = x.max(0);
+    let end = (x + count as i32).min(width);
+    if start >= end {
+        return 0;
+    }
+    grid.read_bits(row_start + start as u32, (end - start) as u32) << (start - x)
+}
+
+impl ChunkedWorld {
+    // voxel p of `other` lands on voxel p + offset of `self`, where both are solid `self` keeps its material.
+    // Rows of a chunk are one word, they are combined as bitmasks and only voxels that change are written
+    pub fn boolean_in_place(&mut self, other: &ChunkedWorld, offset: IVec3, op: BooleanOp) {
+        let size = CHUNK_SIZE as i32;
+        let mut affected: Vec<IVec3> = match op {
+            // these can only clear voxels of `self`, so only existing chunks change
+            BooleanOp::Intersection | BooleanOp::Difference => self.chunks().map(|chunk| chunk.position()).collect(),
+            // these can add voxels to every chunk `other` overlaps
+            BooleanOp::Union | BooleanOp::Xor => other
+                .chunks()
+                .flat_map(|chunk| {
+                    let min = split_world_position(chunk.origin() + offset).0;
+                    let max = split_world_position(chunk.origin() + offset + size - 1).0;
+                    (min.z..=max.z).flat_map(move |z| (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| ivec3(x, y, z))))
+                })
+                .collect(),
+        };
+        affected.sort_by_key(|position| (position.z, position.y, position.x));
+        affected.dedup();
+
+        for chunk_position in affected {
+            let origin = chunk_position * size;
+            for z in 0..size {
+                for y in 0..size {
+                    let bits = self.chunk(chunk_position).map_or(0, |chunk| chunk.solid_row(y as u32, z as u32));
+                    let other_bits = solid_row_at(other, origin + ivec3(0, y, z) - offset);
+                    let result = op.apply(bits, other_bits);
+                    let mut changed = bits ^ result;
+                    while changed != 0 {
+                        let x = changed.trailing_zeros() as i32;
+                        changed &= changed - 1;
+                        let position = origin + ivec3(x, y, z);
+                        // set bits were empty in `self`, so they come from `other`
+                        let material = if result & (1 << x) != 0 { other.get(position - offset) } else { AIR };
+                        self.set(position, material);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the solid bits of the CHUNK_SIZE voxels starting at `start` along x, which can span two chunks
+fn solid_row_at(world: &ChunkedWorld, start: IVec3) -> u32 {
+    let (chunk, local) = split_world_position(start);
+    let row = |chunk: IVec3| world.chunk(chunk).map_or(0, |chunk| chunk.solid_row(local.y, local.z));
+    let bits = row(chunk) >> local.x;
+    if local.x == 0 {
+        bits
+    } else {
+        bits | (row(chunk + IVec3::X) << (CHUNK_SIZE - local.x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::material::{is_solid, Material};
+    use crate::world::noise::hash_3d;
+    use glam::UVec3;
+
+    const OPS: [BooleanOp; 4] = [BooleanOp::Union, BooleanOp::Intersection, BooleanOp::Difference, BooleanOp::Xor];
+    // offsets that cross word and chunk boundaries in both directions
+    const OFFSETS: [IVec3; 6] = [
+        ivec3(0, 0, 0),
+        ivec3(1, 0, 0),
+        ivec3(-7, 2, 1),
+        ivec3(13, -3, 2),
+        ivec3(33, 1, -1),
+        ivec3(-45, 31, 40),
+    ];
+
+    fn for_each_position(min: IVec3, max: IVec3, mut f: impl FnMut(IVec3)) {
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    f(ivec3(x, y, z));
+                }
+            }
+        }
+    }
+
+    fn random_bitfield(seed: u32, dimensions: UVec3) -> MeshGridBitfield {
+        let mut bitfield = MeshGridBitfield::new("random", dimensions).unwrap();
+        for_each_position(IVec3::ZERO, dimensions.as_ivec3(), |p| {
+            bitfield.set_bit(p.as_uvec3(), hash_3d(seed, p.x, p.y, p.z).is_multiple_of(2));
+        });
+        bitfield
+    }
+
+    #[test]
+    fn bitfield_booleans_match_per_voxel_booleans() {
+        // widths that are not a multiple of the word size, so rows start in the middle of words
+        let a = random_bitfield(1, UVec3::new(37, 5, 3));
+        let b = random_bitfield(2, UVec3::new(70, 4, 6));
+        for op in OPS {
+            for offset in OFFSETS {
+                let result = a.boolean(&b, offset, op);
+                for_each_position(IVec3::ZERO, a.dimensions().as_ivec3(), |p| {
+                    let expected = op.apply(a.is_solid(p) as u32, b.is_solid(p - offset) as u32) != 0;
+                    assert_eq!(result.is_solid(p), expected, "{:?} {} {}", op, offset, p);
+                });
+            }
+        }
+    }
+
+    fn random_world(seed: u32, min: IVec3, max: IVec3) -> ChunkedWorld {
+        let mut world = ChunkedWorld::new();
+        for_each_position(min, max, |p| {
+            let hash = hash_3d(seed, p.x, p.y, p.z);
+            if hash.is_multiple_of(3) {
+                world.set(p, 1 + (hash >> 8) as Material % 4);
+            }
+        });
+        world
+    }
+
+    #[test]
+    fn chunked_booleans_match_per_voxel_booleans() {
+        let (a_min, a_max) = (ivec3(-20, -3, 0), ivec3(40, 2, 3));
+        let (b_min, b_max) = (ivec3(-10, -8, -40), ivec3(50, 0, 0));
+        let b = random_world(4, b_min, b_max);
+        for op in OPS {
+            for offset in OFFSETS {
+                let mut result = random_world(3, a_min, a_max);
+                result.boolean_in_place(&b, offset, op);
+
+                let mut expected = random_world(3, a_min, a_max);
+                let (min, max) = (a_min.min(b_min + offset) - 1, a_max.max(b_max + offset) + 1);
+                for_each_position(min, max, |p| {
+                    let material = expected.get(p);
+                    let other_material = b.get(p - offset);
+                    let material = match op {
+                        BooleanOp::Union if !is_solid(material) => other_material,
+                        BooleanOp::Intersection if !is_solid(other_material) => AIR,
+                        BooleanOp::Difference if is_solid(other_material) => AIR,
+                        BooleanOp::Xor if is_solid(material) == is_solid(other_material) => AIR,
+                        BooleanOp::Xor if !is_solid(material) => other_material,
+                        _ => material,
+                    };
+                    expected.set(p, material);
+                });
+                for_each_position(min, max, |p| assert_eq!(result.get(p), expected.get(p), "{:?} {} {}", op, offset, p));
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use std::ops::Range;
+
+// consecutive runs of sorted unique indices
+pub fn index_ranges(indices: impl IntoIterator<Item = u32>) -> Vec<Range<u32>> {
+    let mut ranges: Vec<Range<u32>> = Vec::new();
+    for index in indices {
+        match ranges.last_mut() {
+            Some(range) if range.end == index => range.end += 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+    ranges
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+
+use glam::{uvec3, IVec3, UVec3};
+use serde::{Deserialize, Serialize};
+
+use super::morphology::Connectivity;
+use super::voxelized::MeshGridBitfield;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistanceMetric {
+    Euclidean,
+    Chebyshev,
+}
+
+// distance from every voxel center to the nearest solid voxel center in voxels, rounded down and clamped to
+// 255, solid voxels store 0
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DistanceField {
+    dimensions: UVec3,
+    metric: DistanceMetric,
+    distances: Vec<u8>,
+}
+
+impl DistanceField {
+    pub fn from_bitfield(grid: &MeshGridBitfield, metric: DistanceMetric) -> Self {
+        let distances = match metric {
+            DistanceMetric::Euclidean => squared_euclidean_distance_transform(grid)
+                .into_iter()
+                .map(|squared| (squared as f64).sqrt().min(255.0) as u8)
+                .collect(),
+            DistanceMetric::Chebyshev => chebyshev_distance_transform(grid)
+                .into_iter()
+                .map(|distance| distance.min(255) as u8)
+                .collect(),
+        };
+        Self {
+            dimensions: grid.dimensions(),
+            metric,
+            distances,
+        }
+    }
+    pub fn dimensions(&self) -> UVec3 {
+        self.dimensions
+    }
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+    pub fn get(&self, position: UVec3) -> u8 {
+        self.distances[(position.x + position.y * self.dimensions.x + position.z * self.dimensions.x * self.dimensions.y) as usize]
+    }
+    pub fn distances(&self) -> &[u8] {
+        &self.distances
+    }
+
+    // smallest distance of every brick of `brick_size`^3 voxels, x major like the voxel data itself
+    pub fn brick_distances(&self, brick_size: u32) -> (UVec3, Vec<u8>) {
+        let bricks = (self.dimensions + brick_size - 1) / brick_size;
+        let mut distances = vec![u8::MAX; (bricks.x * bricks.y * bricks.z) as usize];
+        for z in 0..self.dimensions.z {
+            for y in 0..self.dimensions.y {
+                for x in 0..self.dimensions.x {
+                    let brick = uvec3(x, y, z) / brick_size;
+                    let index = (brick.x + brick.y * bricks.x + brick.z * bricks.x * bricks.y) as usize;
+                    distances[index] = distances[index].min(self.get(uvec3(x, y, z)));
+                }
+            }
+        }
+        (bricks, distances)
+    }
+}
+
+// exact squared euclidean distance to the nearest solid voxel, separable over the three axes
+// (Felzenszwalb and Huttenlocher, "Distance Transforms of Sampled Functions")
+pub fn squared_euclidean_distance_transform(grid: &MeshGridBitfield) -> Vec<u32> {
+    let d = grid.dimensions();
+    let infinity = i64::MAX / 4;
+    let mut field: Vec<i64> = (0..d.x * d.y * d.z)
+        .map(|index| {
+            let position = uvec3(index % d.x, (index / d.x) % d.y, index / (d.x * d.y));
+            if grid.get_bit(position) {
+                0
+            } else {
+                infinity
+            }
+        })
+        .collect();
+
+    let strides = [1, d.x as usize, (d.x * d.y) as usize];
+    let lengths = [d.x as usize, d.y as usize, d.z as usize];
+    let mut line = Vec::new();
+    let mut transformed = Vec::new();
+    for axis in 0..3 {
+        let (length, stride) = (lengths[axis], strides[axis]);
+        if length == 0 {
+            continue;
+        }
+        // every line along `axis` starts at a position where that axis is 0
+        for start in 0..field.len() {
+            if (start / stride) % length != 0 {
+                continue;
+            }
+            line.clear();
+            line.extend((0..length).map(|i| field[start + i * stride]));
+            distance_transform_1d(&line, &mut transformed, infinity);
+            for (i, value) in transformed.iter().enumerate() {
+                field[start + i * stride] = *value;
+            }
+        }
+    }
+    field.into_iter().map(|value| value.min(u32::MAX as i64) as u32).collect()
+}
+
+// lower envelope of the parabolas rooted at every sample
+fn distance_transform_1d(f: &[i64], result: &mut Vec<i64>, infinity: i64) {
+    let n = f.len();
+    result.clear();
+    result.resize(n, infinity);
+    let mut roots = vec![0usize; n];
+    let mut boundaries = vec![0f64; n + 1];
+    let mut k = 0;
+    let first = f.iter().position(|&value| value < infinity);
+    let Some(first) = first else {
+        return;
+    };
+    roots[0] = first;
+    boundaries[0] = f64::NEG_INFINITY;
+    boundaries[1] = f64::INFINITY;
+    let intersection = |q: usize, v: usize| {
+        let (q_i, v_i) = (q as i64, v as i64);
+        ((f[q] + q_i * q_i) - (f[v] + v_i * v_i)) as f64 / (2 * (q_i - v_i)) as f64
+    };
+    for (q, &value) in f.iter().enumerate().skip(first + 1) {
+        if value >= infinity {
+            continue;
+        }
+        let mut s = intersection(q, roots[k]);
+        while s <= boundaries[k] {
+            k -= 1;
+            s = intersection(q, roots[k]);
+        }
+        k += 1;
+        roots[k] = q;
+        boundaries[k] = s;
+        boundaries[k + 1] = f64::INFINITY;
+    }
+    k = 0;
+    for (q, value) in result.iter_mut().enumerate() {
+        while boundaries[k + 1] < q as f64 {
+            k += 1;
+        }
+        let offset = q as i64 - roots[k] as i64;
+        *value = offset * offset + f[roots[k]];
+    }
+}
+
+// exact chebyshev distance, a breadth first search over vertex neighbours visits voxels in chebyshev order
+pub fn chebyshev_distance_transform(grid: &MeshGridBitfield) -> Vec<u32> {
+    let d = grid.dimensions();
+    let mut field = vec![u32::MAX; (d.x * d.y * d.z) as usize];
+    let mut queue = VecDeque::new();
+    for position in grid.solid_positions() {
+        field[grid.linear_index(position) as usize] = 0;
+        queue.push_back(position);
+    }
+    let offsets = Connectivity::TwentySix.offsets();
+    while let Some(position) = queue.pop_front() {
+        let distance = field[grid.linear_index(position) as usize];
+        for offset in &offsets {
+            let neighbour = position.as_ivec3() + *offset;
+            if neighbour.cmplt(IVec3::ZERO).any() || neighbour.cmpge(d.as_ivec3()).any() {
+                continue;
+            }
+            let index = grid.linear_index(neighbour.as_uvec3()) as usize;
+            if field[index] == u32::MAX {
+                field[index] = distance + 1;
+                queue.push_back(neighbour.as_uvec3());
+            }
+        }
+    }
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::noise::hash_3d;
+
+    // random grids of different shapes, from empty to dense
+    fn random_grids() -> impl Iterator<Item = MeshGridBitfield> {
+        (0..40u32).map(|seed| {
+            let dimensions = uvec3(1 + seed % 9, 1 + seed * 7 % 6, 1 + seed * 5 % 8);
+            let density = seed % 5;
+            let mut grid = MeshGridBitfield::new("random", dimensions).unwrap();
+            for z in 0..dimensions.z {
+                for y in 0..dimensions.y {
+                    for x in 0..dimensions.x {
+                        let solid = hash_3d(seed, x as i32, y as i32, z as i32) % 16 < density;
+                        grid.set_bit(uvec3(x, y, z), solid);
+                    }
+                }
+            }
+            grid
+        })
+    }
+
+    // compares every voxel with every solid voxel
+    fn brute_force(grid: &MeshGridBitfield, distance: impl Fn(IVec3) -> u32) -> Vec<u32> {
+        let d = grid.dimensions();
+        let solid: Vec<UVec3> = grid.solid_positions().collect();
+        (0..d.x * d.y * d.z)
+            .map(|index| {
+                let position = uvec3(index % d.x, (index / d.x) % d.y, index / (d.x * d.y));
+                solid
+                    .iter()
+                    .map(|other| distance(position.as_ivec3() - other.as_ivec3()))
+                    .min()
+                    .unwrap_or(u32::MAX)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn euclidean_transform_matches_brute_force() {
+        for grid in random_grids() {
+            let expected = brute_force(&grid, |offset| offset.length_squared() as u32);
+            assert_eq!(squared_euclidean_distance_transform(&grid), expected, "{}", grid.dimensions());
+        }
+    }
+
+    #[test]
+    fn chebyshev_transform_matches_brute_force() {
+        for grid in random_grids() {
+            let expected = brute_force(&grid, |offset| offset.abs().max_element() as u32);
+            assert_eq!(chebyshev_distance_transform(&grid), expected, "{}", grid.dimensions());
+        }
+    }
+
+    #[test]
+    fn distances_are_rounded_down_per_brick() {
+        let mut grid = MeshGridBitfield::new("line", uvec3(8, 1, 1)).unwrap();
+        grid.set_bit(UVec3::ZERO, true);
+        let field = DistanceField::from_bitfield(&grid, DistanceMetric::Euclidean);
+        assert_eq!(field.distances(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(field.brick_distances(4), (uvec3(2, 1, 1), vec![0, 4]));
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::VecDeque;
+
+use anyhow::{bail, Result};
+use glam::{IVec3, Vec3};
+
+use super::material::{is_solid, Material, AIR};
+use super::raycast::Dda;
+use super::VoxelStorage;
+
+// box of voxels [min, max) touched by an edit, only this part of the world has to be uploaded again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRegion {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl DirtyRegion {
+    pub fn from_voxel(position: IVec3) -> Self {
+        Self {
+            min: position,
+            max: position + 1,
+        }
+    }
+    pub fn union(&self, other: &DirtyRegion) -> DirtyRegion {
+        DirtyRegion {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+    pub fn volume(&self) -> i64 {
+        let size = (self.max - self.min).max(IVec3::ZERO).as_i64vec3();
+        size.x * size.y * size.z
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Brush {
+    Sphere { radius: f32 },
+    Box { half_extents: IVec3 },
+    // upright cylinder along the y axis
+    Cylinder { radius: f32, half_height: i32 },
+}
+
+impl Brush {
+    pub fn positions(&self, center: IVec3) -> Vec<IVec3> {
+        let extent = match self {
+            Brush::Sphere { radius } => IVec3::splat(radius.ceil() as i32),
+            Brush::Box { half_extents } => *half_extents,
+            Brush::Cylinder { radius, half_height } => IVec3::new(radius.ceil() as i32, *half_height, radius.ceil() as i32),
+        };
+        let mut positions = Vec::new();
+        for z in -extent.z..=extent.z {
+            for y in -extent.y..=extent.y {
+                for x in -extent.x..=extent.x {
+                    let offset = IVec3::new(x, y, z);
+                    let inside = match self {
+                        Brush::Sphere { radius } => offset.as_vec3().length() <= *radius,
+                        Brush::Box { .. } => true,
+                        Brush::Cylinder { radius, .. } => Vec3::new(x as f32, 0.0, z as f32).length() <= *radius,
+                    };
+                    if inside {
+                        positions.push(center + offset);
+                    }
+                }
+            }
+        }
+        positions
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOperation {
+    Place(Material),
+    Remove,
+    // changes the material of voxels that are already solid
+    Paint(Material),
+}
+
+impl EditOperation {
+    fn apply(&self, current: Material) -> Material {
+        match *self {
+            EditOperation::Place(material) => material,
+            EditOperation::Remove => AIR,
+            EditOperation::Paint(material) if is_solid(current) => material,
+            EditOperation::Paint(_) => current,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct VoxelChange {
+    position: IVec3,
+    before: Material,
+    after: Material,
+}
+
+// a recorded edit, it stores the previous material of every voxel it changed so it can be inverted exactly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditCommand {
+    changes: Vec<VoxelChange>,
+    region: DirtyRegion,
+}
+
+impl EditCommand {
+    pub fn region(&self) -> DirtyRegion {
+        self.region
+    }
+    pub fn changed_voxels(&self) -> usize {
+        self.changes.len()
+    }
+    fn apply(&self, world: &mut impl VoxelStorage) {
+        for change in &self.changes {
+            world.set_material(change.position, change.after);
+        }
+    }
+    fn revert(&self, world: &mut impl VoxelStorage) {
+        for change in self.changes.iter().rev() {
+            world.set_material(change.position, change.before);
+        }
+    }
+}
+
+pub struct EditHistory {
+    undo_stack: VecDeque<EditCommand>,
+    redo_stack: Vec<EditCommand>,
+    // oldest commands are dropped once the history grows beyond this
+    max_commands: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl EditHistory {
+    pub fn new(max_commands: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            max_commands,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+    // for when the world was changed without going through the history, undoing would write back stale voxels
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    pub fn set_voxel(&mut self, world: &mut impl VoxelStorage, position: IVec3, material: Material) -> Result<Option<DirtyRegion>> {
+        self.edit(world, &[position], EditOperation::Place(material))
+    }
+
+    pub fn clear_voxel(&mut self, world: &mut impl VoxelStorage, position: IVec3) -> Result<Option<DirtyRegion>> {
+        self.edit(world, &[position], EditOperation::Remove)
+    }
+
+    pub fn brush(&mut self, world: &mut impl VoxelStorage, center: IVec3, brush: Brush, operation: EditOperation) -> Result<Option<DirtyRegion>> {
+        self.edit(world, &brush.positions(center), operation)
+    }
+
+    // stamps the brush on every voxel of the line from `from` to `to`, as a single undoable edit
+    pub fn line(
+        &mut self,
+        world: &mut impl VoxelStorage,
+        from: IVec3,
+        to: IVec3,
+        brush: Brush,
+        operation: EditOperation,
+    ) -> Result<Option<DirtyRegion>> {
+        let mut positions: Vec<IVec3> = line_voxels(from, to).into_iter().flat_map(|voxel| brush.positions(voxel)).collect();
+        positions.sort_by_key(|position| (position.z, position.y, position.x));
+        positions.dedup();
+        self.edit(world, &positions, operation)
+    }
+
+    // applies `operation` to every position, nothing is recorded when no voxel changed
+    pub fn edit(&mut self, world: &mut impl VoxelStorage, positions: &[IVec3], operation: EditOperation) -> Result<Option<DirtyRegion>> {
+        if matches!(operation, EditOperation::Paint(_)) && !world.stores_materials() {
+            bail!("this world does not store materials, so it can not be painted");
+        }
+        let mut changes = Vec::new();
+        let mut region: Option<DirtyRegion> = None;
+        for &position in positions {
+            let before = world.get_material(position);
+            let after = operation.apply(before);
+            if before == after {
+                continue;
+            }
+            world.set_material(position, after);
+            // bounded storages ignore writes outside of them
+            let after = world.get_material(position);
+            if before == after {
+                continue;
+            }
+            changes.push(VoxelChange { position, before, after });
+            let voxel = DirtyRegion::from_voxel(position);
+            region = Some(region.map_or(voxel, |region| region.union(&voxel)));
+        }
+        let Some(region) = region else {
+            return Ok(None);
+        };
+        self.undo_stack.push_back(EditCommand { changes, region });
+        if self.undo_stack.len() > self.max_commands {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+        Ok(Some(region))
+    }
+
+    pub fn undo(&mut self, world: &mut impl VoxelStorage) -> Option<DirtyRegion> {
+        let command = self.undo_stack.pop_back()?;
+        command.revert(world);
+        let region = command.region;
+        self.redo_stack.push(command);
+        Some(region)
+    }
+
+    pub fn redo(&mut self, world: &mut impl VoxelStorage) -> Option<DirtyRegion> {
+        let command = self.redo_stack.pop()?;
+        command.apply(world);
+        let region = command.region;
+        self.undo_stack.push_back(command);
+        Some(region)
+    }
+}
+
+// connected voxel line between the centers of two voxels, including both ends
+pub fn line_voxels(from: IVec3, to: IVec3) -> Vec<IVec3> {
+    if from == to {
+        return vec![from];
+    }
+    let mut dda = Dda::new(from.as_vec3() + 0.5, (to - from).as_vec3());
+    let mut voxels = vec![from];
+    let distance = (to - from).abs();
+    let max_steps = distance.x + distance.y + distance.z;
+    for _ in 0..max_steps {
+        dda.step();
+        voxels.push(dda.position);
+        if dda.position == to {
+            break;
+        }
+    }
+    voxels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunked::ChunkedWorld;
+    use crate::world::material::{GRASS, STONE};
+    use crate::world::voxelized::MeshGridBitfield;
+    use glam::UVec3;
+
+    #[test]
+    fn undo_and_redo_restore_every_voxel() {
+        let mut world = ChunkedWorld::new();
+        let mut history = EditHistory::default();
+        let brush = Brush::Sphere { radius: 2.0 };
+        history.brush(&mut world, IVec3::ZERO, brush, EditOperation::Place(STONE)).unwrap();
+        let region = history
+            .brush(&mut world, IVec3::new(1, 0, 0), brush, EditOperation::Paint(GRASS))
+            .unwrap()
+            .unwrap();
+        assert_eq!((region.min.x, region.max.x), (-1, 3));
+        assert_eq!(world.get(IVec3::new(2, 0, 0)), GRASS);
+        // painting only changes voxels that are already solid
+        assert_eq!(world.get(IVec3::new(3, 0, 0)), AIR);
+
+        history.undo(&mut world).unwrap();
+        assert_eq!(world.get(IVec3::new(2, 0, 0)), STONE);
+        history.undo(&mut world).unwrap();
+        assert!(world.chunks().all(|chunk| chunk.is_empty()));
+        assert!(!history.can_undo());
+        history.redo(&mut world).unwrap();
+        history.redo(&mut world).unwrap();
+        assert_eq!(world.get(IVec3::new(2, 0, 0)), GRASS);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn oldest_edits_are_dropped() {
+        let mut world = ChunkedWorld::new();
+        let mut history = EditHistory::new(3);
+        for x in 0..5 {
+            history.set_voxel(&mut world, IVec3::new(x, 0, 0), STONE).unwrap();
+        }
+        while history.undo(&mut world).is_some() {}
+        assert_eq!(world.get(IVec3::new(1, 0, 0)), STONE);
+        assert_eq!(world.get(IVec3::new(2, 0, 0)), AIR);
+        // edits that change nothing are not recorded
+        assert_eq!(history.clear_voxel(&mut world, IVec3::new(9, 0, 0)).unwrap(), None);
+    }
+
+    #[test]
+    fn cleared_history_leaves_the_world_alone() {
+        let mut world = ChunkedWorld::new();
+        let mut history = EditHistory::default();
+        history.set_voxel(&mut world, IVec3::ZERO, STONE).unwrap();
+        history.set_voxel(&mut world, IVec3::X, STONE).unwrap();
+        history.undo(&mut world).unwrap();
+        history.clear();
+        assert!(!history.can_undo() && !history.can_redo());
+        assert_eq!(history.undo(&mut world), None);
+        assert_eq!(history.redo(&mut world), None);
+        assert_eq!((world.get(IVec3::ZERO), world.get(IVec3::X)), (STONE, AIR));
+    }
+
+    #[test]
+    fn region_volume() {
+        let region = DirtyRegion::from_voxel(IVec3::new(-1, 0, 0)).union(&DirtyRegion::from_voxel(IVec3::new(0, 2, 3)));
+        assert_eq!(region.volume(), 2 * 3 * 4);
+        assert_eq!(
+            DirtyRegion {
+                min: IVec3::ONE,
+                max: IVec3::ZERO
+            }
+            .volume(),
+            0
+        );
+    }
+
+    #[test]
+    fn painting_needs_materials() {
+        let mut grid = MeshGridBitfield::new("grid", UVec3::splat(4)).unwrap();
+        let mut history = EditHistory::default();
+        let positions = [IVec3::ONE];
+        assert!(history.edit(&mut grid, &positions, EditOperation::Place(STONE)).unwrap().is_some());
+        assert!(history.edit(&mut grid, &positions, EditOperation::Paint(GRASS)).is_err());
+    }
+
+    #[test]
+    fn lines_are_connected() {
+        let (from, to) = (IVec3::new(-3, 1, 2), IVec3::new(7, -4, 5));
+        let voxels = line_voxels(from, to);
+        assert_eq!((voxels[0], *voxels.last().unwrap()), (from, to));
+        assert!(voxels.windows(2).all(|pair| (pair[1] - pair[0]).abs().max_element() == 1));
+
+        let mut world = ChunkedWorld::new();
+        let region = EditHistory::default().line(
+            &mut world,
+            from,
+            to,
+            Brush::Box { half_extents: IVec3::ZERO },
+            EditOperation::Place(STONE),
+        );
+        let region = region.unwrap().unwrap();
+        assert_eq!((region.min, region.max), (from.min(to), from.max(to) + 1));
+        assert!(voxels.iter().all(|&voxel| world.get(voxel) == STONE));
+    }
+}
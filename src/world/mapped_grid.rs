@@ -0,0 +1,159 @@
+use std::fs::File;
+
+use anyhow::{ensure, Context, Result};
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, UVec3};
+use memmap2::Mmap;
+
+use crate::io::{as_bytes, read_pod, read_pod_slice, slice_as_bytes, view_slice, write_to_file};
+
+use super::voxelized::{word_count, MeshGridBitfield};
+
+// Uncompressed grids that can be memory mapped, so large grids can be used without reading them into memory
+// first. The file is a `RawGridHeader` followed by the words of the grid in the layout of `MeshGridBitfield`
+const MAGIC: [u8; 4] = *b"SVWG";
+const VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable)]
+struct RawGridHeader {
+    magic: [u8; 4],
+    version: u32,
+    dimensions: UVec3,
+    word_count: u32,
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<RawGridHeader>();
+
+pub fn write_raw_grid(grid: &MeshGridBitfield, filename: &str) -> Result<()> {
+    let header = RawGridHeader {
+        magic: MAGIC,
+        version: VERSION,
+        dimensions: grid.dimensions(),
+        word_count: grid.data().len() as u32,
+    };
+    let mut bytes = as_bytes(&header).to_vec();
+    bytes.extend_from_slice(slice_as_bytes(grid.data()));
+    write_to_file(&bytes, filename)
+}
+
+// checks the header and returns the dimensions and the bytes of the words
+fn parse_raw_grid(bytes: &[u8]) -> Result<(UVec3, &[u8])> {
+    ensure!(bytes.len() >= HEADER_SIZE, "file is too small to be a raw grid");
+    let header: RawGridHeader = read_pod(&bytes[..HEADER_SIZE])?;
+    ensure!(header.magic == MAGIC, "not a raw grid file");
+    ensure!(
+        header.version <= VERSION,
+        "raw grid version {} is newer than the supported version {}",
+        header.version,
+        VERSION
+    );
+    let d = header.dimensions;
+    let expected_words = word_count(d)?;
+    ensure!(
+        header.word_count as usize == expected_words,
+        "header says {} words but a grid of {} needs {}",
+        header.word_count,
+        d,
+        expected_words
+    );
+    let words = &bytes[HEADER_SIZE..];
+    ensure!(
+        words.len() == header.word_count as usize * 4,
+        "file is truncated, expected {} bytes of grid data but found {}",
+        header.word_count as usize * 4,
+        words.len()
+    );
+    Ok((d, words))
+}
+
+// reads a raw grid into memory
+pub fn read_raw_grid(filename: &str, name: &str) -> Result<MeshGridBitfield> {
+    let bytes = std::fs::read(filename).with_context(|| format!("could not read from file: {}", filename))?;
+    let (dimensions, words) = parse_raw_grid(&bytes).with_context(|| format!("could not load raw grid: {}", filename))?;
+    MeshGridBitfield::from_data(name, dimensions, read_pod_slice(words)?)
+}
+
+// a raw grid file mapped into memory, pages are only read from disk when they are accessed
+pub struct MappedGrid {
+    map: Mmap,
+    dimensions: UVec3,
+}
+
+impl MappedGrid {
+    pub fn open(filename: &str) -> Result<Self> {
+        let file = File::open(filename).with_context(|| format!("could not open file: {}", filename))?;
+        // the map must not be modified while it is in use, which holds as long as nothing else writes the file
+        let map = unsafe { Mmap::map(&file) }.with_context(|| format!("could not map file: {}", filename))?;
+        let (dimensions, words) = parse_raw_grid(&map).with_context(|| format!("could not load raw grid: {}", filename))?;
+        // maps are page aligned and the header keeps the words aligned, check once so data() can not fail
+        view_slice::<u32>(words)?;
+        Ok(Self { map, dimensions })
+    }
+
+    pub fn dimensions(&self) -> UVec3 {
+        self.dimensions
+    }
+
+    pub fn data(&self) -> &[u32] {
+        view_slice(&self.map[HEADER_SIZE..]).unwrap()
+    }
+
+    // `position` has to be inside of the grid
+    fn get_bit(&self, position: UVec3) -> bool {
+        let d = self.dimensions;
+        let index = (position.x + position.y * d.x + position.z * d.x * d.y) as usize;
+        self.data()[index / 32] & (1 << (index % 32)) != 0
+    }
+
+    // positions outside of the grid are empty
+    pub fn is_solid(&self, position: IVec3) -> bool {
+        position.cmpge(IVec3::ZERO).all() && position.cmplt(self.dimensions.as_ivec3()).all() && self.get_bit(position.as_uvec3())
+    }
+
+    pub fn to_bitfield(&self, name: &str) -> MeshGridBitfield {
+        MeshGridBitfield::from_data(name, self.dimensions, self.data().to_vec()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_grids_match_the_written_grid() {
+        let path = std::env::temp_dir().join(format!("svw_mapped_grid_{}.raw", std::process::id()));
+        let filename = path.to_string_lossy().into_owned();
+        let mut grid = MeshGridBitfield::new("grid", UVec3::new(5, 3, 7)).unwrap();
+        for position in [UVec3::ZERO, UVec3::new(4, 2, 6), UVec3::new(1, 2, 3)] {
+            grid.set_bit(position, true);
+        }
+        write_raw_grid(&grid, &filename).unwrap();
+        let mapped = MappedGrid::open(&filename);
+        let read = read_raw_grid(&filename, "grid");
+        std::fs::remove_file(&filename).unwrap();
+
+        let mapped = mapped.unwrap();
+        assert!(read.unwrap() == grid);
+        assert!(mapped.to_bitfield("grid") == grid);
+        for z in -1..8 {
+            for y in -1..4 {
+                for x in -1..6 {
+                    let position = IVec3::new(x, y, z);
+                    assert_eq!(mapped.is_solid(position), grid.is_solid(position), "{}", position);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_headers_are_rejected() {
+        let header = RawGridHeader {
+            magic: MAGIC,
+            version: VERSION,
+            dimensions: UVec3::splat(u32::MAX),
+            word_count: 0,
+        };
+        assert!(parse_raw_grid(as_bytes(&header)).is_err());
+    }
+}
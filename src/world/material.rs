@@ -0,0 +1,31 @@
+pub type Material = u8;
+
+pub const AIR: Material = 0;
+pub const STONE: Material = 1;
+pub const DIRT: Material = 2;
+pub const GRASS: Material = 3;
+pub const SAND: Material = 4;
+pub const SNOW: Material = 5;
+pub const GRAVEL: Material = 6;
+pub const ICE: Material = 7;
+pub const WOOD: Material = 8;
+pub const LEAVES: Material = 9;
+pub const BRICK: Material = 10;
+pub const WATER: Material = 11;
+
+// indexed by material id
+pub const MATERIAL_NAMES: [&str; 12] = [
+    "air", "stone", "dirt", "grass", "sand", "snow", "gravel", "ice", "wood", "leaves", "brick", "water",
+];
+
+pub fn material_from_name(name: &str) -> Option<Material> {
+    MATERIAL_NAMES
+        .iter()
+        .position(|&material| material == name)
+        .map(|index| index as Material)
+}
+
+// water is stored like any other material but rays, queries and the occupancy bits pass through it
+pub fn is_solid(material: Material) -> bool {
+    material != AIR && material != WATER
+}
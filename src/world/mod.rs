@@ -1,2 +1,43 @@
 pub mod asset;
+pub mod brickmap;
+pub mod chunk;
+pub mod chunked;
+pub mod components;
+pub mod csg;
+pub mod dirty;
+pub mod distance_field;
+pub mod edit;
+pub mod mapped_grid;
+pub mod material;
+pub mod morphology;
+pub mod noise;
+pub mod occupancy;
+pub mod picking;
+pub mod query;
+pub mod raycast;
+pub mod sdf;
+pub mod sparse;
+pub mod statistics;
+pub mod structures;
+pub mod terrain;
+pub mod transform;
 pub mod voxelized;
+
+use glam::IVec3;
+use material::Material;
+
+// voxel access shared by the different world representations, storages without materials store every solid
+// voxel as solid and report it as `material::STONE`
+pub trait VoxelStorage {
+    fn get_material(&self, position: IVec3) -> Material;
+    // writes outside of a bounded storage are ignored
+    fn set_material(&mut self, position: IVec3, material: Material);
+
+    fn is_solid(&self, position: IVec3) -> bool {
+        material::is_solid(self.get_material(position))
+    }
+    // false for storages that only know whether a voxel is solid
+    fn stores_materials(&self) -> bool {
+        true
+    }
+}
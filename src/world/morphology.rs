@@ -0,0 +1,252 @@
+use glam::{ivec3, IVec3, UVec3};
+
+use super::csg::BooleanOp;
+use super::voxelized::MeshGridBitfield;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    // neighbours sharing a face
+    Six,
+    // neighbours sharing a face or an edge
+    Eighteen,
+    // neighbours sharing a face, an edge or a corner
+    TwentySix,
+}
+
+impl Connectivity {
+    pub fn offsets(&self) -> Vec<IVec3> {
+        let max_axes = match self {
+            Connectivity::Six => 1,
+            Connectivity::Eighteen => 2,
+            Connectivity::TwentySix => 3,
+        };
+        let mut offsets = Vec::new();
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let offset = ivec3(x, y, z);
+                    let axes = x.abs() + y.abs() + z.abs();
+                    if axes > 0 && axes <= max_axes {
+                        offsets.push(offset);
+                    }
+                }
+            }
+        }
+        offsets
+    }
+}
+
+// Voxels outside of the grid count as empty for every operation. Solid voxels on the border of the grid
+// therefore always erode, which keeps the shell of `hollow` closed where a model touches the border
+impl MeshGridBitfield {
+    // every shifted copy is combined word by word
+    pub fn dilate(&self, connectivity: Connectivity) -> MeshGridBitfield {
+        let mut result = self.clone();
+        for offset in connectivity.offsets() {
+            result.boolean_in_place(self, offset, BooleanOp::Union);
+        }
+        result
+    }
+
+    pub fn erode(&self, connectivity: Connectivity) -> MeshGridBitfield {
+        let mut result = self.clone();
+        for offset in connectivity.offsets() {
+            result.boolean_in_place(self, offset, BooleanOp::Intersection);
+        }
+        result
+    }
+
+    // removes features thinner than the structuring element
+    pub fn open(&self, connectivity: Connectivity) -> MeshGridBitfield {
+        self.erode(connectivity).dilate(connectivity)
+    }
+
+    // fills gaps and holes thinner than the structuring element
+    pub fn close(&self, connectivity: Connectivity) -> MeshGridBitfield {
+        self.dilate(connectivity).erode(connectivity)
+    }
+
+    // keeps a shell of `thickness` voxels along every surface and empties everything inside of it
+    pub fn hollow(&self, thickness: u32, connectivity: Connectivity) -> MeshGridBitfield {
+        if thickness == 0 {
+            return self.clone();
+        }
+        let mut interior = self.clone();
+        for _ in 0..thickness {
+            interior = interior.erode(connectivity);
+        }
+        self.boolean(&interior, IVec3::ZERO, BooleanOp::Difference)
+    }
+
+    // fills every empty region that can not be reached from the grid border through face connected empty voxels
+    pub fn fill_interior(&self) -> MeshGridBitfield {
+        let d = self.dimensions();
+        let mut outside = self.new_within(self.name(), d);
+        let mut stack = Vec::new();
+        let visit = |position: UVec3, outside: &mut MeshGridBitfield, stack: &mut Vec<UVec3>| {
+            if !self.get_bit(position) && !outside.get_bit(position) {
+                outside.set_bit(position, true);
+                stack.push(position);
+            }
+        };
+        for z in 0..d.z {
+            for y in 0..d.y {
+                for x in 0..d.x {
+                    let on_border = x == 0 || y == 0 || z == 0 || x == d.x - 1 || y == d.y - 1 || z == d.z - 1;
+                    if on_border {
+                        visit(UVec3::new(x, y, z), &mut outside, &mut stack);
+                    }
+                }
+            }
+        }
+        while let Some(position) = stack.pop() {
+            for offset in Connectivity::Six.offsets() {
+                let neighbour = position.as_ivec3() + offset;
+                if neighbour.cmpge(IVec3::ZERO).all() && neighbour.cmplt(d.as_ivec3()).all() {
+                    visit(neighbour.as_uvec3(), &mut outside, &mut stack);
+                }
+            }
+        }
+        // everything that is not outside is either solid or an enclosed cavity
+        let mut filled = self.new_within(self.name(), d);
+        filled.fill(true);
+        filled.boolean_in_place(&outside, IVec3::ZERO, BooleanOp::Difference);
+        filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::noise::hash_3d;
+
+    fn filled_box(dimensions: UVec3, min: UVec3, max: UVec3) -> MeshGridBitfield {
+        let mut bitfield = MeshGridBitfield::new("box", dimensions).unwrap();
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    bitfield.set_bit(UVec3::new(x, y, z), true);
+                }
+            }
+        }
+        bitfield
+    }
+
+    #[test]
+    fn border_voxels_erode() {
+        let full = filled_box(UVec3::splat(7), UVec3::ZERO, UVec3::splat(7));
+        let eroded = full.erode(Connectivity::Six);
+        assert_eq!(eroded.data(), filled_box(UVec3::splat(7), UVec3::ONE, UVec3::splat(6)).data());
+        // a box away from the border shrinks by the same amount
+        let inner = filled_box(UVec3::splat(9), UVec3::ONE, UVec3::splat(8));
+        assert_eq!(
+            inner.erode(Connectivity::Six).data(),
+            filled_box(UVec3::splat(9), UVec3::splat(2), UVec3::splat(7)).data()
+        );
+    }
+
+    #[test]
+    fn hollow_keeps_a_shell() {
+        let full = filled_box(UVec3::splat(7), UVec3::ZERO, UVec3::splat(7));
+        assert_eq!(full.hollow(0, Connectivity::Six).data(), full.data());
+        let shell = full.hollow(1, Connectivity::Six);
+        assert_eq!(shell.solid_positions().count(), 7 * 7 * 7 - 5 * 5 * 5);
+        assert!(!shell.get_bit(UVec3::splat(3)));
+        assert!(shell.get_bit(UVec3::new(0, 3, 3)));
+    }
+
+    // a voxel is in the dilation when any voxel at one of the offsets is solid, in the erosion when all are
+    fn brute_force(grid: &MeshGridBitfield, connectivity: Connectivity, dilate: bool) -> MeshGridBitfield {
+        let mut result = grid.clone();
+        let d = grid.dimensions();
+        for z in 0..d.z {
+            for y in 0..d.y {
+                for x in 0..d.x {
+                    let position = UVec3::new(x, y, z);
+                    let neighbours = connectivity
+                        .offsets()
+                        .into_iter()
+                        .map(|offset| grid.is_solid(position.as_ivec3() + offset));
+                    let solid = match dilate {
+                        true => grid.get_bit(position) || neighbours.into_iter().any(|solid| solid),
+                        false => grid.get_bit(position) && neighbours.into_iter().all(|solid| solid),
+                    };
+                    result.set_bit(position, solid);
+                }
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn connectivities_have_the_expected_neighbours() {
+        for (connectivity, count) in [(Connectivity::Six, 6), (Connectivity::Eighteen, 18), (Connectivity::TwentySix, 26)] {
+            let offsets = connectivity.offsets();
+            assert_eq!(offsets.len(), count);
+            assert!(offsets.iter().all(|offset| *offset != IVec3::ZERO && offset.abs().max_element() == 1));
+            // a single voxel grows into exactly its neighbourhood
+            let single = filled_box(UVec3::splat(5), UVec3::splat(2), UVec3::splat(3));
+            assert_eq!(single.dilate(connectivity).solid_positions().count(), count + 1);
+        }
+    }
+
+    #[test]
+    fn dilation_and_erosion_match_brute_force() {
+        for seed in 0..12u32 {
+            // one axis is longer than a word so shifts cross word boundaries
+            let mut axes = [3 + seed % 5, 2 + seed % 3, 33 + seed % 4];
+            axes.rotate_left(seed as usize % 3);
+            let dimensions = UVec3::from_array(axes);
+            let mut grid = MeshGridBitfield::new("random", dimensions).unwrap();
+            for position in (0..dimensions.x * dimensions.y * dimensions.z).filter(|i| !hash_3d(seed, *i as i32, 0, 0).is_multiple_of(3)) {
+                grid.set_bit(
+                    UVec3::new(
+                        position % dimensions.x,
+                        position / dimensions.x % dimensions.y,
+                        position / (dimensions.x * dimensions.y),
+                    ),
+                    true,
+                );
+            }
+            for connectivity in [Connectivity::Six, Connectivity::Eighteen, Connectivity::TwentySix] {
+                assert!(
+                    grid.dilate(connectivity) == brute_force(&grid, connectivity, true),
+                    "{} {:?}",
+                    dimensions,
+                    connectivity
+                );
+                assert!(
+                    grid.erode(connectivity) == brute_force(&grid, connectivity, false),
+                    "{} {:?}",
+                    dimensions,
+                    connectivity
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn opening_removes_spikes_and_closing_fills_notches() {
+        let solid_box = filled_box(UVec3::splat(11), UVec3::splat(3), UVec3::splat(8));
+        let mut spiked = solid_box.clone();
+        spiked.set_bit(UVec3::new(5, 8, 5), true);
+        assert!(spiked.open(Connectivity::TwentySix) == solid_box);
+
+        let mut notched = solid_box.clone();
+        notched.set_bit(UVec3::new(3, 5, 5), false);
+        assert!(notched.close(Connectivity::TwentySix) == solid_box);
+        assert!(solid_box.close(Connectivity::TwentySix) == solid_box);
+    }
+
+    #[test]
+    fn enclosed_cavities_are_filled() {
+        let solid_box = filled_box(UVec3::splat(9), UVec3::splat(1), UVec3::splat(8));
+        let shell = solid_box.hollow(1, Connectivity::Six);
+        assert!(shell.fill_interior() == solid_box);
+
+        // a hole in the shell connects the cavity to the outside
+        let mut open = shell.clone();
+        open.set_bit(UVec3::new(4, 7, 4), false);
+        assert!(open.fill_interior() == open);
+    }
+}
@@ -0,0 +1,164 @@
+use glam::{Vec2, Vec3};
+
+// same integer hash as wang_hash in shaders/common/random.hlsl
+pub fn wang_hash(seed: u32) -> u32 {
+    let mut seed = (seed ^ 61) ^ (seed >> 16);
+    seed = seed.wrapping_mul(9);
+    seed ^= seed >> 4;
+    seed = seed.wrapping_mul(0x27d4eb2d);
+    seed ^ (seed >> 15)
+}
+
+pub fn hash_2d(seed: u32, x: i32, y: i32) -> u32 {
+    wang_hash(seed ^ wang_hash((x as u32) ^ wang_hash(y as u32)))
+}
+
+pub fn hash_3d(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    wang_hash(seed ^ wang_hash((x as u32) ^ wang_hash((y as u32) ^ wang_hash(z as u32))))
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn gradient_2d(hash: u32, offset: Vec2) -> f32 {
+    const GRADIENTS: [Vec2; 8] = [
+        Vec2::new(1.0, 0.0),
+        Vec2::new(-1.0, 0.0),
+        Vec2::new(0.0, 1.0),
+        Vec2::new(0.0, -1.0),
+        Vec2::new(0.70710677, 0.70710677),
+        Vec2::new(-0.70710677, 0.70710677),
+        Vec2::new(0.70710677, -0.70710677),
+        Vec2::new(-0.70710677, -0.70710677),
+    ];
+    GRADIENTS[(hash & 7) as usize].dot(offset)
+}
+
+fn gradient_3d(hash: u32, offset: Vec3) -> f32 {
+    // the 12 cube edge directions, padded to 16 so the hash can be masked
+    const GRADIENTS: [Vec3; 16] = [
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 0.0),
+        Vec3::new(1.0, -1.0, 0.0),
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(1.0, 0.0, 1.0),
+        Vec3::new(-1.0, 0.0, 1.0),
+        Vec3::new(1.0, 0.0, -1.0),
+        Vec3::new(-1.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 1.0),
+        Vec3::new(0.0, -1.0, 1.0),
+        Vec3::new(0.0, 1.0, -1.0),
+        Vec3::new(0.0, -1.0, -1.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 1.0),
+        Vec3::new(0.0, -1.0, -1.0),
+    ];
+    GRADIENTS[(hash & 15) as usize].dot(offset)
+}
+
+// gradient noise in roughly [-1, 1]
+pub fn gradient_noise_2d(seed: u32, position: Vec2) -> f32 {
+    let cell = position.floor();
+    let offset = position - cell;
+    let (x, y) = (cell.x as i32, cell.y as i32);
+
+    let n00 = gradient_2d(hash_2d(seed, x, y), offset);
+    let n10 = gradient_2d(hash_2d(seed, x + 1, y), offset - Vec2::new(1.0, 0.0));
+    let n01 = gradient_2d(hash_2d(seed, x, y + 1), offset - Vec2::new(0.0, 1.0));
+    let n11 = gradient_2d(hash_2d(seed, x + 1, y + 1), offset - Vec2::new(1.0, 1.0));
+
+    let u = fade(offset.x);
+    let v = fade(offset.y);
+    lerp(lerp(n00, n10, u), lerp(n01, n11, u), v) * std::f32::consts::SQRT_2
+}
+
+// gradient noise in roughly [-1, 1]
+pub fn gradient_noise_3d(seed: u32, position: Vec3) -> f32 {
+    let cell = position.floor();
+    let offset = position - cell;
+    let (x, y, z) = (cell.x as i32, cell.y as i32, cell.z as i32);
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let hash = hash_3d(seed, x + dx, y + dy, z + dz);
+        gradient_3d(hash, offset - Vec3::new(dx as f32, dy as f32, dz as f32))
+    };
+
+    let u = fade(offset.x);
+    let v = fade(offset.y);
+    let w = fade(offset.z);
+    let bottom = lerp(lerp(corner(0, 0, 0), corner(1, 0, 0), u), lerp(corner(0, 1, 0), corner(1, 1, 0), u), v);
+    let top = lerp(lerp(corner(0, 0, 1), corner(1, 0, 1), u), lerp(corner(0, 1, 1), corner(1, 1, 1), u), v);
+    lerp(bottom, top, w)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FractalNoise {
+    pub seed: u32,
+    pub frequency: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+impl FractalNoise {
+    pub fn new(seed: u32, frequency: f32, octaves: u32) -> Self {
+        Self {
+            seed,
+            frequency,
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    // sum of octaves normalized back to roughly [-1, 1], zero octaves sample like one
+    pub fn sample_2d(&self, position: Vec2) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut normalization = 0.0;
+        for octave in 0..self.octaves.max(1) {
+            total += gradient_noise_2d(wang_hash(self.seed.wrapping_add(octave)), position * frequency) * amplitude;
+            normalization += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        total / normalization
+    }
+
+    pub fn sample_3d(&self, position: Vec3) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut normalization = 0.0;
+        for octave in 0..self.octaves.max(1) {
+            total += gradient_noise_3d(wang_hash(self.seed.wrapping_add(octave)), position * frequency) * amplitude;
+            normalization += amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+        total / normalization
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_octaves_sample_like_one() {
+        let (none, one) = (FractalNoise::new(3, 0.1, 0), FractalNoise::new(3, 0.1, 1));
+        for i in 0..32 {
+            let position = Vec3::new(i as f32 * 1.7, i as f32 * 0.3, -(i as f32));
+            assert_eq!(none.sample_3d(position), one.sample_3d(position));
+            assert_eq!(none.sample_2d(position.truncate()), one.sample_2d(position.truncate()));
+            assert!(none.sample_3d(position).is_finite());
+        }
+    }
+}
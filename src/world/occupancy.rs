@@ -0,0 +1,275 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, UVec3, Vec3};
+
+use super::raycast::{Dda, RayHit};
+use super::voxelized::MeshGridBitfield;
+
+// Every level marks a cell as occupied when any of the `reduction`^3 cells below it is occupied,
+// level 0 is a copy of the voxel grid itself.
+// CPU-side only for now: nothing uploads `gpu_levels`/`gpu_data` yet and the shaders still march the flat grid.
+pub struct OccupancyPyramid {
+    reduction: u32,
+    levels: Vec<MeshGridBitfield>,
+}
+
+// GPU layout: one `OccupancyLevelGpu` per level, finest first, followed by a single u32 buffer holding all levels
+// back to back. Cell (x, y, z) of a level is bit x + y * dimensions.x + z * dimensions.x * dimensions.y counted
+// from bit 0 of word `word_offset`, the same bit order as `MeshGridBitfield`.
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable, Debug, PartialEq)]
+pub struct OccupancyLevelGpu {
+    pub dimensions: UVec3,
+    pub word_offset: u32,
+}
+
+impl OccupancyPyramid {
+    pub fn from_bitfield(grid: &MeshGridBitfield, reduction: u32) -> Self {
+        assert!(reduction >= 2);
+        let mut levels = vec![grid.clone()];
+        while levels.last().unwrap().dimensions().max_element() > 1 {
+            let finer = levels.last().unwrap();
+            let dimensions = (finer.dimensions() + reduction - 1) / reduction;
+            let mut coarser = finer.new_within(&format!("{} level {}", grid.name(), levels.len()), dimensions);
+            for position in finer.solid_positions() {
+                coarser.set_bit(position / reduction, true);
+            }
+            levels.push(coarser);
+        }
+        Self { reduction, levels }
+    }
+
+    pub fn reduction(&self) -> u32 {
+        self.reduction
+    }
+    pub fn levels(&self) -> &[MeshGridBitfield] {
+        &self.levels
+    }
+    pub fn dimensions(&self) -> UVec3 {
+        self.levels[0].dimensions()
+    }
+
+    // updates the voxel and every level above it
+    pub fn set_voxel(&mut self, position: UVec3, value: bool) {
+        self.levels[0].set_bit(position, value);
+        let mut position = position;
+        for level in 1..self.levels.len() {
+            let parent = position / self.reduction;
+            let occupied = value || self.block_occupied(level - 1, parent);
+            if self.levels[level].get_bit(parent) == occupied {
+                break;
+            }
+            self.levels[level].set_bit(parent, occupied);
+            position = parent;
+        }
+    }
+
+    // rebuilds the levels above a box of voxels [min, max) that was edited directly in the grid
+    pub fn update_region(&mut self, grid: &MeshGridBitfield, min: UVec3, max: UVec3) {
+        assert_eq!(grid.dimensions(), self.dimensions());
+        let mut min = min;
+        let mut max = max.min(self.dimensions());
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    let position = UVec3::new(x, y, z);
+                    self.levels[0].set_bit(position, grid.get_bit(position));
+                }
+            }
+        }
+        for level in 1..self.levels.len() {
+            min /= self.reduction;
+            max = (max + self.reduction - 1) / self.reduction;
+            for z in min.z..max.z {
+                for y in min.y..max.y {
+                    for x in min.x..max.x {
+                        let position = UVec3::new(x, y, z);
+                        let occupied = self.block_occupied(level - 1, position);
+                        self.levels[level].set_bit(position, occupied);
+                    }
+                }
+            }
+        }
+    }
+
+    fn block_occupied(&self, level: usize, parent: UVec3) -> bool {
+        let finer = &self.levels[level];
+        let min = parent * self.reduction;
+        let max = (min + self.reduction).min(finer.dimensions());
+        (min.z..max.z).any(|z| (min.y..max.y).any(|y| (min.x..max.x).any(|x| finer.get_bit(UVec3::new(x, y, z)))))
+    }
+
+    pub fn gpu_levels(&self) -> Vec<OccupancyLevelGpu> {
+        let mut word_offset = 0;
+        self.levels
+            .iter()
+            .map(|level| {
+                let header = OccupancyLevelGpu {
+                    dimensions: level.dimensions(),
+                    word_offset,
+                };
+                word_offset += level.data().len() as u32;
+                header
+            })
+            .collect()
+    }
+
+    pub fn gpu_data(&self) -> Vec<u32> {
+        self.levels.iter().flat_map(|level| level.data().iter().copied()).collect()
+    }
+
+    // hierarchical DDA, returns the same hit as walking the voxels one by one but skips empty cells of every level
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_steps: u32) -> Option<RayHit> {
+        let grid_min = IVec3::ZERO;
+        let grid_max = self.dimensions().as_ivec3();
+        let mut dda = Dda::new(origin, direction);
+        let mut steps = 0;
+        while steps < max_steps {
+            let inside = dda.position.cmpge(grid_min).all() && dda.position.cmplt(grid_max).all();
+            if !inside {
+                if dda.leaving(grid_min, grid_max) {
+                    return None;
+                }
+                // everything outside of the grid is empty, jump straight to where the ray enters it
+                let entry = dda.entry_time(grid_min, grid_max)?;
+                dda.advance_to(entry);
+                steps += 1;
+                continue;
+            }
+            let voxel = dda.position.as_uvec3();
+            if self.levels[0].get_bit(voxel) {
+                return Some(dda.hit(steps));
+            }
+
+            let mut level = 0;
+            let mut cell_size = 1;
+            while level + 1 < self.levels.len() && !self.levels[level + 1].get_bit(voxel / (cell_size * self.reduction)) {
+                level += 1;
+                cell_size *= self.reduction;
+            }
+            if level == 0 {
+                dda.step();
+            } else {
+                let cell_min = (voxel / cell_size * cell_size).as_ivec3();
+                let cell_max = cell_min + cell_size as i32;
+                dda.advance_to(dda.exit_time(cell_min, cell_max));
+            }
+            steps += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::noise::hash_3d;
+    use crate::world::raycast::raycast_flat;
+
+    const MAX_STEPS: u32 = 1000;
+
+    fn random_unit(seed: u32, index: i32, axis: i32) -> f32 {
+        hash_3d(seed, index, axis, 0) as f32 / u32::MAX as f32
+    }
+
+    #[test]
+    fn hierarchical_hits_match_flat_hits_in_fewer_steps() {
+        // a floor and a few scattered voxels leave large empty regions to skip
+        let dimensions = UVec3::splat(64);
+        let mut grid = MeshGridBitfield::new("sparse", dimensions).unwrap();
+        for z in 0..64 {
+            for x in 0..64 {
+                grid.set_bit(UVec3::new(x, 0, z), true);
+            }
+        }
+        for index in 0..200 {
+            let position = UVec3::new(hash_3d(1, index, 0, 0) % 64, hash_3d(1, index, 1, 0) % 64, hash_3d(1, index, 2, 0) % 64);
+            grid.set_bit(position, true);
+        }
+        let pyramid = OccupancyPyramid::from_bitfield(&grid, 4);
+
+        let (mut flat_steps, mut hierarchical_steps, mut hits) = (0, 0, 0);
+        for index in 0..2000 {
+            let unit = |axis| {
+                Vec3::new(
+                    random_unit(2, index, axis),
+                    random_unit(2, index, axis + 3),
+                    random_unit(2, index, axis + 6),
+                )
+            };
+            // half of the rays start outside of the grid
+            let origin = if index % 2 == 0 { unit(0) * 64.0 } else { unit(0) * 192.0 - 64.0 };
+            let direction = (unit(1) * 2.0 - 1.0).normalize();
+            let flat = raycast_flat(origin, direction, (IVec3::ZERO, dimensions.as_ivec3()), MAX_STEPS, |p| grid.is_solid(p));
+            let hierarchical = pyramid.raycast(origin, direction, MAX_STEPS);
+            match (flat, hierarchical) {
+                (Some(flat), Some(hierarchical)) => {
+                    assert_eq!(
+                        (flat.voxel, flat.normal),
+                        (hierarchical.voxel, hierarchical.normal),
+                        "{} {}",
+                        origin,
+                        direction
+                    );
+                    assert!((flat.depth - hierarchical.depth).abs() < 1e-3);
+                    assert!(hierarchical.steps <= flat.steps);
+                    flat_steps += flat.steps;
+                    hierarchical_steps += hierarchical.steps;
+                    hits += 1;
+                }
+                (None, None) => {}
+                (flat, hierarchical) => panic!("{} {}: {:?} {:?}", origin, direction, flat, hierarchical),
+            }
+        }
+        assert!(hits > 200, "{}", hits);
+        assert!(hierarchical_steps * 2 < flat_steps, "{} {}", hierarchical_steps, flat_steps);
+    }
+
+    fn assert_matches_rebuild(pyramid: &OccupancyPyramid, grid: &MeshGridBitfield) {
+        let rebuilt = OccupancyPyramid::from_bitfield(grid, pyramid.reduction());
+        assert_eq!(pyramid.levels().len(), rebuilt.levels().len());
+        for (level, (updated, expected)) in pyramid.levels().iter().zip(rebuilt.levels()).enumerate() {
+            assert_eq!(updated.dimensions(), expected.dimensions(), "level {}", level);
+            assert_eq!(updated.data(), expected.data(), "level {}", level);
+        }
+    }
+
+    #[test]
+    fn edits_match_a_rebuilt_pyramid() {
+        // odd dimensions leave partial cells at the far edge of every level
+        let dimensions = UVec3::new(37, 20, 11);
+        let mut grid = MeshGridBitfield::new("edits", dimensions).unwrap();
+        let mut pyramid = OccupancyPyramid::from_bitfield(&grid, 3);
+
+        let position = |seed, index| {
+            UVec3::new(
+                hash_3d(seed, index, 0, 0) % dimensions.x,
+                hash_3d(seed, index, 1, 0) % dimensions.y,
+                hash_3d(seed, index, 2, 0) % dimensions.z,
+            )
+        };
+        // set and clear single voxels, clearing often empties a whole cell of some level
+        for index in 0..400 {
+            let voxel = position(3, index);
+            let value = index % 3 != 0;
+            grid.set_bit(voxel, value);
+            pyramid.set_voxel(voxel, value);
+            assert_matches_rebuild(&pyramid, &grid);
+        }
+
+        // edit boxes directly in the grid, including boxes reaching past the far edge
+        for index in 0..40 {
+            let min = position(4, index);
+            let max = min + position(5, index) / 2 + 1;
+            let value = index % 2 == 0;
+            for z in min.z..max.z.min(dimensions.z) {
+                for y in min.y..max.y.min(dimensions.y) {
+                    for x in min.x..max.x.min(dimensions.x) {
+                        grid.set_bit(UVec3::new(x, y, z), value);
+                    }
+                }
+            }
+            pyramid.update_region(&grid, min, max);
+            assert_matches_rebuild(&pyramid, &grid);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use glam::{IVec3, Vec3};
+
+use super::material::Material;
+use super::raycast::{raycast_flat, MAX_TRACE_STEPS};
+use super::VoxelStorage;
+
+// what is under a pixel, the same values the primary ray caster writes to its G-buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PickResult {
+    pub voxel: IVec3,
+    // points along the ray like the normal trace() writes, the face that was hit is on the opposite side
+    pub normal: IVec3,
+    pub depth: f32,
+    pub material: Material,
+}
+
+impl PickResult {
+    // the voxel in front of the face that was hit
+    pub fn adjacent(&self) -> IVec3 {
+        self.voxel - self.normal
+    }
+}
+
+// CPU version of the GPU pick, `bounds` is the part [min, max) of the world that can contain solid voxels
+pub fn pick(world: &impl VoxelStorage, bounds: (IVec3, IVec3), origin: Vec3, direction: Vec3) -> Option<PickResult> {
+    let hit = raycast_flat(origin, direction, bounds, MAX_TRACE_STEPS, |position| world.is_solid(position))?;
+    Some(PickResult {
+        voxel: hit.voxel,
+        normal: hit.normal,
+        depth: hit.depth,
+        material: world.get_material(hit.voxel),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunked::ChunkedWorld;
+    use crate::world::material::{BRICK, STONE};
+
+    const BOUNDS: (IVec3, IVec3) = (IVec3::splat(-16), IVec3::splat(16));
+
+    // a stone wall at x = 4 with one brick voxel in it
+    fn wall() -> ChunkedWorld {
+        let mut world = ChunkedWorld::new();
+        for z in -8..8 {
+            for y in -8..8 {
+                world.set(IVec3::new(4, y, z), STONE);
+            }
+        }
+        world.set(IVec3::new(4, 1, 0), BRICK);
+        world
+    }
+
+    // port of gbuffer_voxel in shaders/common/gbuffer.hlsl
+    fn gbuffer_voxel(origin: Vec3, direction: Vec3, depth: f32, normal: IVec3) -> IVec3 {
+        let hit = origin + direction * depth * direction.length();
+        (hit + normal.as_vec3() * 0.5).floor().as_ivec3()
+    }
+
+    #[test]
+    fn picks_match_the_gbuffer_convention() {
+        let world = wall();
+        let origin = Vec3::new(0.5, 1.5, 0.5);
+        for direction in [Vec3::X, Vec3::new(2.0, 0.0, 0.0), Vec3::new(3.0, 0.5, -1.0), Vec3::new(1.0, -0.7, 0.9)] {
+            let pick = pick(&world, BOUNDS, origin, direction).unwrap();
+            assert_eq!(pick.voxel.x, 4);
+            assert_eq!(pick.normal, IVec3::X);
+            assert_eq!(pick.adjacent(), pick.voxel - IVec3::X);
+            assert_eq!(pick.material, world.get(pick.voxel));
+            assert_eq!(gbuffer_voxel(origin, direction, pick.depth, pick.normal), pick.voxel, "{}", direction);
+        }
+        assert_eq!(pick(&world, BOUNDS, origin, Vec3::X).unwrap().material, BRICK);
+    }
+
+    #[test]
+    fn far_picks_rebuild_the_voxel_from_a_32_bit_depth() {
+        let origin = Vec3::new(0.3, 0.6, 0.2);
+        for target in [
+            IVec3::new(700, 0, 0),
+            IVec3::new(700, 311, -157),
+            IVec3::new(700, -689, 523),
+            IVec3::new(700, 2, 699),
+        ] {
+            let mut world = ChunkedWorld::new();
+            for z in -2..=2 {
+                for y in -2..=2 {
+                    world.set(target + IVec3::new(0, y, z), STONE);
+                }
+            }
+            let direction = target.as_vec3() + Vec3::new(0.0, 0.37, 0.61) - origin;
+            let bounds = (IVec3::splat(-1024), IVec3::splat(1024));
+            let pick = pick(&world, bounds, origin, direction).unwrap();
+            assert_eq!(pick.voxel, target);
+            assert_eq!(gbuffer_voxel(origin, direction, pick.depth, pick.normal), target, "{}", direction);
+        }
+    }
+
+    #[test]
+    fn misses_and_rays_starting_inside() {
+        let world = wall();
+        assert_eq!(pick(&world, BOUNDS, Vec3::new(0.5, 0.5, 0.5), -Vec3::X), None);
+        // rays from outside of the bounds are traced until they leave them
+        assert!(pick(&world, BOUNDS, Vec3::new(-40.5, 0.5, 0.5), Vec3::X).is_some());
+        let inside = pick(&world, BOUNDS, Vec3::new(4.5, 0.5, 0.5), Vec3::X).unwrap();
+        assert_eq!((inside.voxel, inside.normal), (IVec3::new(4, 0, 0), IVec3::ZERO));
+    }
+}
@@ -0,0 +1,347 @@
+use glam::{IVec3, Vec3};
+
+use super::brickmap::Brickmap;
+use super::chunk::CHUNK_SIZE;
+use super::chunked::ChunkedWorld;
+use super::raycast::{Dda, RayHit, MAX_TRACE_STEPS};
+use super::sparse::SparseVoxels;
+use super::voxelized::MeshGridBitfield;
+use super::VoxelStorage;
+
+// spatial queries for gameplay code, answered on the CPU from any storage that can tell which part of the
+// world may contain solid voxels
+pub trait WorldQuery: VoxelStorage {
+    // box [min, max) outside of which every voxel is empty, None when the world is empty
+    fn query_bounds(&self) -> Option<(IVec3, IVec3)>;
+
+    // first solid voxel along the ray within `max_distance`. Unlike trace(), which scales depths with the length
+    // of the direction it is given, the direction is normalized first, so `max_distance` and the depth of the hit
+    // are distances in voxels to the face the ray entered through. For a unit direction the hit is exactly the one
+    // trace() and `raycast_flat` find
+    fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+        let (min, max) = self.query_bounds()?;
+        let direction = direction.try_normalize()?;
+        let mut dda = Dda::new(origin, direction);
+        for steps in 0..MAX_TRACE_STEPS {
+            if self.is_solid(dda.position) {
+                return Some(dda.hit(steps));
+            }
+            if dda.leaving(min, max) || dda.side_distances().min_element() > max_distance {
+                return None;
+            }
+            dda.step();
+        }
+        None
+    }
+
+    // true when no solid voxel is entered on the way from `from` to `to`
+    fn line_of_sight(&self, from: Vec3, to: Vec3) -> bool {
+        let distance = from.distance(to);
+        if distance == 0.0 {
+            return !self.is_solid(from.floor().as_ivec3());
+        }
+        !self.raycast(from, to - from, distance).is_some_and(|hit| hit.depth < distance)
+    }
+
+    // solid voxels in the box [min, max)
+    fn solid_in_box(&self, min: IVec3, max: IVec3) -> Vec<IVec3> {
+        let mut solid = Vec::new();
+        let Some((bounds_min, bounds_max)) = self.query_bounds() else {
+            return solid;
+        };
+        let min = min.max(bounds_min);
+        let max = max.min(bounds_max);
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    let position = IVec3::new(x, y, z);
+                    if self.is_solid(position) {
+                        solid.push(position);
+                    }
+                }
+            }
+        }
+        solid
+    }
+
+    fn overlaps_box(&self, min: IVec3, max: IVec3) -> bool {
+        !self.solid_in_box(min, max).is_empty()
+    }
+
+    // solid voxels whose unit cube intersects the sphere
+    fn solid_in_sphere(&self, center: Vec3, radius: f32) -> Vec<IVec3> {
+        let min = (center - radius).floor().as_ivec3();
+        let max = (center + radius).floor().as_ivec3() + 1;
+        self.solid_in_box(min, max)
+            .into_iter()
+            .filter(|voxel| {
+                let closest = center.clamp(voxel.as_vec3(), voxel.as_vec3() + 1.0);
+                closest.distance_squared(center) <= radius * radius
+            })
+            .collect()
+    }
+
+    fn overlaps_sphere(&self, center: Vec3, radius: f32) -> bool {
+        !self.solid_in_sphere(center, radius).is_empty()
+    }
+
+    // solid voxel with the closest center to `position` within `max_distance`, ties go to the voxel that comes
+    // first in z, y, x order. Searches shells of growing size around the voxel containing `position`
+    fn nearest_solid(&self, position: Vec3, max_distance: f32) -> Option<(IVec3, f32)> {
+        let (min, max) = self.query_bounds()?;
+        let start = position.floor().as_ivec3();
+        // the shell that contains the furthest corner of the bounds
+        let furthest = (min - start).abs().max((max - 1 - start).abs()).max_element();
+        // saturating because an infinite distance converts to i32::MAX
+        let last_shell = furthest.min((max_distance.ceil() as i32).saturating_add(1));
+        let mut nearest: Option<(IVec3, f32)> = None;
+        for shell in 0..=last_shell {
+            // every voxel in this shell or further out has its center at least shell - 0.5 voxels away
+            if nearest.is_some_and(|(_, distance)| distance < shell as f32 - 0.5) {
+                break;
+            }
+            for_each_in_shell(start, shell, |voxel| {
+                if voxel.cmplt(min).any() || voxel.cmpge(max).any() || !self.is_solid(voxel) {
+                    return;
+                }
+                let distance = (voxel.as_vec3() + 0.5).distance(position);
+                let closer = match nearest {
+                    Some((best, best_distance)) => {
+                        distance < best_distance || (distance == best_distance && (voxel.z, voxel.y, voxel.x) < (best.z, best.y, best.x))
+                    }
+                    None => true,
+                };
+                if distance <= max_distance && closer {
+                    nearest = Some((voxel, distance));
+                }
+            });
+        }
+        nearest
+    }
+}
+
+// voxels at chebyshev distance `shell` from `center`
+fn for_each_in_shell(center: IVec3, shell: i32, mut f: impl FnMut(IVec3)) {
+    for z in -shell..=shell {
+        for y in -shell..=shell {
+            if z.abs() == shell || y.abs() == shell {
+                for x in -shell..=shell {
+                    f(center + IVec3::new(x, y, z));
+                }
+            } else {
+                f(center + IVec3::new(-shell, y, z));
+                if shell != 0 {
+                    f(center + IVec3::new(shell, y, z));
+                }
+            }
+        }
+    }
+}
+
+impl WorldQuery for MeshGridBitfield {
+    fn query_bounds(&self) -> Option<(IVec3, IVec3)> {
+        Some((IVec3::ZERO, self.dimensions().as_ivec3()))
+    }
+}
+
+impl WorldQuery for ChunkedWorld {
+    fn query_bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.chunks().fold(None, |bounds, chunk| {
+            let min = chunk.origin();
+            let max = min + CHUNK_SIZE as i32;
+            match bounds {
+                Some((bounds_min, bounds_max)) => Some((min.min(bounds_min), max.max(bounds_max))),
+                None => Some((min, max)),
+            }
+        })
+    }
+}
+
+impl WorldQuery for Brickmap {
+    fn query_bounds(&self) -> Option<(IVec3, IVec3)> {
+        Some((IVec3::ZERO, self.voxel_dimensions().as_ivec3()))
+    }
+}
+
+impl WorldQuery for SparseVoxels {
+    fn query_bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.bounds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::material::STONE;
+    use crate::world::noise::hash_3d;
+    use crate::world::raycast::raycast_flat;
+    use glam::UVec3;
+
+    fn random_vec3(seed: u32, index: i32) -> Vec3 {
+        let unit = |axis| hash_3d(seed, index, axis, 0) as f32 / u32::MAX as f32;
+        Vec3::new(unit(0), unit(1), unit(2))
+    }
+
+    // normalizing a direction that is already normalized can change its last bit
+    fn same_hit(a: Option<RayHit>, b: Option<RayHit>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => (a.voxel, a.normal, a.steps) == (b.voxel, b.normal, b.steps) && (a.depth - b.depth).abs() < 1e-4,
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+
+    #[test]
+    fn raycasts_match_flat_raycasts_for_unit_directions() {
+        let mut world = ChunkedWorld::new();
+        for index in 0..300 {
+            world.set((random_vec3(1, index) * 40.0 - 20.0).floor().as_ivec3(), STONE);
+        }
+        let bounds = world.query_bounds().unwrap();
+        let mut hits = 0;
+        for index in 0..500 {
+            let origin = random_vec3(2, index) * 30.0 - 15.0;
+            let direction = (random_vec3(3, index) * 2.0 - 1.0).normalize();
+            let flat = raycast_flat(origin, direction, bounds, MAX_TRACE_STEPS, |p| world.is_solid(p));
+            let hit = world.raycast(origin, direction, f32::INFINITY);
+            assert!(same_hit(hit, flat), "{} {}: {:?} {:?}", origin, direction, hit, flat);
+            // the length of the direction does not matter
+            assert!(same_hit(world.raycast(origin, direction * 3.0, f32::INFINITY), hit));
+
+            let Some(hit) = hit else {
+                continue;
+            };
+            hits += 1;
+            // the depth is the distance to the entry face, so the hit point lies on it
+            let entry = origin + direction * hit.depth;
+            let face = hit.voxel.as_vec3() + (hit.normal.as_vec3().min(Vec3::ZERO)).abs();
+            for axis in (0..3).filter(|&axis| hit.normal[axis] != 0) {
+                assert!((entry[axis] - face[axis]).abs() < 1e-3, "{} {}", entry, face);
+            }
+            // hits further away than `max_distance` are not reported, rays starting in a solid voxel always hit
+            assert!(same_hit(world.raycast(origin, direction, hit.depth + 0.01), Some(hit)));
+            if hit.steps > 0 {
+                assert_eq!(world.raycast(origin, direction, hit.depth - 0.01), None);
+            }
+        }
+        assert!(hits > 50, "{}", hits);
+    }
+
+    // the same random voxels in a grid and, crossing chunk borders, in a chunked world
+    fn random_worlds(seed: u32) -> (MeshGridBitfield, ChunkedWorld, IVec3) {
+        let dimensions = UVec3::new(14, 11, 9);
+        let offset = IVec3::new(-20, 25, -4);
+        let mut grid = MeshGridBitfield::new("random", dimensions).unwrap();
+        let mut world = ChunkedWorld::new();
+        for z in 0..dimensions.z as i32 {
+            for y in 0..dimensions.y as i32 {
+                for x in 0..dimensions.x as i32 {
+                    if hash_3d(seed, x, y, z).is_multiple_of(8) {
+                        grid.set_material(IVec3::new(x, y, z), STONE);
+                        world.set(IVec3::new(x, y, z) + offset, STONE);
+                    }
+                }
+            }
+        }
+        (grid, world, offset)
+    }
+
+    // every solid voxel in z, y, x order
+    fn solid_voxels(world: &impl WorldQuery) -> Vec<IVec3> {
+        let (min, max) = world.query_bounds().unwrap();
+        let mut solid = Vec::new();
+        for z in min.z..max.z {
+            for y in min.y..max.y {
+                for x in min.x..max.x {
+                    if world.is_solid(IVec3::new(x, y, z)) {
+                        solid.push(IVec3::new(x, y, z));
+                    }
+                }
+            }
+        }
+        solid
+    }
+
+    // checks every query against testing each solid voxel on its own, for points around the voxels at `offset`
+    fn check_against_brute_force(world: &impl WorldQuery, offset: IVec3, seed: u32) {
+        let solid = solid_voxels(world);
+        assert!(solid.len() > 50, "{}", solid.len());
+        let point = |index| random_vec3(seed, index) * Vec3::new(18.0, 15.0, 13.0) - 2.0 + offset.as_vec3();
+        let mut blocked = 0;
+        for index in 0..300 {
+            let (from, to) = (point(2 * index), point(2 * index + 1));
+
+            // the segment enters a voxel when the slabs of all three axes overlap in front of `to`
+            let distance = from.distance(to);
+            let direction = (to - from) / distance;
+            let expected = !solid.iter().any(|voxel| {
+                let a = (voxel.as_vec3() - from) / direction;
+                let b = (voxel.as_vec3() + 1.0 - from) / direction;
+                let enter = a.min(b).max_element().max(0.0);
+                let exit = a.max(b).min_element();
+                enter < exit && enter < distance
+            });
+            assert_eq!(world.line_of_sight(from, to), expected, "{} {}", from, to);
+            blocked += !expected as u32;
+
+            let (min, max) = (from.min(to).floor().as_ivec3(), from.max(to).floor().as_ivec3() + 1);
+            let expected: Vec<IVec3> = solid.iter().copied().filter(|v| v.cmpge(min).all() && v.cmplt(max).all()).collect();
+            assert_eq!(world.solid_in_box(min, max), expected, "{} {}", min, max);
+            assert_eq!(world.overlaps_box(min, max), !expected.is_empty());
+
+            let radius = distance / 4.0;
+            let expected: Vec<IVec3> = solid
+                .iter()
+                .copied()
+                .filter(|v| from.clamp(v.as_vec3(), v.as_vec3() + 1.0).distance_squared(from) <= radius * radius)
+                .collect();
+            assert_eq!(world.solid_in_sphere(from, radius), expected, "{} {}", from, radius);
+            assert_eq!(world.overlaps_sphere(from, radius), !expected.is_empty());
+
+            // half of the points are voxel centers, where many voxels are at exactly the same distance
+            let position = if index % 2 == 0 { from.floor() + 0.5 } else { from };
+            for max_distance in [0.0, 1.5, distance / 2.0, f32::INFINITY] {
+                // `solid` is in z, y, x order, so the first of equally near voxels wins
+                let expected = solid
+                    .iter()
+                    .map(|&voxel| (voxel, (voxel.as_vec3() + 0.5).distance(position)))
+                    .filter(|&(_, distance)| distance <= max_distance)
+                    .fold(None, |nearest: Option<(IVec3, f32)>, (voxel, distance)| match nearest {
+                        Some((_, best)) if best <= distance => nearest,
+                        _ => Some((voxel, distance)),
+                    });
+                assert_eq!(world.nearest_solid(position, max_distance), expected, "{} {}", position, max_distance);
+            }
+        }
+        assert!(blocked > 30 && blocked < 270, "{}", blocked);
+    }
+
+    #[test]
+    fn grid_queries_match_brute_force() {
+        for seed in 0..4 {
+            let (grid, _, _) = random_worlds(seed);
+            check_against_brute_force(&grid, IVec3::ZERO, seed + 10);
+        }
+    }
+
+    #[test]
+    fn chunked_queries_match_brute_force() {
+        for seed in 0..4 {
+            let (_, world, offset) = random_worlds(seed);
+            check_against_brute_force(&world, offset, seed + 10);
+        }
+    }
+
+    #[test]
+    fn nearest_solid_looks_past_the_first_shell_with_a_hit() {
+        let mut grid = MeshGridBitfield::new("shells", UVec3::splat(8)).unwrap();
+        let position = Vec3::splat(4.01);
+        // the only voxel of the first shell is further away than a voxel in the second shell
+        grid.set_material(IVec3::splat(5), STONE);
+        grid.set_material(IVec3::new(2, 4, 4), STONE);
+        let (voxel, distance) = grid.nearest_solid(position, f32::INFINITY).unwrap();
+        assert_eq!(voxel, IVec3::new(2, 4, 4));
+        assert!((distance - (1.51f32 * 1.51 + 2.0 * 0.49 * 0.49).sqrt()).abs() < 1e-5);
+        assert_eq!(grid.nearest_solid(position, 1.0), None);
+        assert_eq!(ChunkedWorld::new().nearest_solid(position, f32::INFINITY), None);
+    }
+}
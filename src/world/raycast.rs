@@ -0,0 +1,190 @@
+use glam::{BVec3, IVec3, Vec3};
+
+// same iteration limit as trace() in shaders/common/trace.hlsl
+pub const MAX_TRACE_STEPS: u32 = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub voxel: IVec3,
+    // same convention as trace(), the axis the ray stepped along to enter the voxel times the step direction
+    pub normal: IVec3,
+    // same value trace() writes to g_depth
+    pub depth: f32,
+    pub steps: u32,
+}
+
+// voxel walking state shared by the flat and the hierarchical traversal. Crossing times are always computed
+// from the voxel position instead of being accumulated, so skipping a block of voxels ends up in exactly the
+// same state as stepping through it one voxel at a time
+#[derive(Debug, Clone, Copy)]
+pub struct Dda {
+    origin: Vec3,
+    direction: Vec3,
+    inverse_direction: Vec3,
+    step: IVec3,
+    pub position: IVec3,
+    pub mask: BVec3,
+}
+
+fn sign(value: f32) -> i32 {
+    if value > 0.0 {
+        1
+    } else if value < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+impl Dda {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            inverse_direction: 1.0 / direction,
+            step: IVec3::new(sign(direction.x), sign(direction.y), sign(direction.z)),
+            position: origin.floor().as_ivec3(),
+            mask: BVec3::FALSE,
+        }
+    }
+
+    pub fn step_direction(&self) -> IVec3 {
+        self.step
+    }
+
+    // time at which the ray enters voxel slab `voxel` along `axis`
+    pub fn enter_time(&self, axis: usize, voxel: i32) -> f32 {
+        let plane = if self.step[axis] > 0 { voxel } else { voxel + 1 };
+        (plane as f32 - self.origin[axis]) * self.inverse_direction[axis]
+    }
+
+    // time of the next crossing along every axis, infinite for axes the ray does not move along
+    pub fn side_distances(&self) -> Vec3 {
+        let mut side = Vec3::INFINITY;
+        for axis in 0..3 {
+            if self.step[axis] != 0 {
+                side[axis] = self.enter_time(axis, self.position[axis] + self.step[axis]);
+            }
+        }
+        side
+    }
+
+    // advance to the next voxel, all axes crossing at the same time step together like in trace()
+    pub fn step(&mut self) {
+        let side = self.side_distances();
+        let nearest = side.min_element();
+        self.mask = BVec3::new(side.x <= nearest, side.y <= nearest, side.z <= nearest);
+        for axis in 0..3 {
+            if self.mask.test(axis) {
+                self.position[axis] += self.step[axis];
+            }
+        }
+    }
+
+    // performs every crossing happening at or before `time` at once
+    pub fn advance_to(&mut self, time: f32) {
+        let mut mask = BVec3::FALSE;
+        for axis in 0..3 {
+            let step = self.step[axis];
+            if step == 0 {
+                continue;
+            }
+            let start = self.position[axis];
+            // estimate the voxel from the position at `time` and correct for rounding
+            let mut voxel = (self.origin[axis] + self.direction[axis] * time).floor() as i32;
+            if (voxel - start) * step < 0 {
+                voxel = start;
+            }
+            while self.enter_time(axis, voxel + step) <= time {
+                voxel += step;
+            }
+            while voxel != start && self.enter_time(axis, voxel) > time {
+                voxel -= step;
+            }
+            if voxel != start {
+                self.position[axis] = voxel;
+                mask.set(axis, self.enter_time(axis, voxel) == time);
+            }
+        }
+        self.mask = mask;
+    }
+
+    // time at which the ray leaves the box [min, max), the box must contain the current voxel
+    pub fn exit_time(&self, min: IVec3, max: IVec3) -> f32 {
+        let mut time = f32::INFINITY;
+        for axis in 0..3 {
+            match self.step[axis] {
+                1 => time = time.min(self.enter_time(axis, max[axis])),
+                -1 => time = time.min(self.enter_time(axis, min[axis] - 1)),
+                _ => {}
+            }
+        }
+        time
+    }
+
+    // time at which the ray enters the box [min, max), None when it never does
+    pub fn entry_time(&self, min: IVec3, max: IVec3) -> Option<f32> {
+        let mut entry = f32::NEG_INFINITY;
+        let mut exit = f32::INFINITY;
+        for axis in 0..3 {
+            let position = self.position[axis];
+            if self.step[axis] == 0 {
+                if position < min[axis] || position >= max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let (first, last) = if self.step[axis] > 0 {
+                (min[axis], max[axis])
+            } else {
+                (max[axis] - 1, min[axis] - 1)
+            };
+            if (position - first) * self.step[axis] < 0 {
+                entry = entry.max(self.enter_time(axis, first));
+            }
+            exit = exit.min(self.enter_time(axis, last));
+        }
+        (entry < exit).then_some(entry.max(0.0))
+    }
+
+    // moving away from the box [min, max) on an axis it is already outside of
+    pub fn leaving(&self, min: IVec3, max: IVec3) -> bool {
+        (0..3).any(|axis| {
+            let position = self.position[axis];
+            let step = self.step[axis];
+            (position < min[axis] && step <= 0) || (position >= max[axis] && step >= 0)
+        })
+    }
+
+    pub fn hit(&self, steps: u32) -> RayHit {
+        let normal = IVec3::new(self.mask.x as i32, self.mask.y as i32, self.mask.z as i32) * self.step;
+        let mut last_crossing = Vec3::ZERO;
+        for axis in 0..3 {
+            if self.mask.test(axis) {
+                last_crossing[axis] = self.enter_time(axis, self.position[axis]);
+            }
+        }
+        RayHit {
+            voxel: self.position,
+            normal,
+            depth: last_crossing.length() / self.direction.length(),
+            steps,
+        }
+    }
+}
+
+// voxel by voxel traversal with the same semantics as trace(), `is_solid` is queried for every visited voxel
+// and the walk stops early once the ray is outside of [min, max) and moving away from it
+pub fn raycast_flat(origin: Vec3, direction: Vec3, bounds: (IVec3, IVec3), max_steps: u32, is_solid: impl Fn(IVec3) -> bool) -> Option<RayHit> {
+    let mut dda = Dda::new(origin, direction);
+    for steps in 0..max_steps {
+        if is_solid(dda.position) {
+            return Some(dda.hit(steps));
+        }
+        if dda.leaving(bounds.0, bounds.1) {
+            return None;
+        }
+        dda.step();
+    }
+    None
+}
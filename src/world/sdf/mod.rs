@@ -0,0 +1,228 @@
+mod parser;
+
+pub use parser::*;
+
+use anyhow::Result;
+use glam::{ivec3, IVec3, Quat, UVec3, Vec3};
+
+use super::material::{Material, STONE};
+use super::noise::gradient_noise_3d;
+use super::voxelized::MeshGridBitfield;
+use super::VoxelStorage;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SdfNode {
+    Sphere {
+        radius: f32,
+    },
+    Box {
+        half_extents: Vec3,
+    },
+    Capsule {
+        a: Vec3,
+        b: Vec3,
+        radius: f32,
+    },
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    // solid below the plane dot(p, normal) = offset
+    Plane {
+        normal: Vec3,
+        offset: f32,
+    },
+
+    Union(Vec<SdfNode>),
+    Intersection(Vec<SdfNode>),
+    // the first child minus all following children
+    Subtraction(Vec<SdfNode>),
+    SmoothUnion {
+        k: f32,
+        children: Vec<SdfNode>,
+    },
+    SmoothIntersection {
+        k: f32,
+        children: Vec<SdfNode>,
+    },
+    SmoothSubtraction {
+        k: f32,
+        children: Vec<SdfNode>,
+    },
+    Invert(Box<SdfNode>),
+
+    Translate {
+        offset: Vec3,
+        child: Box<SdfNode>,
+    },
+    Rotate {
+        rotation: Quat,
+        child: Box<SdfNode>,
+    },
+    Scale {
+        factor: f32,
+        child: Box<SdfNode>,
+    },
+    // infinite repetition, axes with a period of 0 are not repeated
+    Repeat {
+        period: Vec3,
+        child: Box<SdfNode>,
+    },
+    Displace {
+        amplitude: f32,
+        frequency: f32,
+        seed: u32,
+        child: Box<SdfNode>,
+    },
+    Material {
+        material: Material,
+        child: Box<SdfNode>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfSample {
+    pub distance: f32,
+    pub material: Material,
+}
+
+impl SdfNode {
+    pub fn distance(&self, position: Vec3) -> f32 {
+        self.sample(position, STONE).distance
+    }
+
+    pub fn sample(&self, p: Vec3, material: Material) -> SdfSample {
+        let sample = |distance: f32| SdfSample { distance, material };
+        match self {
+            SdfNode::Sphere { radius } => sample(p.length() - radius),
+            SdfNode::Box { half_extents } => {
+                let d = p.abs() - *half_extents;
+                sample(d.max_element().min(0.0) + d.max(Vec3::ZERO).length())
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = (pa.dot(ba) / ba.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+                sample((pa - ba * h).length() - radius)
+            }
+            SdfNode::Torus { major_radius, minor_radius } => {
+                let q = glam::vec2(glam::vec2(p.x, p.z).length() - major_radius, p.y);
+                sample(q.length() - minor_radius)
+            }
+            SdfNode::Plane { normal, offset } => sample(p.dot(normal.normalize()) - offset),
+
+            SdfNode::Union(children) => fold(children, p, material, |a, b| if b.distance < a.distance { b } else { a }),
+            SdfNode::Intersection(children) => fold(children, p, material, |a, b| if b.distance > a.distance { b } else { a }),
+            SdfNode::Subtraction(children) => fold(children, p, material, |a, b| {
+                if -b.distance > a.distance {
+                    SdfSample {
+                        distance: -b.distance,
+                        material: a.material,
+                    }
+                } else {
+                    a
+                }
+            }),
+            SdfNode::SmoothUnion { k, children } => fold(children, p, material, |a, b| {
+                let h = (0.5 + 0.5 * (b.distance - a.distance) / k).clamp(0.0, 1.0);
+                SdfSample {
+                    distance: lerp(b.distance, a.distance, h) - k * h * (1.0 - h),
+                    material: if h > 0.5 { a.material } else { b.material },
+                }
+            }),
+            SdfNode::SmoothIntersection { k, children } => fold(children, p, material, |a, b| {
+                let h = (0.5 - 0.5 * (b.distance - a.distance) / k).clamp(0.0, 1.0);
+                SdfSample {
+                    distance: lerp(b.distance, a.distance, h) + k * h * (1.0 - h),
+                    material: if h > 0.5 { a.material } else { b.material },
+                }
+            }),
+            SdfNode::SmoothSubtraction { k, children } => fold(children, p, material, |a, b| {
+                let h = (0.5 - 0.5 * (a.distance + b.distance) / k).clamp(0.0, 1.0);
+                SdfSample {
+                    distance: lerp(a.distance, -b.distance, h) + k * h * (1.0 - h),
+                    material: a.material,
+                }
+            }),
+            SdfNode::Invert(child) => {
+                let inner = child.sample(p, material);
+                SdfSample {
+                    distance: -inner.distance,
+                    material: inner.material,
+                }
+            }
+
+            SdfNode::Translate { offset, child } => child.sample(p - *offset, material),
+            SdfNode::Rotate { rotation, child } => child.sample(rotation.inverse() * p, material),
+            SdfNode::Scale { factor, child } => {
+                let inner = child.sample(p / *factor, material);
+                SdfSample {
+                    distance: inner.distance * factor,
+                    material: inner.material,
+                }
+            }
+            SdfNode::Repeat { period, child } => {
+                let repeat = |p: f32, period: f32| if period > 0.0 { p - period * (p / period).round() } else { p };
+                let q = Vec3::new(repeat(p.x, period.x), repeat(p.y, period.y), repeat(p.z, period.z));
+                child.sample(q, material)
+            }
+            SdfNode::Displace {
+                amplitude,
+                frequency,
+                seed,
+                child,
+            } => {
+                let inner = child.sample(p, material);
+                SdfSample {
+                    distance: inner.distance + amplitude * gradient_noise_3d(*seed, p * *frequency),
+                    material: inner.material,
+                }
+            }
+            SdfNode::Material { material, child } => child.sample(p, *material),
+        }
+    }
+}
+
+fn fold(children: &[SdfNode], p: Vec3, material: Material, combine: impl Fn(SdfSample, SdfSample) -> SdfSample) -> SdfSample {
+    let mut children = children.iter().map(|child| child.sample(p, material));
+    let first = children.next().unwrap_or(SdfSample {
+        distance: f32::INFINITY,
+        material,
+    });
+    children.fold(first, combine)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// voxels are solid when the field is negative at their center, the same test getVoxel in trace.hlsl does
+pub fn voxelize_into_world(scene: &SdfNode, world: &mut impl VoxelStorage, min: IVec3, max: IVec3) {
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let position = ivec3(x, y, z);
+                let sample = scene.sample(position.as_vec3() + 0.5, STONE);
+                if sample.distance < 0.0 {
+                    world.set_material(position, sample.material);
+                }
+            }
+        }
+    }
+}
+
+// voxel (0, 0, 0) of the bitfield is sampled at `offset`
+pub fn voxelize_to_bitfield(scene: &SdfNode, name: &str, dimensions: UVec3, offset: IVec3) -> Result<MeshGridBitfield> {
+    let mut bitfield = MeshGridBitfield::new(name, dimensions)?;
+    for z in 0..dimensions.z {
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                let position = UVec3::new(x, y, z);
+                if scene.distance((position.as_ivec3() + offset).as_vec3() + 0.5) < 0.0 {
+                    bitfield.set_bit(position, true);
+                }
+            }
+        }
+    }
+    Ok(bitfield)
+}
@@ -0,0 +1,337 @@
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use glam::{Quat, Vec3};
+
+use crate::io::read_file;
+use crate::world::material::{material_from_name, Material};
+
+use super::SdfNode;
+
+// Scenes are written as s-expressions, every top level expression is combined with a union:
+//
+//   # hollow box
+//   (material stone
+//     (subtract (box 6 6 6) (sphere 7.5)))
+//
+// primitives:  (sphere r) (box hx hy hz) (capsule ax ay az bx by bz r) (torus major minor) (plane nx ny nz offset)
+// csg:         (union a b ..) (intersect a b ..) (subtract a b ..) (invert a)
+//              (smooth-union k a b ..) (smooth-intersect k a b ..) (smooth-subtract k a b ..)
+// transforms:  (translate x y z a) (rotate ax ay az degrees a) (scale s a) (repeat px py pz a)
+//              (displace amplitude frequency seed a) (material name_or_id a)
+pub fn load_sdf_scene(filename: &str) -> Result<SdfNode> {
+    let source = read_file(filename)?;
+    let source = String::from_utf8(source).with_context(|| format!("scene is not valid utf8: {}", filename))?;
+    parse_sdf_scene(&source).with_context(|| format!("could not parse scene: {}", filename))
+}
+
+pub fn parse_sdf_scene(source: &str) -> Result<SdfNode> {
+    let tokens = tokenize(source);
+    let mut parser = Parser { tokens, position: 0 };
+    let mut nodes = Vec::new();
+    while parser.position < parser.tokens.len() {
+        nodes.push(parser.node()?);
+    }
+    match nodes.len() {
+        0 => bail!("scene does not contain any nodes"),
+        1 => Ok(nodes.pop().unwrap()),
+        _ => Ok(SdfNode::Union(nodes)),
+    }
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (line_index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let spaced = line.replace('(', " ( ").replace(')', " ) ");
+        tokens.extend(spaced.split_whitespace().map(|text| Token {
+            text: text.to_owned(),
+            line: line_index + 1,
+        }));
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Result<&Token> {
+        let Some(token) = self.tokens.get(self.position) else {
+            bail!("unexpected end of scene");
+        };
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn peek_is_close(&self) -> bool {
+        self.tokens.get(self.position).is_some_and(|token| token.text == ")")
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        let token = self.next()?;
+        if token.text != expected {
+            bail!("line {}: expected '{}' but found '{}'", token.line, expected, token.text);
+        }
+        Ok(())
+    }
+
+    fn float(&mut self) -> Result<f32> {
+        let token = self.next()?;
+        token
+            .text
+            .parse()
+            .with_context(|| format!("line {}: expected a number but found '{}'", token.line, token.text))
+    }
+
+    fn integer(&mut self) -> Result<u32> {
+        let token = self.next()?;
+        token
+            .text
+            .parse()
+            .with_context(|| format!("line {}: expected an integer but found '{}'", token.line, token.text))
+    }
+
+    // the blend distance of the smooth operations, 0 would divide by zero
+    fn smoothness(&mut self, line: usize) -> Result<f32> {
+        let k = self.float()?;
+        if k.is_nan() || k <= 0.0 {
+            bail!("line {}: smoothness must be positive", line);
+        }
+        Ok(k)
+    }
+
+    fn vec3(&mut self) -> Result<Vec3> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    fn material(&mut self) -> Result<Material> {
+        let token = self.next()?;
+        let material = match material_from_name(&token.text) {
+            Some(material) => material,
+            None => token
+                .text
+                .parse()
+                .with_context(|| format!("line {}: unknown material '{}'", token.line, token.text))?,
+        };
+        Ok(material)
+    }
+
+    fn child(&mut self) -> Result<Box<SdfNode>> {
+        Ok(Box::new(self.node()?))
+    }
+
+    fn children(&mut self, operation: &str, line: usize) -> Result<Vec<SdfNode>> {
+        let mut children = Vec::new();
+        while !self.peek_is_close() {
+            children.push(self.node()?);
+        }
+        if children.is_empty() {
+            bail!("line {}: '{}' needs at least one child", line, operation);
+        }
+        Ok(children)
+    }
+
+    fn node(&mut self) -> Result<SdfNode> {
+        self.expect("(")?;
+        let token = self.next()?;
+        let (operation, line) = (token.text.clone(), token.line);
+        let node = match operation.as_str() {
+            "sphere" => SdfNode::Sphere { radius: self.float()? },
+            "box" => SdfNode::Box { half_extents: self.vec3()? },
+            "capsule" => SdfNode::Capsule {
+                a: self.vec3()?,
+                b: self.vec3()?,
+                radius: self.float()?,
+            },
+            "torus" => SdfNode::Torus {
+                major_radius: self.float()?,
+                minor_radius: self.float()?,
+            },
+            "plane" => {
+                let normal = self.vec3()?;
+                if !normal.is_finite() || normal.length_squared() == 0.0 {
+                    bail!("line {}: plane normal can not be zero", line);
+                }
+                SdfNode::Plane {
+                    normal,
+                    offset: self.float()?,
+                }
+            }
+            "union" => SdfNode::Union(self.children(&operation, line)?),
+            "intersect" => SdfNode::Intersection(self.children(&operation, line)?),
+            "subtract" => SdfNode::Subtraction(self.children(&operation, line)?),
+            "smooth-union" => SdfNode::SmoothUnion {
+                k: self.smoothness(line)?,
+                children: self.children(&operation, line)?,
+            },
+            "smooth-intersect" => SdfNode::SmoothIntersection {
+                k: self.smoothness(line)?,
+                children: self.children(&operation, line)?,
+            },
+            "smooth-subtract" => SdfNode::SmoothSubtraction {
+                k: self.smoothness(line)?,
+                children: self.children(&operation, line)?,
+            },
+            "invert" => SdfNode::Invert(self.child()?),
+            "translate" => SdfNode::Translate {
+                offset: self.vec3()?,
+                child: self.child()?,
+            },
+            "rotate" => {
+                let axis = self.vec3()?;
+                let degrees = self.float()?;
+                if axis.length_squared() == 0.0 {
+                    bail!("line {}: rotation axis can not be zero", line);
+                }
+                SdfNode::Rotate {
+                    rotation: Quat::from_axis_angle(axis.normalize(), degrees.to_radians()),
+                    child: self.child()?,
+                }
+            }
+            "scale" => {
+                let factor = self.float()?;
+                if !factor.is_finite() || factor <= 0.0 {
+                    bail!("line {}: scale must be positive and finite", line);
+                }
+                SdfNode::Scale {
+                    factor,
+                    child: self.child()?,
+                }
+            }
+            "repeat" => SdfNode::Repeat {
+                period: self.vec3()?,
+                child: self.child()?,
+            },
+            "displace" => SdfNode::Displace {
+                amplitude: self.float()?,
+                frequency: self.float()?,
+                seed: self.integer()?,
+                child: self.child()?,
+            },
+            "material" => SdfNode::Material {
+                material: self.material()?,
+                child: self.child()?,
+            },
+            _ => bail!("line {}: unknown node '{}'", line, operation),
+        };
+        self.expect(")")?;
+        Ok(node)
+    }
+}
+
+// writes the node back in the scene format, parsing the output gives the same node
+impl fmt::Display for SdfNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vec3 = |v: &Vec3| format!("{} {} {}", v.x, v.y, v.z);
+        let children = |children: &[SdfNode]| children.iter().map(|child| format!(" {}", child)).collect::<String>();
+        match self {
+            SdfNode::Sphere { radius } => write!(f, "(sphere {})", radius),
+            SdfNode::Box { half_extents } => write!(f, "(box {})", vec3(half_extents)),
+            SdfNode::Capsule { a, b, radius } => write!(f, "(capsule {} {} {})", vec3(a), vec3(b), radius),
+            SdfNode::Torus { major_radius, minor_radius } => write!(f, "(torus {} {})", major_radius, minor_radius),
+            SdfNode::Plane { normal, offset } => write!(f, "(plane {} {})", vec3(normal), offset),
+            SdfNode::Union(nodes) => write!(f, "(union{})", children(nodes)),
+            SdfNode::Intersection(nodes) => write!(f, "(intersect{})", children(nodes)),
+            SdfNode::Subtraction(nodes) => write!(f, "(subtract{})", children(nodes)),
+            SdfNode::SmoothUnion { k, children: nodes } => write!(f, "(smooth-union {}{})", k, children(nodes)),
+            SdfNode::SmoothIntersection { k, children: nodes } => write!(f, "(smooth-intersect {}{})", k, children(nodes)),
+            SdfNode::SmoothSubtraction { k, children: nodes } => write!(f, "(smooth-subtract {}{})", k, children(nodes)),
+            SdfNode::Invert(child) => write!(f, "(invert {})", child),
+            SdfNode::Translate { offset, child } => write!(f, "(translate {} {})", vec3(offset), child),
+            SdfNode::Rotate { rotation, child } => {
+                let (axis, angle) = rotation.to_axis_angle();
+                write!(f, "(rotate {} {} {})", vec3(&axis), angle.to_degrees(), child)
+            }
+            SdfNode::Scale { factor, child } => write!(f, "(scale {} {})", factor, child),
+            SdfNode::Repeat { period, child } => write!(f, "(repeat {} {})", vec3(period), child),
+            SdfNode::Displace {
+                amplitude,
+                frequency,
+                seed,
+                child,
+            } => write!(f, "(displace {} {} {} {})", amplitude, frequency, seed, child),
+            SdfNode::Material { material, child } => write!(f, "(material {} {})", material, child),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::ivec3;
+
+    const EVERY_NODE: &str = "
+        (material stone
+          (smooth-union 0.5
+            (sphere 3) (box 1 2 3) (capsule 0 0 0 1 2 3 0.5) (torus 4 1) (plane 0 1 0 -2)
+            (union (sphere 1)) (intersect (sphere 1) (sphere 2)) (subtract (sphere 2) (sphere 1))
+            (smooth-intersect 1.5 (sphere 1) (sphere 2)) (smooth-subtract 0.25 (sphere 2) (sphere 1))
+            (invert (sphere 1)) (translate 1 -2 3.5 (sphere 1)) (scale 2 (sphere 1))
+            (repeat 8 0 8 (sphere 1)) (displace 0.5 0.1 42 (sphere 1))))
+        (material 7 (rotate 0 1 0 90 (box 1 2 3)))";
+
+    #[test]
+    fn scenes_round_trip() {
+        let scene = parse_sdf_scene(EVERY_NODE).unwrap();
+        let written = scene.to_string();
+        let reparsed = parse_sdf_scene(&written).unwrap();
+        // rotations go through an axis and angle, the rest of the tree has to match exactly
+        assert_eq!(reparsed.to_string(), written);
+        for position in [ivec3(0, 0, 0), ivec3(3, -1, 2), ivec3(-5, 4, 7), ivec3(20, 20, -20)] {
+            let position = position.as_vec3() + 0.25;
+            assert!((scene.distance(position) - reparsed.distance(position)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        for scene in [
+            "(smooth-union 0 (sphere 1))",
+            "(smooth-intersect -1 (sphere 1))",
+            "(smooth-subtract nan (sphere 1))",
+            "(displace 1 1 1.5 (sphere 1))",
+            "(displace 1 1 -1 (sphere 1))",
+            "(scale 0 (sphere 1))",
+            "(scale nan (sphere 1))",
+            "(scale inf (sphere 1))",
+            "(plane 0 0 0 1)",
+            "(rotate 0 0 0 90 (sphere 1))",
+            "(material nothing (sphere 1))",
+            "(sphere 1",
+        ] {
+            assert!(parse_sdf_scene(scene).is_err(), "{}", scene);
+        }
+        let error = parse_sdf_scene("(union\n  (plane 0 0 0 1))").unwrap_err();
+        assert_eq!(format!("{:#}", error), "line 2: plane normal can not be zero");
+    }
+
+    // the scene the world was hard coded to before scenes were loaded from files
+    fn old_get_voxel(position: Vec3) -> bool {
+        let p = position + 0.5;
+        let sphere = |p: Vec3, radius: f32| p.length() - radius;
+        let d = p.abs() - Vec3::splat(6.0);
+        let cube = d.max_element().min(0.0) + d.max(Vec3::ZERO).length();
+        (-sphere(p, 7.5)).max(cube).min(-sphere(p, 50.0)) < 0.0
+    }
+
+    #[test]
+    fn default_scene_matches_the_old_hard_coded_scene() {
+        let scene = parse_sdf_scene(include_str!("../../../scenes/hollow_box.sdf")).unwrap();
+        for z in -56..56 {
+            for y in -56..56 {
+                for x in -56..56 {
+                    let position = ivec3(x, y, z).as_vec3();
+                    assert_eq!(scene.distance(position + 0.5) < 0.0, old_get_voxel(position), "{}", position);
+                }
+            }
+        }
+    }
+}
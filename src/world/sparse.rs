@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use glam::{IVec3, UVec3};
+use serde::{Deserialize, Serialize};
+
+use crate::io::{read_versioned_file, write_versioned_file, Versioned};
+
+use super::material::{Material, AIR};
+use super::VoxelStorage;
+
+const SPARSE_BRICK_SIZE: i32 = 8;
+const SPARSE_BRICK_VOLUME: usize = (SPARSE_BRICK_SIZE * SPARSE_BRICK_SIZE * SPARSE_BRICK_SIZE) as usize;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SparseBrick {
+    materials: Vec<Material>,
+    solid_count: u32,
+}
+
+// voxels hashed by the 8^3 brick that contains them, only bricks with at least one voxel that is not air are stored,
+// so memory scales with the number of voxels instead of the extent they are spread over
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct SparseVoxels {
+    bricks: HashMap<IVec3, SparseBrick>,
+    len: usize,
+}
+
+fn split(position: IVec3) -> (IVec3, usize) {
+    let brick = position.div_euclid(IVec3::splat(SPARSE_BRICK_SIZE));
+    let local = position - brick * SPARSE_BRICK_SIZE;
+    (
+        brick,
+        (local.x + local.y * SPARSE_BRICK_SIZE + local.z * SPARSE_BRICK_SIZE * SPARSE_BRICK_SIZE) as usize,
+    )
+}
+
+impl SparseVoxels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(filename: &str) -> Result<Self> {
+        read_versioned_file(filename)
+    }
+    pub fn save(&self, filename: &str) -> Result<()> {
+        write_versioned_file(self, filename)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn brick_count(&self) -> usize {
+        self.bricks.len()
+    }
+    pub fn clear(&mut self) {
+        self.bricks.clear();
+        self.len = 0;
+    }
+
+    pub fn get(&self, position: IVec3) -> Option<Material> {
+        let (brick, index) = split(position);
+        let material = self.bricks.get(&brick)?.materials[index];
+        (material != AIR).then_some(material)
+    }
+    pub fn contains(&self, position: IVec3) -> bool {
+        self.get(position).is_some()
+    }
+
+    // returns the material that was stored before, inserting air removes the voxel
+    pub fn insert(&mut self, position: IVec3, material: Material) -> Option<Material> {
+        if material == AIR {
+            return self.remove(position);
+        }
+        let (brick, index) = split(position);
+        let brick = self.bricks.entry(brick).or_insert_with(|| SparseBrick {
+            materials: vec![AIR; SPARSE_BRICK_VOLUME],
+            solid_count: 0,
+        });
+        let previous = std::mem::replace(&mut brick.materials[index], material);
+        if previous != AIR {
+            Some(previous)
+        } else {
+            brick.solid_count += 1;
+            self.len += 1;
+            None
+        }
+    }
+
+    pub fn remove(&mut self, position: IVec3) -> Option<Material> {
+        let (brick_position, index) = split(position);
+        let brick = self.bricks.get_mut(&brick_position)?;
+        let previous = std::mem::replace(&mut brick.materials[index], AIR);
+        if previous == AIR {
+            return None;
+        }
+        brick.solid_count -= 1;
+        self.len -= 1;
+        if brick.solid_count == 0 {
+            self.bricks.remove(&brick_position);
+        }
+        Some(previous)
+    }
+
+    // every voxel that is not air, bricks are visited in hash order
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, Material)> + '_ {
+        self.bricks.iter().flat_map(|(brick, data)| {
+            let origin = *brick * SPARSE_BRICK_SIZE;
+            data.materials
+                .iter()
+                .enumerate()
+                .filter(|(_, &material)| material != AIR)
+                .map(move |(index, &material)| {
+                    let index = index as i32;
+                    let local = IVec3::new(
+                        index % SPARSE_BRICK_SIZE,
+                        (index / SPARSE_BRICK_SIZE) % SPARSE_BRICK_SIZE,
+                        index / (SPARSE_BRICK_SIZE * SPARSE_BRICK_SIZE),
+                    );
+                    (origin + local, material)
+                })
+        })
+    }
+
+    // solid voxels inside of the box [min, max), visits the stored bricks instead of every brick of the box
+    // when the box covers more bricks than are stored
+    pub fn query_box(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, Material)> + '_ {
+        // max is inclusive from here on so boxes reaching i32::MAX do not overflow
+        let empty = !min.cmplt(max).all();
+        let max = if empty { min } else { max - 1 };
+        let brick_min = min.div_euclid(IVec3::splat(SPARSE_BRICK_SIZE));
+        let brick_max = max.div_euclid(IVec3::splat(SPARSE_BRICK_SIZE));
+        let box_bricks = (brick_max - brick_min + 1)
+            .as_uvec3()
+            .to_array()
+            .iter()
+            .fold(1u64, |count, &extent| count.saturating_mul(extent as u64));
+        let bricks: Vec<IVec3> = if empty {
+            vec![]
+        } else if box_bricks > self.bricks.len() as u64 {
+            self.bricks
+                .keys()
+                .copied()
+                .filter(|brick| brick.cmpge(brick_min).all() && brick.cmple(brick_max).all())
+                .collect()
+        } else {
+            (brick_min.z..=brick_max.z)
+                .flat_map(|z| (brick_min.y..=brick_max.y).flat_map(move |y| (brick_min.x..=brick_max.x).map(move |x| IVec3::new(x, y, z))))
+                .filter(|brick| self.bricks.contains_key(brick))
+                .collect()
+        };
+        bricks
+            .into_iter()
+            .flat_map(move |brick| {
+                let origin = brick * SPARSE_BRICK_SIZE;
+                let from = min.max(origin);
+                let to = max.min(origin + (SPARSE_BRICK_SIZE - 1));
+                (from.z..=to.z).flat_map(move |z| (from.y..=to.y).flat_map(move |y| (from.x..=to.x).map(move |x| IVec3::new(x, y, z))))
+            })
+            .filter_map(|position| self.get(position).map(|material| (position, material)))
+    }
+
+    // (min, max) of all solid voxels with an exclusive max
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.iter().fold(None, |bounds, (position, _)| match bounds {
+            Some((min, max)) => Some((position.min(min), (position + 1).max(max))),
+            None => Some((position, position + 1)),
+        })
+    }
+
+    // size of the dense grid a `MeshGridBitfield` would need for the same voxels
+    pub fn dense_dimensions(&self) -> UVec3 {
+        self.bounds().map_or(UVec3::ZERO, |(min, max)| (max - min).as_uvec3())
+    }
+}
+
+impl Versioned for SparseVoxels {
+    const TYPE_TAG: &'static str = "sparse_voxels";
+    const VERSION: u32 = 1;
+}
+
+impl VoxelStorage for SparseVoxels {
+    fn get_material(&self, position: IVec3) -> Material {
+        self.get(position).unwrap_or(AIR)
+    }
+    fn set_material(&mut self, position: IVec3, material: Material) {
+        self.insert(position, material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::noise::hash_3d;
+
+    fn random_voxels(count: i32) -> SparseVoxels {
+        let mut voxels = SparseVoxels::new();
+        for index in 0..count {
+            let coordinate = |axis| (hash_3d(5, index, axis, 0) % 40) as i32 - 20;
+            voxels.insert(IVec3::new(coordinate(0), coordinate(1), coordinate(2)), 1 + (index % 4) as Material);
+        }
+        voxels
+    }
+
+    #[test]
+    fn bricks_are_freed_when_they_become_empty() {
+        let mut voxels = SparseVoxels::new();
+        assert_eq!(voxels.insert(IVec3::new(-1, -1, -1), 3), None);
+        assert_eq!(voxels.insert(IVec3::new(-8, -8, -8), 4), None);
+        assert_eq!(voxels.insert(IVec3::new(-1, -1, -1), 5), Some(3));
+        assert_eq!((voxels.len(), voxels.brick_count()), (2, 1));
+        assert_eq!(voxels.bounds(), Some((IVec3::splat(-8), IVec3::ZERO)));
+        assert_eq!(voxels.dense_dimensions(), UVec3::splat(8));
+
+        assert_eq!(voxels.insert(IVec3::new(-1, -1, -1), AIR), Some(5));
+        assert_eq!(voxels.remove(IVec3::new(-1, -1, -1)), None);
+        assert_eq!(voxels.remove(IVec3::new(-8, -8, -8)), Some(4));
+        assert!(voxels.is_empty());
+        assert_eq!(voxels.brick_count(), 0);
+        assert_eq!(voxels.bounds(), None);
+    }
+
+    #[test]
+    fn box_queries_match_filtering_every_voxel() {
+        let voxels = random_voxels(2000);
+        for (min, max) in [
+            (IVec3::splat(-20), IVec3::splat(20)),
+            (IVec3::new(-3, -9, 1), IVec3::new(5, 2, 17)),
+            (IVec3::new(0, 0, 0), IVec3::new(1, 1, 1)),
+            (IVec3::new(4, 4, 4), IVec3::new(4, 9, 9)),
+            (IVec3::new(4, 4, 4), IVec3::new(2, 9, 9)),
+            // far larger than the set, only the stored bricks are visited
+            (IVec3::splat(i32::MIN), IVec3::splat(i32::MAX)),
+            (IVec3::new(-10, i32::MIN, -10), IVec3::new(10, 0, 10)),
+        ] {
+            let mut queried: Vec<_> = voxels.query_box(min, max).collect();
+            let mut expected: Vec<_> = voxels
+                .iter()
+                .filter(|(position, _)| position.cmpge(min).all() && position.cmplt(max).all())
+                .collect();
+            queried.sort_by_key(|(position, _)| position.to_array());
+            expected.sort_by_key(|(position, _)| position.to_array());
+            assert_eq!(queried, expected, "{} {}", min, max);
+        }
+    }
+
+    #[test]
+    fn box_queries_reach_the_edges_of_i32() {
+        let mut voxels = SparseVoxels::new();
+        let corners = [IVec3::splat(i32::MIN), IVec3::splat(i32::MAX - 1), IVec3::new(i32::MAX - 1, i32::MIN, 0)];
+        for corner in corners {
+            voxels.insert(corner, 2);
+        }
+        // max is exclusive so a voxel at i32::MAX can not be part of any box
+        let queried: Vec<_> = voxels.query_box(IVec3::splat(i32::MAX - 8), IVec3::splat(i32::MAX)).collect();
+        assert_eq!(queried, vec![(IVec3::splat(i32::MAX - 1), 2)]);
+        let queried: Vec<_> = voxels.query_box(IVec3::splat(i32::MIN), IVec3::splat(i32::MIN + 1)).collect();
+        assert_eq!(queried, vec![(IVec3::splat(i32::MIN), 2)]);
+        assert_eq!(voxels.query_box(IVec3::splat(i32::MIN), IVec3::splat(i32::MAX)).count(), 3);
+        assert_eq!(
+            voxels.query_box(IVec3::splat(i32::MIN), IVec3::new(i32::MAX, i32::MIN, i32::MAX)).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let voxels = random_voxels(500);
+        let filename = std::env::temp_dir().join(format!("sparse_voxels_{}.svw", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        voxels.save(filename).unwrap();
+        let loaded = SparseVoxels::load(filename);
+        std::fs::remove_file(filename).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.len(), voxels.len());
+        assert!(voxels.iter().all(|(position, material)| loaded.get(position) == Some(material)));
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{Context, Result};
+use glam::{DVec3, IVec3, Mat3, UVec3, Vec3};
+use serde::{Deserialize, Serialize};
+
+use super::csg::BooleanOp;
+use super::morphology::Connectivity;
+use super::voxelized::MeshGridBitfield;
+
+// every voxel is treated as a unit cube with unit density
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoxelStatistics {
+    pub name: String,
+    pub dimensions: UVec3,
+    pub volume: u64,
+    // number of voxel faces not covered by another solid voxel, faces on the grid border count as exposed
+    pub surface_area: u64,
+    // (min, max) with an exclusive max
+    pub bounds: Option<(UVec3, UVec3)>,
+    pub center_of_mass: Option<Vec3>,
+    // about the center of mass
+    pub inertia_tensor: Mat3,
+    pub components: u32,
+    // empty regions fully enclosed by solid voxels
+    pub cavities: u32,
+    pub euler_characteristic: i64,
+    // number of tunnels through the model, derived from the euler characteristic
+    pub genus: i64,
+}
+
+impl VoxelStatistics {
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).with_context(|| format!("could not encode statistics of {} to json", self.name))
+    }
+    pub fn to_cbor(&self) -> Result<Vec<u8>> {
+        serde_cbor::to_vec(self).with_context(|| format!("could not encode statistics of {} to cbor", self.name))
+    }
+}
+
+impl MeshGridBitfield {
+    pub fn statistics(&self) -> VoxelStatistics {
+        let d = self.dimensions();
+        let mut volume = 0u64;
+        let mut surface_area = 0u64;
+        let mut sum = DVec3::ZERO;
+        let mut sum_of_squares = [[0f64; 3]; 3];
+        for position in self.solid_positions() {
+            volume += 1;
+            for offset in Connectivity::Six.offsets() {
+                if !self.is_solid(position.as_ivec3() + offset) {
+                    surface_area += 1;
+                }
+            }
+            let center = position.as_dvec3() + 0.5;
+            sum += center;
+            let center = center.to_array();
+            for (row, sum_row) in sum_of_squares.iter_mut().enumerate() {
+                for (column, value) in sum_row.iter_mut().enumerate() {
+                    *value += center[row] * center[column];
+                }
+            }
+        }
+
+        let (center_of_mass, inertia_tensor) = if volume > 0 {
+            let mass = volume as f64;
+            let center = sum / mass;
+            let c = center.to_array();
+            // second moments about the center of mass, every cube adds 1/12 on the diagonal for its own extent
+            let mut covariance = [[0f64; 3]; 3];
+            for row in 0..3 {
+                for column in 0..3 {
+                    covariance[row][column] = sum_of_squares[row][column] - mass * c[row] * c[column];
+                }
+                covariance[row][row] += mass / 12.0;
+            }
+            let trace = covariance[0][0] + covariance[1][1] + covariance[2][2];
+            let mut inertia = [[0f32; 3]; 3];
+            for row in 0..3 {
+                for column in 0..3 {
+                    let identity = if row == column { trace } else { 0.0 };
+                    inertia[row][column] = (identity - covariance[row][column]) as f32;
+                }
+            }
+            (Some(center.as_vec3()), Mat3::from_cols_array_2d(&inertia))
+        } else {
+            (None, Mat3::ZERO)
+        };
+
+        let components = self.label_components(Connectivity::TwentySix).components().len() as u32;
+        let cavities = self.count_cavities();
+        let euler_characteristic = self.euler_characteristic();
+        VoxelStatistics {
+            name: self.name().to_owned(),
+            dimensions: d,
+            volume,
+            surface_area,
+            bounds: self.solid_bounds(),
+            center_of_mass,
+            inertia_tensor,
+            components,
+            cavities,
+            euler_characteristic,
+            // euler characteristic = components - tunnels + cavities
+            genus: components as i64 + cavities as i64 - euler_characteristic,
+        }
+    }
+
+    // vertices - edges + faces - cubes of the union of all solid voxels as closed unit cubes
+    pub fn euler_characteristic(&self) -> i64 {
+        let d = self.dimensions().as_ivec3();
+        // an element at lattice position p spanning the axes in `extent` exists when any voxel touching it is solid
+        let touches_solid = |p: IVec3, extent: IVec3| {
+            let mut offsets = vec![IVec3::ZERO];
+            for axis in 0..3 {
+                if extent[axis] == 0 {
+                    let mut shifted = offsets.clone();
+                    for offset in &mut shifted {
+                        offset[axis] -= 1;
+                    }
+                    offsets.extend(shifted);
+                }
+            }
+            offsets.into_iter().any(|offset| self.is_solid(p + offset))
+        };
+        let mut characteristic = 0i64;
+        for z in 0..=d.z {
+            for y in 0..=d.y {
+                for x in 0..=d.x {
+                    let p = IVec3::new(x, y, z);
+                    characteristic += touches_solid(p, IVec3::ZERO) as i64;
+                    for extent in [IVec3::X, IVec3::Y, IVec3::Z] {
+                        characteristic -= touches_solid(p, extent) as i64;
+                    }
+                    for extent in [IVec3::new(0, 1, 1), IVec3::new(1, 0, 1), IVec3::new(1, 1, 0)] {
+                        characteristic += touches_solid(p, extent) as i64;
+                    }
+                }
+            }
+        }
+        characteristic - self.solid_positions().count() as i64
+    }
+
+    fn count_cavities(&self) -> u32 {
+        // enclosed empty regions are face connected, which is the counterpart of vertex connected solids
+        let cavities = self.fill_interior().boolean(self, IVec3::ZERO, BooleanOp::Difference);
+        cavities.label_components(Connectivity::Six).components().len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(dimensions: UVec3, solid: impl Fn(UVec3) -> bool) -> MeshGridBitfield {
+        let mut grid = MeshGridBitfield::new("test", dimensions).unwrap();
+        for z in 0..dimensions.z {
+            for y in 0..dimensions.y {
+                for x in 0..dimensions.x {
+                    let position = UVec3::new(x, y, z);
+                    grid.set_bit(position, solid(position));
+                }
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn box_statistics() {
+        let statistics = filled(UVec3::new(2, 3, 4), |_| true).statistics();
+        assert_eq!(statistics.volume, 24);
+        assert_eq!(statistics.surface_area, 2 * (2 * 3 + 2 * 4 + 3 * 4));
+        assert_eq!(statistics.bounds, Some((UVec3::ZERO, UVec3::new(2, 3, 4))));
+        assert_eq!(statistics.center_of_mass, Some(Vec3::new(1.0, 1.5, 2.0)));
+        assert_eq!(
+            (
+                statistics.components,
+                statistics.cavities,
+                statistics.euler_characteristic,
+                statistics.genus
+            ),
+            (1, 0, 1, 0)
+        );
+
+        let decoded: VoxelStatistics = serde_json::from_str(&statistics.to_json().unwrap()).unwrap();
+        assert_eq!(decoded, statistics);
+        let decoded: VoxelStatistics = serde_cbor::from_slice(&statistics.to_cbor().unwrap()).unwrap();
+        assert_eq!(decoded, statistics);
+    }
+
+    #[test]
+    fn inertia_tensors_match_solid_bodies() {
+        // a solid box of mass m has m * (b^2 + c^2) / 12 about its x axis and no products of inertia
+        let statistics = filled(UVec3::new(2, 3, 4), |_| true).statistics();
+        let expected = Mat3::from_diagonal(Vec3::new(50.0, 40.0, 26.0));
+        assert!(statistics.inertia_tensor.abs_diff_eq(expected, 1e-4), "{}", statistics.inertia_tensor);
+
+        // two cubes touching along an edge, each one 1/sqrt(2) from the center of mass
+        let statistics = filled(UVec3::new(2, 2, 1), |p| p.x == p.y).statistics();
+        let (a, b) = (5.0 / 6.0, 4.0 / 3.0);
+        let expected = Mat3::from_cols(Vec3::new(a, -0.5, 0.0), Vec3::new(-0.5, a, 0.0), Vec3::new(0.0, 0.0, b));
+        assert!(statistics.inertia_tensor.abs_diff_eq(expected, 1e-4), "{}", statistics.inertia_tensor);
+        assert_eq!(statistics.center_of_mass, Some(Vec3::new(1.0, 1.0, 0.5)));
+    }
+
+    #[test]
+    fn cavities_and_tunnels() {
+        let shell = filled(UVec3::splat(5), |p| !(p.cmpge(UVec3::ONE).all() && p.cmplt(UVec3::splat(4)).all()));
+        let statistics = shell.statistics();
+        assert_eq!(
+            (
+                statistics.components,
+                statistics.cavities,
+                statistics.euler_characteristic,
+                statistics.genus
+            ),
+            (1, 1, 2, 0)
+        );
+
+        let ring = filled(UVec3::new(3, 1, 3), |p| p.x != 1 || p.z != 1);
+        let statistics = ring.statistics();
+        assert_eq!(
+            (
+                statistics.components,
+                statistics.cavities,
+                statistics.euler_characteristic,
+                statistics.genus
+            ),
+            (1, 0, 0, 1)
+        );
+    }
+}
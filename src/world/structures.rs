@@ -0,0 +1,417 @@
+use anyhow::Result;
+use glam::{ivec3, uvec3, IVec3, UVec3};
+
+use super::chunk::{Chunk, CHUNK_SIZE};
+use super::material::{Material, BRICK, LEAVES, STONE, WOOD};
+use super::noise::{hash_2d, wang_hash};
+use super::terrain::{Biome, TerrainGenerator};
+use super::voxelized::MeshGridBitfield;
+
+pub struct PrefabLayer {
+    pub material: Material,
+    pub shape: MeshGridBitfield,
+}
+
+// a voxel structure made of one occupancy grid per material, all layers share the same dimensions
+pub struct Prefab {
+    dimensions: UVec3,
+    // voxel of the prefab that is placed directly on top of the terrain surface
+    anchor: UVec3,
+    layers: Vec<PrefabLayer>,
+}
+
+impl Prefab {
+    pub fn new(dimensions: UVec3, anchor: UVec3) -> Self {
+        assert!(anchor.cmplt(dimensions).all());
+        Self {
+            dimensions,
+            anchor,
+            layers: Vec::new(),
+        }
+    }
+    pub fn dimensions(&self) -> UVec3 {
+        self.dimensions
+    }
+    pub fn add_layer(&mut self, material: Material, shape: MeshGridBitfield) {
+        assert_eq!(shape.dimensions(), self.dimensions);
+        self.layers.push(PrefabLayer { material, shape });
+    }
+    // later layers win when several layers contain the same voxel
+    pub fn material_at(&self, position: UVec3) -> Option<Material> {
+        self.layers
+            .iter()
+            .rev()
+            .find(|layer| layer.shape.get_bit(position))
+            .map(|layer| layer.material)
+    }
+
+    pub fn tree(trunk_height: u32, canopy_radius: u32) -> Result<Self> {
+        let width = canopy_radius * 2 + 1;
+        let dimensions = uvec3(width, trunk_height + canopy_radius + 1, width);
+        let center = IVec3::new(canopy_radius as i32, 0, canopy_radius as i32);
+        let canopy_center = center + IVec3::Y * trunk_height as i32;
+        let mut trunk = MeshGridBitfield::new("tree trunk", dimensions)?;
+        let mut leaves = MeshGridBitfield::new("tree leaves", dimensions)?;
+        for position in grid_positions(dimensions) {
+            let offset = position.as_ivec3() - canopy_center;
+            if position.x as i32 == center.x && position.z as i32 == center.z && position.y < trunk_height {
+                trunk.set_bit(position, true);
+            } else if offset.length_squared() <= (canopy_radius * canopy_radius) as i32 {
+                leaves.set_bit(position, true);
+            }
+        }
+        let mut prefab = Self::new(dimensions, center.as_uvec3());
+        prefab.add_layer(LEAVES, leaves);
+        prefab.add_layer(WOOD, trunk);
+        Ok(prefab)
+    }
+
+    pub fn rock(radius: u32) -> Result<Self> {
+        let width = radius * 2 + 1;
+        let dimensions = uvec3(width, radius + 1, width);
+        let center = IVec3::new(radius as i32, 0, radius as i32);
+        let mut shape = MeshGridBitfield::new("rock", dimensions)?;
+        for position in grid_positions(dimensions) {
+            if (position.as_ivec3() - center).length_squared() <= (radius * radius) as i32 {
+                shape.set_bit(position, true);
+            }
+        }
+        let mut prefab = Self::new(dimensions, center.as_uvec3());
+        prefab.add_layer(STONE, shape);
+        Ok(prefab)
+    }
+
+    // hollow box with a door opening, anchored at its floor corner
+    pub fn hut(size: UVec3) -> Result<Self> {
+        let mut walls = MeshGridBitfield::new("hut walls", size)?;
+        for position in grid_positions(size) {
+            let on_wall = position.x == 0 || position.x == size.x - 1 || position.z == 0 || position.z == size.z - 1;
+            let on_roof = position.y == size.y - 1;
+            let in_door = position.z == 0 && position.x == size.x / 2 && position.y < 2;
+            if (on_wall || on_roof) && !in_door {
+                walls.set_bit(position, true);
+            }
+        }
+        let mut prefab = Self::new(size, UVec3::ZERO);
+        prefab.add_layer(BRICK, walls);
+        Ok(prefab)
+    }
+}
+
+pub struct PlacementRule {
+    pub prefab: usize,
+    pub biomes: Vec<Biome>,
+    // placement candidates are jittered over a grid with cells of this size
+    pub spacing: u32,
+    // chance that a cell actually contains a structure
+    pub density: f32,
+    // maximum height difference between the corners of the footprint
+    pub max_slope: i32,
+}
+
+pub struct StructurePlacer {
+    seed: u32,
+    prefabs: Vec<Prefab>,
+    rules: Vec<PlacementRule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub prefab: usize,
+    // world space position of prefab voxel (0, 0, 0)
+    pub origin: IVec3,
+}
+
+impl StructurePlacer {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            prefabs: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn with_default_structures(seed: u32) -> Result<Self> {
+        let mut placer = Self::new(seed);
+        let tree = placer.add_prefab(Prefab::tree(6, 3)?);
+        let rock = placer.add_prefab(Prefab::rock(2)?);
+        let hut = placer.add_prefab(Prefab::hut(uvec3(7, 5, 7))?);
+        placer.add_rule(PlacementRule {
+            prefab: tree,
+            biomes: vec![Biome::Forest],
+            spacing: 8,
+            density: 0.7,
+            max_slope: 2,
+        });
+        placer.add_rule(PlacementRule {
+            prefab: tree,
+            biomes: vec![Biome::Plains, Biome::Tundra],
+            spacing: 24,
+            density: 0.3,
+            max_slope: 2,
+        });
+        placer.add_rule(PlacementRule {
+            prefab: rock,
+            biomes: vec![Biome::Plains, Biome::Desert, Biome::Mountains, Biome::Tundra, Biome::Beach],
+            spacing: 16,
+            density: 0.25,
+            max_slope: 4,
+        });
+        placer.add_rule(PlacementRule {
+            prefab: hut,
+            biomes: vec![Biome::Plains],
+            spacing: 128,
+            density: 0.5,
+            max_slope: 1,
+        });
+        Ok(placer)
+    }
+
+    pub fn add_prefab(&mut self, prefab: Prefab) -> usize {
+        self.prefabs.push(prefab);
+        self.prefabs.len() - 1
+    }
+
+    pub fn add_rule(&mut self, rule: PlacementRule) {
+        assert!(rule.prefab < self.prefabs.len());
+        assert!(rule.spacing > 0);
+        self.rules.push(rule);
+    }
+
+    pub fn prefab(&self, index: usize) -> &Prefab {
+        &self.prefabs[index]
+    }
+
+    // All structures whose bounds overlap the column range [min, max) on the xz plane. Where structures would
+    // overlap each other only the one with the highest priority is placed: earlier rules first, then the lower
+    // hash of the cell. A structure gives way to every candidate that overlaps it, even one that was dropped
+    // itself, so the result does not depend on which area is asked for and neighbouring chunks agree
+    pub fn placements_in_area(&self, generator: &TerrainGenerator, min: (i32, i32), max: (i32, i32)) -> Vec<Placement> {
+        // every candidate overlapping a structure that reaches into the area
+        let largest = self.largest_prefab();
+        let candidates = self.candidates_in_area(generator, (min.0 - largest.x, min.1 - largest.z), (max.0 + largest.x, max.1 + largest.z));
+        candidates
+            .iter()
+            .filter(|candidate| candidate.overlaps_columns(min, max))
+            .filter(|candidate| {
+                !candidates
+                    .iter()
+                    .any(|other| other.priority < candidate.priority && other.overlaps(candidate))
+            })
+            .map(|candidate| candidate.placement)
+            .collect()
+    }
+
+    // Stamps every structure overlapping the chunk, structures crossing the chunk border are clipped to it so
+    // neighbouring chunks can be decorated independently and still line up. Structures only fill voxels that
+    // are still empty, they never carve terrain: ground reaching into a hut stays and fills part of it
+    pub fn decorate_chunk(&self, generator: &TerrainGenerator, chunk: &mut Chunk) {
+        let chunk_min = chunk.origin();
+        let chunk_max = chunk_min + CHUNK_SIZE as i32;
+        for placement in self.placements_in_area(generator, (chunk_min.x, chunk_min.z), (chunk_max.x, chunk_max.z)) {
+            let prefab = &self.prefabs[placement.prefab];
+            let min = placement.origin.max(chunk_min);
+            let max = (placement.origin + prefab.dimensions.as_ivec3()).min(chunk_max);
+            for z in min.z..max.z {
+                for y in min.y..max.y {
+                    for x in min.x..max.x {
+                        let world = ivec3(x, y, z);
+                        let local = (world - chunk_min).as_uvec3();
+                        if chunk.is_solid(local) {
+                            continue;
+                        }
+                        if let Some(material) = prefab.material_at((world - placement.origin).as_uvec3()) {
+                            chunk.set(local, material);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn largest_prefab(&self) -> IVec3 {
+        self.rules
+            .iter()
+            .map(|rule| self.prefabs[rule.prefab].dimensions.as_ivec3())
+            .fold(IVec3::ZERO, IVec3::max)
+    }
+
+    // structures whose bounds overlap the column range [min, max), before overlaps between them are resolved
+    fn candidates_in_area(&self, generator: &TerrainGenerator, min: (i32, i32), max: (i32, i32)) -> Vec<Candidate> {
+        let mut candidates = Vec::new();
+        for (rule_index, rule) in self.rules.iter().enumerate() {
+            let size = self.prefabs[rule.prefab].dimensions.as_ivec3();
+            let spacing = rule.spacing as i32;
+            // widen the searched cells so structures rooted outside the area but reaching into it are found
+            let cell_min = ((min.0 - size.x).div_euclid(spacing), (min.1 - size.z).div_euclid(spacing));
+            let cell_max = ((max.0 + size.x).div_euclid(spacing), (max.1 + size.z).div_euclid(spacing));
+            for cell_z in cell_min.1..=cell_max.1 {
+                for cell_x in cell_min.0..=cell_max.0 {
+                    if let Some(candidate) = self.place_in_cell(generator, rule_index, cell_x, cell_z) {
+                        if candidate.overlaps_columns(min, max) {
+                            candidates.push(candidate);
+                        }
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    fn place_in_cell(&self, generator: &TerrainGenerator, rule_index: usize, cell_x: i32, cell_z: i32) -> Option<Candidate> {
+        let rule = &self.rules[rule_index];
+        let prefab = &self.prefabs[rule.prefab];
+        let hash = hash_2d(wang_hash(self.seed ^ rule_index as u32), cell_x, cell_z);
+        if (hash & 0xffff) as f32 / 65536.0 >= rule.density {
+            return None;
+        }
+        let spacing = rule.spacing as i32;
+        let jitter = wang_hash(hash);
+        let x = cell_x * spacing + (jitter % rule.spacing) as i32;
+        let z = cell_z * spacing + ((jitter >> 16) % rule.spacing) as i32;
+
+        let ground = generator.height_at(x, z);
+        if !rule.biomes.contains(&generator.biome_at(x, z)) {
+            return None;
+        }
+        let anchor = prefab.anchor.as_ivec3();
+        let origin = ivec3(x, ground + 1, z) - anchor;
+        let size = prefab.dimensions.as_ivec3();
+        let corners = [
+            (origin.x, origin.z),
+            (origin.x + size.x - 1, origin.z),
+            (origin.x, origin.z + size.z - 1),
+            (origin.x + size.x - 1, origin.z + size.z - 1),
+        ];
+        let slope = corners
+            .iter()
+            .map(|&(x, z)| (generator.height_at(x, z) - ground).abs())
+            .max()
+            .unwrap_or(0);
+        if slope > rule.max_slope {
+            return None;
+        }
+        Some(Candidate {
+            placement: Placement { prefab: rule.prefab, origin },
+            size,
+            priority: (rule_index, hash, cell_x, cell_z),
+        })
+    }
+}
+
+// a structure that fits its rule, it is only placed when no candidate with a higher priority overlaps it
+struct Candidate {
+    placement: Placement,
+    size: IVec3,
+    // lower is more important, the cell breaks ties between equal hashes
+    priority: (usize, u32, i32, i32),
+}
+
+impl Candidate {
+    fn overlaps(&self, other: &Candidate) -> bool {
+        let (min, max) = (self.placement.origin, self.placement.origin + self.size);
+        let (other_min, other_max) = (other.placement.origin, other.placement.origin + other.size);
+        min.cmplt(other_max).all() && other_min.cmplt(max).all()
+    }
+    fn overlaps_columns(&self, min: (i32, i32), max: (i32, i32)) -> bool {
+        let origin = self.placement.origin;
+        origin.x < max.0 && origin.x + self.size.x > min.0 && origin.z < max.1 && origin.z + self.size.z > min.1
+    }
+}
+
+fn grid_positions(dimensions: UVec3) -> impl Iterator<Item = UVec3> {
+    (0..dimensions.z).flat_map(move |z| (0..dimensions.y).flat_map(move |y| (0..dimensions.x).map(move |x| uvec3(x, y, z))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::split_world_position;
+    use crate::world::material::AIR;
+
+    const SEED: u32 = 7;
+
+    fn bounds(placer: &StructurePlacer, placement: &Placement) -> (IVec3, IVec3) {
+        (
+            placement.origin,
+            placement.origin + placer.prefab(placement.prefab).dimensions().as_ivec3(),
+        )
+    }
+
+    #[test]
+    fn structures_do_not_overlap() {
+        let generator = TerrainGenerator::new(SEED);
+        let placer = StructurePlacer::with_default_structures(SEED).unwrap();
+        let placements = placer.placements_in_area(&generator, (-256, -256), (256, 256));
+        assert!(placements.len() > 10);
+        for (index, a) in placements.iter().enumerate() {
+            for b in &placements[index + 1..] {
+                let ((a_min, a_max), (b_min, b_max)) = (bounds(&placer, a), bounds(&placer, b));
+                assert!(!(a_min.cmplt(b_max).all() && b_min.cmplt(a_max).all()), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn areas_agree_on_placements() {
+        let generator = TerrainGenerator::new(SEED);
+        let placer = StructurePlacer::with_default_structures(SEED).unwrap();
+        let everything = placer.placements_in_area(&generator, (-256, -256), (256, 256));
+        for chunk_x in -4..4 {
+            for chunk_z in -4..4 {
+                let min = (chunk_x * CHUNK_SIZE as i32, chunk_z * CHUNK_SIZE as i32);
+                let max = (min.0 + CHUNK_SIZE as i32, min.1 + CHUNK_SIZE as i32);
+                let mut in_chunk = placer.placements_in_area(&generator, min, max);
+                let mut expected: Vec<Placement> = everything
+                    .iter()
+                    .copied()
+                    .filter(|placement| {
+                        let (placement_min, placement_max) = bounds(&placer, placement);
+                        placement_min.x < max.0 && placement_max.x > min.0 && placement_min.z < max.1 && placement_max.z > min.1
+                    })
+                    .collect();
+                in_chunk.sort_by_key(|placement| (placement.origin.x, placement.origin.y, placement.origin.z, placement.prefab));
+                expected.sort_by_key(|placement| (placement.origin.x, placement.origin.y, placement.origin.z, placement.prefab));
+                assert_eq!(in_chunk, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn structures_crossing_chunk_borders_are_clipped() {
+        let generator = TerrainGenerator::new(SEED);
+        let placer = StructurePlacer::with_default_structures(SEED).unwrap();
+        let placements = placer.placements_in_area(&generator, (-256, -256), (256, 256));
+        let crossing: Vec<&Placement> = placements
+            .iter()
+            .filter(|placement| {
+                let (min, max) = bounds(&placer, placement);
+                split_world_position(min).0 != split_world_position(max - 1).0
+            })
+            .take(4)
+            .collect();
+        assert!(!crossing.is_empty());
+
+        for placement in crossing {
+            let prefab = placer.prefab(placement.prefab);
+            let mut stamped = 0;
+            for position in grid_positions(prefab.dimensions()) {
+                let Some(material) = prefab.material_at(position) else {
+                    continue;
+                };
+                // every chunk is generated and decorated on its own
+                let (chunk_position, local) = split_world_position(placement.origin + position.as_ivec3());
+                let mut chunk = generator.generate_chunk(chunk_position);
+                let terrain = chunk.get(local);
+                placer.decorate_chunk(&generator, &mut chunk);
+                if terrain == AIR {
+                    assert_eq!(chunk.get(local), material);
+                    stamped += 1;
+                } else {
+                    assert_eq!(chunk.get(local), terrain);
+                }
+            }
+            assert!(stamped > 0);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use glam::{ivec3, uvec3, vec2, IVec3, Vec2};
+use serde::{Deserialize, Serialize};
+
+use super::chunk::{Chunk, CHUNK_SIZE};
+use super::material::{Material, AIR, DIRT, GRASS, GRAVEL, ICE, SAND, SNOW, STONE, WATER};
+use super::noise::{wang_hash, FractalNoise};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Biome {
+    Ocean,
+    Beach,
+    Plains,
+    Forest,
+    Desert,
+    Mountains,
+    Tundra,
+}
+
+impl Biome {
+    pub fn surface_material(&self) -> Material {
+        match self {
+            Biome::Ocean => GRAVEL,
+            Biome::Beach | Biome::Desert => SAND,
+            Biome::Plains | Biome::Forest => GRASS,
+            Biome::Mountains => STONE,
+            Biome::Tundra => SNOW,
+        }
+    }
+    pub fn subsurface_material(&self) -> Material {
+        match self {
+            Biome::Ocean | Biome::Beach | Biome::Desert => SAND,
+            Biome::Plains | Biome::Forest => DIRT,
+            Biome::Mountains => STONE,
+            Biome::Tundra => ICE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TerrainSettings {
+    // columns below this height are filled with water up to it
+    pub sea_level: i32,
+    pub base_height: f32,
+    pub height_amplitude: f32,
+    pub height_frequency: f32,
+    pub height_octaves: u32,
+    pub mountain_amplitude: f32,
+    pub cave_frequency: f32,
+    pub cave_octaves: u32,
+    pub cave_threshold: f32,
+    // caves are not carved within this many voxels of the surface
+    pub cave_surface_margin: i32,
+    pub biome_frequency: f32,
+    pub subsurface_depth: i32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            sea_level: 0,
+            base_height: 4.0,
+            height_amplitude: 24.0,
+            height_frequency: 1.0 / 256.0,
+            height_octaves: 5,
+            mountain_amplitude: 64.0,
+            cave_frequency: 1.0 / 48.0,
+            cave_octaves: 3,
+            cave_threshold: 0.02,
+            cave_surface_margin: 4,
+            biome_frequency: 1.0 / 1024.0,
+            subsurface_depth: 3,
+        }
+    }
+}
+
+pub struct TerrainGenerator {
+    settings: TerrainSettings,
+    height_noise: FractalNoise,
+    mountain_noise: FractalNoise,
+    cave_noise: FractalNoise,
+    temperature_noise: FractalNoise,
+    moisture_noise: FractalNoise,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self::with_settings(seed, TerrainSettings::default())
+    }
+
+    pub fn with_settings(seed: u32, settings: TerrainSettings) -> Self {
+        // every noise layer gets its own seed so the layers are uncorrelated
+        let layer_seed = |layer: u32| wang_hash(seed ^ wang_hash(layer));
+        Self {
+            settings,
+            height_noise: FractalNoise::new(layer_seed(1), settings.height_frequency, settings.height_octaves),
+            mountain_noise: FractalNoise::new(layer_seed(2), settings.biome_frequency * 2.0, 2),
+            cave_noise: FractalNoise::new(layer_seed(3), settings.cave_frequency, settings.cave_octaves),
+            temperature_noise: FractalNoise::new(layer_seed(4), settings.biome_frequency, 2),
+            moisture_noise: FractalNoise::new(layer_seed(5), settings.biome_frequency, 2),
+        }
+    }
+
+    pub fn settings(&self) -> &TerrainSettings {
+        &self.settings
+    }
+
+    // height of the topmost solid voxel in column (x, z), ignoring caves
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let position = column_center(x, z);
+        let hills = self.height_noise.sample_2d(position) * self.settings.height_amplitude;
+        // mountains only rise where the mountain mask is positive
+        let mountain_mask = self.mountain_noise.sample_2d(position).max(0.0);
+        let mountains = mountain_mask * self.settings.mountain_amplitude;
+        (self.settings.base_height + hills + mountains).floor() as i32
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        self.biome_for_height(x, z, self.height_at(x, z))
+    }
+
+    pub fn is_cave(&self, position: IVec3, surface_height: i32) -> bool {
+        if position.y > surface_height - self.settings.cave_surface_margin {
+            return false;
+        }
+        // a cave is the thin region where the noise crosses zero, which gives long connected tunnels
+        self.cave_noise.sample_3d(position.as_vec3() + 0.5).abs() < self.settings.cave_threshold
+    }
+
+    pub fn material_at(&self, position: IVec3) -> Material {
+        let height = self.height_at(position.x, position.z);
+        let biome = self.biome_for_height(position.x, position.z, height);
+        self.material_in_column(position, height, biome)
+    }
+
+    pub fn generate_chunk(&self, chunk_position: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(chunk_position);
+        let origin = chunk.origin();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let world_x = origin.x + x as i32;
+                let world_z = origin.z + z as i32;
+                let height = self.height_at(world_x, world_z);
+                let column_top = height.max(self.settings.sea_level);
+                if column_top < origin.y {
+                    continue;
+                }
+                let biome = self.biome_for_height(world_x, world_z, height);
+                let top = (column_top - origin.y).min(CHUNK_SIZE as i32 - 1) as u32;
+                for y in 0..=top {
+                    let material = self.material_in_column(ivec3(world_x, origin.y + y as i32, world_z), height, biome);
+                    chunk.set(uvec3(x, y, z), material);
+                }
+            }
+        }
+        chunk
+    }
+
+    fn biome_for_height(&self, x: i32, z: i32, height: i32) -> Biome {
+        let sea_level = self.settings.sea_level;
+        if height < sea_level - 2 {
+            return Biome::Ocean;
+        }
+        if height <= sea_level + 1 {
+            return Biome::Beach;
+        }
+        if height as f32 > self.settings.base_height + self.settings.height_amplitude * 0.5 + self.settings.mountain_amplitude * 0.25 {
+            return Biome::Mountains;
+        }
+        let position = column_center(x, z);
+        let temperature = self.temperature_noise.sample_2d(position);
+        let moisture = self.moisture_noise.sample_2d(position);
+        if temperature < -0.3 {
+            Biome::Tundra
+        } else if temperature > 0.3 && moisture < 0.0 {
+            Biome::Desert
+        } else if moisture > 0.1 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+
+    fn material_in_column(&self, position: IVec3, height: i32, biome: Biome) -> Material {
+        if position.y > height {
+            return if position.y <= self.settings.sea_level { WATER } else { AIR };
+        }
+        if self.is_cave(position, height) {
+            return AIR;
+        }
+        let depth = height - position.y;
+        if depth == 0 {
+            biome.surface_material()
+        } else if depth <= self.settings.subsurface_depth {
+            biome.subsurface_material()
+        } else {
+            STONE
+        }
+    }
+}
+
+// noise is sampled at voxel centers, gradient noise is always zero on its integer lattice
+fn column_center(x: i32, z: i32) -> Vec2 {
+    vec2(x as f32, z as f32) + 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::split_world_position;
+
+    // FNV-1a, stable across platforms and rust versions unlike the std hasher
+    fn chunk_hash(chunk: &Chunk) -> u64 {
+        chunk
+            .materials()
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, &material| (hash ^ material as u64).wrapping_mul(0x100000001b3))
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_chunk() {
+        let generator = TerrainGenerator::new(42);
+        for chunk in [ivec3(0, 0, 0), ivec3(3, -1, -5)] {
+            assert!(generator.generate_chunk(chunk) == TerrainGenerator::new(42).generate_chunk(chunk));
+        }
+        assert!(generator.generate_chunk(IVec3::ZERO) != TerrainGenerator::new(43).generate_chunk(IVec3::ZERO));
+    }
+
+    // pins the output of the generator, a change here changes every world generated from a seed
+    #[test]
+    fn generated_chunks_are_pinned() {
+        let generator = TerrainGenerator::new(42);
+        // surface with grass and dirt, then the caves below it
+        assert_eq!(chunk_hash(&generator.generate_chunk(ivec3(0, 0, 0))), 9851288513519625143);
+        assert_eq!(chunk_hash(&generator.generate_chunk(ivec3(0, -1, 0))), 12896695743142786925);
+        assert_eq!(chunk_hash(&generator.generate_chunk(ivec3(2, -2, 7))), 14983065763418989497);
+    }
+
+    #[test]
+    fn oceans_are_filled_with_water_up_to_sea_level() {
+        let generator = TerrainGenerator::new(42);
+        let sea_level = generator.settings().sea_level;
+        let (x, z) = (-2000..2000)
+            .step_by(7)
+            .flat_map(|x| (-2000..2000).step_by(7).map(move |z| (x, z)))
+            .find(|&(x, z)| generator.biome_at(x, z) == Biome::Ocean)
+            .expect("seed 42 has no ocean");
+        let height = generator.height_at(x, z);
+        assert_eq!(generator.material_at(ivec3(x, height, z)), GRAVEL);
+        for y in height + 1..=sea_level {
+            assert_eq!(generator.material_at(ivec3(x, y, z)), WATER);
+        }
+        assert_eq!(generator.material_at(ivec3(x, sea_level + 1, z)), AIR);
+
+        let (chunk, local) = split_world_position(ivec3(x, sea_level, z));
+        assert_eq!(generator.generate_chunk(chunk).get(local), WATER);
+    }
+}
@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use glam::{uvec3, UVec3};
+
+use super::voxelized::MeshGridBitfield;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleFilter {
+    Nearest,
+    // a target voxel is solid when at least half of the source voxels it covers are solid
+    Majority,
+}
+
+impl MeshGridBitfield {
+    // rotates counter clockwise by `quarter_turns` * 90 degrees when looking down the positive axis
+    pub fn rotate_90(&self, axis: Axis, quarter_turns: u32) -> MeshGridBitfield {
+        let mut result = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            result = result.rotate_90_once(axis);
+        }
+        result
+    }
+
+    fn rotate_90_once(&self, axis: Axis) -> MeshGridBitfield {
+        let d = self.dimensions();
+        let (dimensions, map): (UVec3, Box<dyn Fn(UVec3) -> UVec3>) = match axis {
+            Axis::X => (uvec3(d.x, d.z, d.y), Box::new(move |p: UVec3| uvec3(p.x, d.z - 1 - p.z, p.y))),
+            Axis::Y => (uvec3(d.z, d.y, d.x), Box::new(move |p: UVec3| uvec3(p.z, p.y, d.x - 1 - p.x))),
+            Axis::Z => (uvec3(d.y, d.x, d.z), Box::new(move |p: UVec3| uvec3(d.y - 1 - p.y, p.x, p.z))),
+        };
+        let mut result = self.new_within(self.name(), dimensions);
+        for position in self.solid_positions() {
+            result.set_bit(map(position), true);
+        }
+        result
+    }
+
+    pub fn mirror(&self, axis: Axis) -> MeshGridBitfield {
+        let d = self.dimensions();
+        let mut result = self.new_within(self.name(), d);
+        for position in self.solid_positions() {
+            let mirrored = match axis {
+                Axis::X => uvec3(d.x - 1 - position.x, position.y, position.z),
+                Axis::Y => uvec3(position.x, d.y - 1 - position.y, position.z),
+                Axis::Z => uvec3(position.x, position.y, d.z - 1 - position.z),
+            };
+            result.set_bit(mirrored, true);
+        }
+        result
+    }
+
+    // smallest box containing every solid voxel as (min, max) with an exclusive max
+    pub fn solid_bounds(&self) -> Option<(UVec3, UVec3)> {
+        let mut bounds: Option<(UVec3, UVec3)> = None;
+        for position in self.solid_positions() {
+            bounds = Some(match bounds {
+                Some((min, max)) => (min.min(position), max.max(position + 1)),
+                None => (position, position + 1),
+            });
+        }
+        bounds
+    }
+
+    // returns the cropped grid and the position of its voxel (0, 0, 0) in the original grid
+    pub fn crop_to_solid(&self) -> (MeshGridBitfield, UVec3) {
+        match self.solid_bounds() {
+            Some((min, max)) => (self.crop(min, max), min),
+            None => (self.new_within(self.name(), UVec3::ZERO), UVec3::ZERO),
+        }
+    }
+
+    pub fn crop(&self, min: UVec3, max: UVec3) -> MeshGridBitfield {
+        assert!(min.cmple(max).all() && max.cmple(self.dimensions()).all());
+        let mut result = self.new_within(self.name(), max - min);
+        copy_region(self, min, &mut result, UVec3::ZERO, max - min);
+        result
+    }
+
+    pub fn pad(&self, before: UVec3, after: UVec3) -> Result<MeshGridBitfield> {
+        let add = |a: UVec3, b: UVec3| Some(uvec3(a.x.checked_add(b.x)?, a.y.checked_add(b.y)?, a.z.checked_add(b.z)?));
+        let dimensions = add(before, self.dimensions())
+            .and_then(|padded| add(padded, after))
+            .with_context(|| format!("padding {} by {} and {} overflows", self.dimensions(), before, after))?;
+        let mut result = MeshGridBitfield::new(self.name(), dimensions)?;
+        copy_region(self, UVec3::ZERO, &mut result, before, self.dimensions());
+        Ok(result)
+    }
+
+    pub fn resample(&self, dimensions: UVec3, filter: ResampleFilter) -> Result<MeshGridBitfield> {
+        let source = self.dimensions();
+        let mut result = MeshGridBitfield::new(self.name(), dimensions)?;
+        if source.cmpeq(UVec3::ZERO).any() {
+            return Ok(result);
+        }
+        for z in 0..dimensions.z {
+            for y in 0..dimensions.y {
+                for x in 0..dimensions.x {
+                    let target = uvec3(x, y, z);
+                    let solid = match filter {
+                        ResampleFilter::Nearest => {
+                            let center = (target.as_vec3() + 0.5) * source.as_vec3() / dimensions.as_vec3();
+                            self.get_bit(center.as_uvec3().min(source - 1))
+                        }
+                        ResampleFilter::Majority => {
+                            // source voxels overlapping the target voxel, always at least one. The products
+                            // are taken in u64 so large grids do not overflow, the quotients fit into u32 again
+                            let (target, source, dimensions) = (target.as_u64vec3(), source.as_u64vec3(), dimensions.as_u64vec3());
+                            let min = (target * source / dimensions).as_uvec3();
+                            let max = (((target + 1) * source + dimensions - 1) / dimensions).as_uvec3().max(min + 1);
+                            let mut solid = 0;
+                            let mut total = 0;
+                            for sz in min.z..max.z {
+                                for sy in min.y..max.y {
+                                    for sx in min.x..max.x {
+                                        solid += self.get_bit(uvec3(sx, sy, sz)) as u32;
+                                        total += 1;
+                                    }
+                                }
+                            }
+                            solid * 2 >= total
+                        }
+                    };
+                    if solid {
+                        result.set_bit(target, true);
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+// copies a box of `size` voxels row by row, 32 bits at a time
+pub fn copy_region(source: &MeshGridBitfield, source_min: UVec3, target: &mut MeshGridBitfield, target_min: UVec3, size: UVec3) {
+    for z in 0..size.z {
+        for y in 0..size.y {
+            let source_row = source.linear_index(source_min + uvec3(0, y, z));
+            let target_row = target.linear_index(target_min + uvec3(0, y, z));
+            let mut x = 0;
+            while x < size.x {
+                let count = (size.x - x).min(32);
+                let bits = source.read_bits(source_row + x, count);
+                target.write_bits(target_row + x, count, bits);
+                x += count;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::noise::hash_3d;
+
+    fn random_grid(seed: u32, dimensions: UVec3) -> MeshGridBitfield {
+        let mut grid = MeshGridBitfield::new("random", dimensions).unwrap();
+        for z in 0..dimensions.z {
+            for y in 0..dimensions.y {
+                for x in 0..dimensions.x {
+                    grid.set_bit(uvec3(x, y, z), hash_3d(seed, x as i32, y as i32, z as i32).is_multiple_of(3));
+                }
+            }
+        }
+        grid
+    }
+
+    fn grid_with(dimensions: UVec3, positions: &[(u32, u32, u32)]) -> MeshGridBitfield {
+        let mut grid = MeshGridBitfield::new("test", dimensions).unwrap();
+        for &(x, y, z) in positions {
+            grid.set_bit(uvec3(x, y, z), true);
+        }
+        grid
+    }
+
+    #[test]
+    fn four_quarter_turns_and_two_mirrors_are_the_identity() {
+        for (seed, axis) in [Axis::X, Axis::Y, Axis::Z].into_iter().enumerate() {
+            let grid = random_grid(seed as u32, uvec3(5, 3, 7));
+            assert!(grid.rotate_90(axis, 4) == grid);
+            assert!(grid.rotate_90(axis, 1).rotate_90(axis, 3) == grid);
+            assert!(grid.rotate_90(axis, 5) == grid.rotate_90(axis, 1));
+            assert!(grid.mirror(axis).mirror(axis) == grid);
+        }
+    }
+
+    #[test]
+    fn single_turns_and_mirrors_move_voxels() {
+        let grid = grid_with(uvec3(3, 2, 1), &[(1, 0, 0)]);
+        let turned = grid.rotate_90(Axis::Z, 1);
+        assert_eq!(turned.dimensions(), uvec3(2, 3, 1));
+        assert_eq!(turned.solid_positions().collect::<Vec<_>>(), vec![uvec3(1, 1, 0)]);
+        let mirrored = grid.mirror(Axis::X);
+        assert_eq!(mirrored.solid_positions().collect::<Vec<_>>(), vec![uvec3(1, 0, 0)]);
+        let mirrored = grid.mirror(Axis::Y);
+        assert_eq!(mirrored.solid_positions().collect::<Vec<_>>(), vec![uvec3(1, 1, 0)]);
+    }
+
+    #[test]
+    fn crop_to_solid_returns_the_offset() {
+        let grid = grid_with(UVec3::splat(8), &[(2, 3, 1), (4, 3, 5)]);
+        let (cropped, offset) = grid.crop_to_solid();
+        assert_eq!(offset, uvec3(2, 3, 1));
+        assert_eq!(cropped.dimensions(), uvec3(3, 1, 5));
+        assert_eq!(cropped.solid_positions().collect::<Vec<_>>(), vec![uvec3(0, 0, 0), uvec3(2, 0, 4)]);
+
+        let (empty, offset) = MeshGridBitfield::new("empty", UVec3::splat(4)).unwrap().crop_to_solid();
+        assert_eq!((empty.dimensions(), offset), (UVec3::ZERO, UVec3::ZERO));
+    }
+
+    #[test]
+    fn cropping_undoes_padding() {
+        let grid = random_grid(7, uvec3(33, 4, 5));
+        let (before, after) = (uvec3(3, 0, 31), uvec3(40, 2, 1));
+        let padded = grid.pad(before, after).unwrap();
+        assert_eq!(padded.dimensions(), before + grid.dimensions() + after);
+        assert_eq!(padded.solid_positions().count(), grid.solid_positions().count());
+        assert!(padded.crop(before, before + grid.dimensions()) == grid);
+        assert!(grid.pad(UVec3::X * u32::MAX, UVec3::ZERO).is_err());
+    }
+
+    #[test]
+    fn majority_resampling_keeps_mostly_solid_blocks() {
+        // the first 2^3 block is full, the second one has a single voxel, the third one half
+        let mut grid = grid_with(uvec3(6, 2, 2), &[(2, 1, 1)]);
+        for position in (0..8).map(|i| uvec3(i & 1, (i >> 1) & 1, i >> 2)) {
+            grid.set_bit(position, true);
+            if position.x == 0 {
+                grid.set_bit(position + uvec3(4, 0, 0), true);
+            }
+        }
+        let resampled = grid.resample(uvec3(3, 1, 1), ResampleFilter::Majority).unwrap();
+        assert_eq!(resampled.solid_positions().collect::<Vec<_>>(), vec![uvec3(0, 0, 0), uvec3(2, 0, 0)]);
+
+        let row = grid_with(uvec3(6, 1, 1), &[(0, 0, 0), (1, 0, 0), (3, 0, 0)]);
+        let resampled = row.resample(uvec3(2, 1, 1), ResampleFilter::Majority).unwrap();
+        assert_eq!(resampled.solid_positions().collect::<Vec<_>>(), vec![uvec3(0, 0, 0)]);
+    }
+
+    #[test]
+    fn nearest_resampling_scales_voxels() {
+        let grid = random_grid(3, uvec3(4, 3, 5));
+        let doubled = grid.resample(grid.dimensions() * 2, ResampleFilter::Nearest).unwrap();
+        for position in doubled.solid_positions() {
+            assert!(grid.get_bit(position / 2));
+        }
+        assert_eq!(doubled.solid_positions().count(), grid.solid_positions().count() * 8);
+        assert!(doubled.resample(grid.dimensions(), ResampleFilter::Majority).unwrap() == grid);
+        assert!(doubled.resample(grid.dimensions(), ResampleFilter::Nearest).unwrap() == grid);
+    }
+}
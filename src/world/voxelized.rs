@@ -1,31 +1,232 @@
-use bytemuck::Pod;
-use glam::UVec3;
+use anyhow::{ensure, Context, Result};
+use glam::{IVec3, UVec3};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+use crate::io::Versioned;
+
+use super::material::{self, Material, AIR, STONE};
+use super::VoxelStorage;
+
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "MeshGridBitfieldData")]
 pub struct MeshGridBitfield {
     grid_name: String,
     dimensions: UVec3,
     data: Vec<u32>,
 }
 
+// what a saved grid contains, checked by `from_data` so the word count always matches the dimensions
+#[derive(Deserialize)]
+struct MeshGridBitfieldData {
+    grid_name: String,
+    dimensions: UVec3,
+    data: Vec<u32>,
+}
+
+impl TryFrom<MeshGridBitfieldData> for MeshGridBitfield {
+    type Error = anyhow::Error;
+
+    fn try_from(data: MeshGridBitfieldData) -> Result<Self> {
+        Self::from_data(&data.grid_name, data.dimensions, data.data)
+    }
+}
+
 impl MeshGridBitfield {
-    pub fn new(name: &str, dimensions: UVec3) -> Self {
-        Self {
+    pub fn new(name: &str, dimensions: UVec3) -> Result<Self> {
+        Ok(Self {
             grid_name: name.to_owned(),
-            dimensions: dimensions,
-            data: vec![0u32; ((dimensions.x / 32) * dimensions.y * dimensions.z) as usize],
-        }
+            dimensions,
+            data: vec![0u32; word_count(dimensions)?],
+        })
+    }
+    // an empty grid with at most as many voxels as this one, so its size is known to be valid
+    pub fn new_within(&self, name: &str, dimensions: UVec3) -> Self {
+        let volume = |d: UVec3| d.x as u64 * d.y as u64 * d.z as u64;
+        assert!(
+            volume(dimensions) <= volume(self.dimensions),
+            "{} does not fit into {}",
+            dimensions,
+            self.dimensions
+        );
+        Self::new(name, dimensions).unwrap()
+    }
+    pub fn from_data(name: &str, dimensions: UVec3, data: Vec<u32>) -> Result<Self> {
+        let expected = word_count(dimensions)?;
+        ensure!(
+            data.len() == expected,
+            "a grid of {} needs {} words but {} were given",
+            dimensions,
+            expected,
+            data.len()
+        );
+        Ok(Self {
+            grid_name: name.to_owned(),
+            dimensions,
+            data,
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.grid_name
+    }
+    pub fn dimensions(&self) -> UVec3 {
+        self.dimensions
+    }
+    pub fn get_bit(&self, position: UVec3) -> bool {
+        let (uint_index, bit) = self.bit_location(position);
+        self.data[uint_index] & bit != 0
+    }
+    // positions outside of the grid are empty
+    pub fn is_solid(&self, position: IVec3) -> bool {
+        position.cmpge(IVec3::ZERO).all() && position.cmplt(self.dimensions.as_ivec3()).all() && self.get_bit(position.as_uvec3())
     }
     pub fn set_bit(&mut self, position: UVec3, value: bool) {
+        let (uint_index, bit) = self.bit_location(position);
+        match value {
+            true => self.data[uint_index] |= bit,
+            false => self.data[uint_index] &= !bit,
+        }
+    }
+    pub fn solid_positions(&self) -> impl Iterator<Item = UVec3> + '_ {
+        let d = self.dimensions;
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, &word)| word != 0)
+            .flat_map(move |(word_index, &word)| {
+                (0..32).filter(move |bit| word & (1 << bit) != 0).map(move |bit| {
+                    let index = word_index as u32 * 32 + bit;
+                    UVec3::new(index % d.x, (index / d.x) % d.y, index / (d.x * d.y))
+                })
+            })
+    }
+    pub fn data(&self) -> &[u32] {
+        &self.data
+    }
+    pub fn fill(&mut self, value: bool) {
+        match value {
+            true => {
+                self.data.fill(u32::MAX);
+                // keep the unused bits of the last word empty
+                let bits = self.dimensions.x * self.dimensions.y * self.dimensions.z;
+                let words = self.data.len() as u32;
+                if let Some(last) = self.data.last_mut() {
+                    *last &= low_bits(bits - (words - 1) * 32);
+                }
+            }
+            false => self.data.fill(0),
+        }
+    }
+
+    // reads `count` (at most 32) consecutive bits starting at linear bit index `bit_index`
+    pub fn read_bits(&self, bit_index: u32, count: u32) -> u32 {
+        debug_assert!(count <= 32);
+        if count == 0 {
+            return 0;
+        }
+        let word = (bit_index / 32) as usize;
+        let shift = bit_index % 32;
+        let mut bits = self.data[word] >> shift;
+        if shift + count > 32 {
+            bits |= self.data[word + 1] << (32 - shift);
+        }
+        bits & low_bits(count)
+    }
+    // writes the lowest `count` (at most 32) bits of `bits` starting at linear bit index `bit_index`
+    pub fn write_bits(&mut self, bit_index: u32, count: u32, bits: u32) {
+        debug_assert!(count <= 32);
+        if count == 0 {
+            return;
+        }
+        let word = (bit_index / 32) as usize;
+        let shift = bit_index % 32;
+        let bits = bits & low_bits(count);
+        let mask = low_bits(count) << shift;
+        self.data[word] = (self.data[word] & !mask) | (bits << shift);
+        if shift + count > 32 {
+            let written = 32 - shift;
+            let mask = low_bits(count - written);
+            self.data[word + 1] = (self.data[word + 1] & !mask) | (bits >> written);
+        }
+    }
+    pub fn linear_index(&self, position: UVec3) -> u32 {
+        position.x + position.y * self.dimensions.x + position.z * self.dimensions.x * self.dimensions.y
+    }
+
+    fn bit_location(&self, position: UVec3) -> (usize, u32) {
         assert!(position.x < self.dimensions.x);
         assert!(position.y < self.dimensions.y);
         assert!(position.z < self.dimensions.z);
-        let uint_index = position.x + position.y * self.dimensions.x + position.z * self.dimensions.x * self.dimensions.y / 32;
-        let bit = 1u32 << ((position.x + position.y * self.dimensions.x + position.z * self.dimensions.x * self.dimensions.y) % 32);
-        match value {
-            true => self.data[uint_index as usize] |= bit,
-            false => self.data[uint_index as usize] &= !bit,
+        let bit_index = self.linear_index(position);
+        ((bit_index / 32) as usize, 1u32 << (bit_index % 32))
+    }
+}
+
+// words needed to store a grid, bit indices are u32 so grids with more voxels are rejected
+pub fn word_count(dimensions: UVec3) -> Result<usize> {
+    let bits = (dimensions.x as u64)
+        .checked_mul(dimensions.y as u64)
+        .and_then(|bits| bits.checked_mul(dimensions.z as u64))
+        .filter(|&bits| bits <= u32::MAX as u64)
+        .with_context(|| format!("a grid of {} has more than {} voxels", dimensions, u32::MAX))?;
+    Ok(bits.div_ceil(32) as usize)
+}
+
+pub fn low_bits(count: u32) -> u32 {
+    if count >= 32 {
+        u32::MAX
+    } else {
+        (1 << count) - 1
+    }
+}
+
+impl Versioned for MeshGridBitfield {
+    const TYPE_TAG: &'static str = "mesh_grid_bitfield";
+    const VERSION: u32 = 1;
+}
+
+impl VoxelStorage for MeshGridBitfield {
+    fn get_material(&self, position: IVec3) -> Material {
+        if self.is_solid(position) {
+            STONE
+        } else {
+            AIR
         }
     }
+    fn set_material(&mut self, position: IVec3, material: Material) {
+        if position.cmpge(IVec3::ZERO).all() && position.cmplt(self.dimensions.as_ivec3()).all() {
+            self.set_bit(position.as_uvec3(), material::is_solid(material));
+        }
+    }
+    fn stores_materials(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grids_with_more_voxels_than_bit_indices_are_rejected() {
+        assert!(MeshGridBitfield::new("large", UVec3::new(65536, 65536, 1)).is_err());
+        assert!(MeshGridBitfield::new("huge", UVec3::splat(u32::MAX)).is_err());
+        assert!(MeshGridBitfield::from_data("huge", UVec3::splat(u32::MAX), Vec::new()).is_err());
+        assert!(MeshGridBitfield::from_data("short", UVec3::splat(4), vec![0; 1]).is_err());
+        assert!(MeshGridBitfield::from_data("exact", UVec3::splat(4), vec![0; 2]).is_ok());
+    }
+
+    #[test]
+    fn loaded_grids_are_checked() {
+        let mut grid = MeshGridBitfield::new("saved", UVec3::new(5, 7, 3)).unwrap();
+        grid.set_bit(UVec3::new(4, 6, 2), true);
+        let load = |grid: &MeshGridBitfield| serde_cbor::from_slice::<MeshGridBitfield>(&serde_cbor::to_vec(grid).unwrap());
+        let loaded = load(&grid).unwrap();
+        assert!(loaded == grid);
+        assert_eq!(loaded.name(), "saved");
+
+        grid.data.pop();
+        assert!(load(&grid).err().unwrap().to_string().contains("needs 4 words but 3 were given"));
+        grid.dimensions = UVec3::splat(u32::MAX);
+        assert!(load(&grid).is_err());
+    }
 }
@@ -0,0 +1,142 @@
+use anyhow::{bail, Result};
+use cogrrs::egui::{self, Button, DragValue, Ui};
+use glam::{IVec3, UVec3};
+use log::error;
+
+use crate::constants::TERRAIN_ARCHIVE;
+use crate::io::ChunkArchive;
+use crate::jobs::{Jobs, Progress};
+use crate::world::brickmap::Brickmap;
+use crate::world::chunk::CHUNK_SIZE;
+use crate::world::chunked::ChunkedWorld;
+use crate::world::structures::StructurePlacer;
+use crate::world::terrain::TerrainGenerator;
+
+const TERRAIN_JOB: &str = "terrain";
+const LOAD_JOB: &str = "load terrain";
+// height in the world the sea level of generated terrain ends up at, a multiple of the chunk size
+const SEA_LEVEL_IN_WORLD: i32 = CHUNK_SIZE as i32;
+
+// Operations on the whole world that are started from the ui. Terrain is generated or loaded in the background
+// and replaces everything in the world once it is done
+pub struct WorldTools {
+    jobs: Jobs<ChunkedWorld>,
+    seed: u32,
+    // the terrain that was generated or loaded last, edits to the world are not part of it
+    terrain: Option<ChunkedWorld>,
+    error: Option<String>,
+}
+
+impl Default for WorldTools {
+    fn default() -> Self {
+        Self {
+            jobs: Jobs::default(),
+            seed: 1,
+            terrain: None,
+            error: None,
+        }
+    }
+}
+
+impl WorldTools {
+    // a generation that is still running is cancelled, only the latest seed ends up in the world
+    pub fn generate_terrain(&mut self, world_dimensions: UVec3) {
+        self.jobs.cancel(TERRAIN_JOB);
+        let seed = self.seed;
+        self.jobs
+            .spawn(TERRAIN_JOB, move |progress| generate_terrain(seed, world_dimensions, progress));
+    }
+
+    pub fn load_terrain(&mut self, world_dimensions: UVec3) {
+        self.jobs.cancel(LOAD_JOB);
+        self.jobs.spawn(LOAD_JOB, move |_| {
+            let mut archive = ChunkArchive::open(TERRAIN_ARCHIVE)?;
+            let (min, max) = terrain_chunks(world_dimensions);
+            let mut terrain = ChunkedWorld::new();
+            terrain.load_region(&mut archive, min, max)?;
+            Ok(terrain)
+        });
+    }
+
+    // the archive is replaced, so it only contains the chunks of this terrain
+    pub fn save_terrain(&mut self) -> Result<()> {
+        let Some(terrain) = &self.terrain else {
+            bail!("there is no terrain to save, generate it first");
+        };
+        let mut archive = ChunkArchive::create(TERRAIN_ARCHIVE)?;
+        terrain.save_to_archive(&mut archive)
+    }
+
+    // returns whether the world was replaced
+    pub fn update(&mut self, world: &mut Brickmap) -> bool {
+        let mut replaced = false;
+        for (name, result) in self.jobs.poll() {
+            match result {
+                Ok(terrain) => {
+                    let mut generated = Brickmap::new(world.dimensions());
+                    terrain.copy_into(&mut generated, IVec3::Y * SEA_LEVEL_IN_WORLD);
+                    // the GPU copy still holds the old world everywhere
+                    generated.mark_all_dirty();
+                    *world = generated;
+                    replaced = true;
+                    self.terrain = Some(terrain);
+                    self.error = None;
+                }
+                Err(error) => {
+                    error!("{} failed: {:#}", name, error);
+                    self.error = Some(format!("{} failed: {:#}", name, error));
+                }
+            }
+        }
+        replaced
+    }
+
+    pub fn draw_ui(&mut self, ui: &mut Ui, world: &mut Brickmap) {
+        ui.horizontal(|ui| {
+            ui.add(DragValue::new(&mut self.seed).prefix("seed: "));
+            if ui.button("Generate terrain").clicked() {
+                self.generate_terrain(world.voxel_dimensions());
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.terrain.is_some(), Button::new("Save terrain")).clicked() {
+                self.error = self.save_terrain().err().map(|error| format!("could not save terrain: {:#}", error));
+            }
+            if ui.button("Load terrain").clicked() {
+                self.load_terrain(world.voxel_dimensions());
+            }
+        });
+        self.jobs.draw_ui(ui);
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+}
+
+// chunks [min, max) of the terrain that cover a world of `world_dimensions` voxels, in terrain coordinates
+fn terrain_chunks(world_dimensions: UVec3) -> (IVec3, IVec3) {
+    let chunks = (world_dimensions + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let first = -IVec3::Y * (SEA_LEVEL_IN_WORLD / CHUNK_SIZE as i32);
+    (first, first + chunks.as_ivec3())
+}
+
+// terrain with structures, empty chunks are left out
+fn generate_terrain(seed: u32, world_dimensions: UVec3, progress: &Progress) -> Result<ChunkedWorld> {
+    let generator = TerrainGenerator::new(seed);
+    let placer = StructurePlacer::with_default_structures(seed)?;
+    let (first, last) = terrain_chunks(world_dimensions);
+    let chunks = (last - first).as_uvec3();
+    let total = chunks.x * chunks.y * chunks.z;
+    let mut terrain = ChunkedWorld::new();
+    for index in 0..total {
+        progress.check_cancelled()?;
+        progress.set(index as f32 / total as f32);
+        let offset = UVec3::new(index % chunks.x, index / chunks.x % chunks.y, index / (chunks.x * chunks.y));
+        let mut chunk = generator.generate_chunk(first + offset.as_ivec3());
+        placer.decorate_chunk(&generator, &mut chunk);
+        if !chunk.is_empty() {
+            terrain.insert_chunk(chunk);
+        }
+    }
+    Ok(terrain)
+}